@@ -0,0 +1,442 @@
+//! Computing the `max_stack` and `max_locals` a method's code needs.
+//!
+//! The operand stack height is tracked along every control flow path, including the paths into
+//! exception handlers, which start with the thrown exception as the only value on the stack. Longs
+//! and doubles take up two entries on the stack and two local variable slots.
+
+use crate::descriptor::{parse_field_descriptor, parse_method_descriptor};
+use crate::instruction::{Instruction, Instructions};
+use crate::types::{Attribute, ClassFile, Constant, ConstantPool, ExceptionHandler, Method, MethodFlag, ParsingError, ParsingErrorKind};
+
+/// The operand stack and local variable sizes of a `Code` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeLimits {
+    pub max_stack: u16,
+    pub max_locals: u16,
+}
+
+/// A method whose `Code` attribute declares other limits than its code needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitsMismatch {
+    /// The index of the method in [`ClassFile::methods`]
+    pub method_index: usize,
+    pub name: String,
+    pub descriptor: String,
+    pub declared: CodeLimits,
+    pub computed: CodeLimits,
+}
+
+impl LimitsMismatch {
+    /// Whether the declared limits are too small for the code, which the JVM rejects. Larger limits
+    /// than needed are only wasteful.
+    pub fn is_too_small(&self) -> bool {
+        self.declared.max_stack < self.computed.max_stack || self.declared.max_locals < self.computed.max_locals
+    }
+}
+
+/// Computes the limits of decoded code. `parameter_slots` is the number of local variable slots the
+/// parameters take up, including `this` for instance methods. Errors are reported at the pc of the
+/// offending instruction.
+pub fn compute_code_limits(instructions: &[(u16, Instruction)], exception_table: &[ExceptionHandler], parameter_slots: u16) -> Result<CodeLimits, ParsingError> {
+    let max_locals = instructions.iter()
+        .filter_map(|(_, instruction)| local_variable_end(instruction))
+        .fold(parameter_slots, u16::max);
+    if instructions.is_empty() {
+        return Ok(CodeLimits { max_stack: 0, max_locals });
+    }
+
+    let index_of = |pc: usize| instructions.binary_search_by_key(&pc, |(pc, _)| *pc as usize)
+        .map_err(|_| ParsingError::new(pc, ParsingErrorKind::InvalidControlFlow { target: pc }));
+    let handlers: Vec<(usize, usize, usize)> = exception_table.iter()
+        .map(|handler| Ok((handler.start_pc as usize, handler.end_pc as usize, index_of(handler.handler_pc as usize)?)))
+        .collect::<Result<_, ParsingError>>()?;
+
+    // The stack height before each instruction, once a path to it has been found
+    let mut heights: Vec<Option<u32>> = vec![None; instructions.len()];
+    heights[0] = Some(0);
+    let mut pending: Vec<usize> = vec![0];
+    let mut max_stack: u32 = 0;
+    while let Some(index) = pending.pop() {
+        let (pc, instruction) = &instructions[index];
+        let pc = *pc as usize;
+        let height = heights[index].expect("pending instructions have a height");
+        let (pops, pushes) = stack_effect(instruction).map_err(|e| e.with_context(format!("code[{}]", pc)))?;
+        if pops > height {
+            return Err(ParsingError::new(pc, ParsingErrorKind::StackUnderflow));
+        }
+        let after = height - pops + pushes;
+        if after > u16::MAX as u32 {
+            return Err(ParsingError::new(pc, ParsingErrorKind::StackOverflow));
+        }
+        max_stack = max_stack.max(after);
+
+        // The instructions reached from this one and their stack heights
+        let mut successors: Vec<(usize, u32)> = Vec::new();
+        for (start_pc, end_pc, handler) in &handlers {
+            if (*start_pc..*end_pc).contains(&pc) {
+                max_stack = max_stack.max(1);
+                successors.push((*handler, 1));
+            }
+        }
+        let next = || match instructions.get(index + 1) {
+            Some(_) => Ok(index + 1),
+            None => Err(ParsingError::new(pc, ParsingErrorKind::FallsOffEnd)),
+        };
+        match instruction {
+            Instruction::Goto(target) | Instruction::GotoW(target) => successors.push((index_of(*target as usize)?, after)),
+            // The subroutine returns to the next instruction, with the stack it started with
+            Instruction::Jsr(target) | Instruction::JsrW(target) => {
+                successors.push((index_of(*target as usize)?, after));
+                successors.push((next()?, height));
+            }
+            Instruction::Ifeq(target) | Instruction::Ifne(target) | Instruction::Iflt(target) |
+            Instruction::Ifge(target) | Instruction::Ifgt(target) | Instruction::Ifle(target) |
+            Instruction::IfIcmpeq(target) | Instruction::IfIcmpne(target) | Instruction::IfIcmplt(target) |
+            Instruction::IfIcmpge(target) | Instruction::IfIcmpgt(target) | Instruction::IfIcmple(target) |
+            Instruction::IfAcmpeq(target) | Instruction::IfAcmpne(target) | Instruction::Ifnull(target) |
+            Instruction::Ifnonnull(target) => {
+                successors.push((index_of(*target as usize)?, after));
+                successors.push((next()?, after));
+            }
+            Instruction::Tableswitch { default, targets, .. } => {
+                for target in std::iter::once(default).chain(targets) {
+                    successors.push((index_of(*target as usize)?, after));
+                }
+            }
+            Instruction::Lookupswitch { default, pairs } => {
+                for target in std::iter::once(default).chain(pairs.iter().map(|(_, target)| target)) {
+                    successors.push((index_of(*target as usize)?, after));
+                }
+            }
+            Instruction::Ireturn | Instruction::Lreturn | Instruction::Freturn | Instruction::Dreturn |
+            Instruction::Areturn | Instruction::Return | Instruction::Athrow | Instruction::Ret(_) => {}
+            _ => successors.push((next()?, after)),
+        }
+
+        for (successor, successor_height) in successors {
+            match heights[successor] {
+                None => {
+                    heights[successor] = Some(successor_height);
+                    pending.push(successor);
+                }
+                Some(expected) if expected != successor_height => {
+                    return Err(ParsingError::new(instructions[successor].0 as usize, ParsingErrorKind::InconsistentStackHeight {
+                        expected: expected as u16,
+                        actual: successor_height as u16,
+                    }));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    Ok(CodeLimits { max_stack: max_stack as u16, max_locals })
+}
+
+/// Decodes the code of `method` and computes its limits. `None` if the method has no `Code` attribute.
+pub fn method_code_limits(method: &Method, constant_pool: &ConstantPool) -> Result<Option<CodeLimits>, ParsingError> {
+    let (code, exception_table) = match method.attributes.iter().find_map(|attribute| match attribute {
+        Attribute::Code { code, exception_table, .. } => Some((code, exception_table)),
+        _ => None,
+    }) {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+    let descriptor = method.parsed_descriptor()?;
+    let this_slots = if method.access_flags.contains(&MethodFlag::AccStatic) { 0 } else { 1 };
+    let instructions: Vec<(u16, Instruction)> = Instructions::new(code, constant_pool).collect::<Result<_, _>>()?;
+    compute_code_limits(&instructions, exception_table, descriptor.parameter_slots() + this_slots).map(Some)
+}
+
+/// Finds the methods whose declared limits differ from the ones their code needs, in both directions:
+/// [`LimitsMismatch::is_too_small`] tells the ones the JVM rejects apart from the merely wasteful
+/// ones. A method whose code can't be analyzed is reported as an error in the place of a mismatch,
+/// with `methods[i]` as the outermost context, and the other methods are still checked.
+pub fn check_code_limits(class_file: &ClassFile) -> Vec<Result<LimitsMismatch, ParsingError>> {
+    let mut mismatches: Vec<Result<LimitsMismatch, ParsingError>> = Vec::new();
+    for (i, method) in class_file.methods.iter().enumerate() {
+        let computed = match method_code_limits(method, &class_file.constant_pool) {
+            Ok(computed) => computed,
+            Err(e) => {
+                mismatches.push(Err(e.with_context(format!("methods[{}]", i))));
+                continue;
+            }
+        };
+        let declared = method.attributes.iter().find_map(|attribute| match attribute {
+            Attribute::Code { max_stack, max_locals, .. } => Some(CodeLimits { max_stack: *max_stack, max_locals: *max_locals }),
+            _ => None,
+        });
+        if let (Some(declared), Some(computed)) = (declared, computed) {
+            if declared != computed {
+                mismatches.push(Ok(LimitsMismatch {
+                    method_index: i,
+                    name: method.name.clone(),
+                    descriptor: method.descriptor.clone(),
+                    declared,
+                    computed,
+                }));
+            }
+        }
+    }
+    mismatches
+}
+
+/// Replaces the declared limits of every `Code` attribute with the computed ones, e.g. after
+/// generating or patching code
+pub fn update_code_limits(class_file: &mut ClassFile) -> Result<(), ParsingError> {
+    for (i, method) in class_file.methods.iter_mut().enumerate() {
+        let computed = match method_code_limits(method, &class_file.constant_pool).map_err(|e| e.with_context(format!("methods[{}]", i)))? {
+            Some(computed) => computed,
+            None => continue,
+        };
+        for attribute in &mut method.attributes {
+            if let Attribute::Code { max_stack, max_locals, .. } = attribute {
+                *max_stack = computed.max_stack;
+                *max_locals = computed.max_locals;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The first local variable slot after the ones `instruction` accesses
+fn local_variable_end(instruction: &Instruction) -> Option<u16> {
+    Some(match instruction {
+        Instruction::Iload(index) | Instruction::Fload(index) | Instruction::Aload(index) |
+        Instruction::Istore(index) | Instruction::Fstore(index) | Instruction::Astore(index) |
+        Instruction::Ret(index) | Instruction::Iinc { index, .. } => index.saturating_add(1),
+        Instruction::Lload(index) | Instruction::Dload(index) | Instruction::Lstore(index) | Instruction::Dstore(index) => index.saturating_add(2),
+        Instruction::Iload0 | Instruction::Fload0 | Instruction::Aload0 | Instruction::Istore0 | Instruction::Fstore0 | Instruction::Astore0 => 1,
+        Instruction::Iload1 | Instruction::Fload1 | Instruction::Aload1 | Instruction::Istore1 | Instruction::Fstore1 | Instruction::Astore1 |
+        Instruction::Lload0 | Instruction::Dload0 | Instruction::Lstore0 | Instruction::Dstore0 => 2,
+        Instruction::Iload2 | Instruction::Fload2 | Instruction::Aload2 | Instruction::Istore2 | Instruction::Fstore2 | Instruction::Astore2 |
+        Instruction::Lload1 | Instruction::Dload1 | Instruction::Lstore1 | Instruction::Dstore1 => 3,
+        Instruction::Iload3 | Instruction::Fload3 | Instruction::Aload3 | Instruction::Istore3 | Instruction::Fstore3 | Instruction::Astore3 |
+        Instruction::Lload2 | Instruction::Dload2 | Instruction::Lstore2 | Instruction::Dstore2 => 4,
+        Instruction::Lload3 | Instruction::Dload3 | Instruction::Lstore3 | Instruction::Dstore3 => 5,
+        _ => return None,
+    })
+}
+
+/// The number of operand stack entries `instruction` pops and pushes
+fn stack_effect(instruction: &Instruction) -> Result<(u32, u32), ParsingError> {
+    Ok(match instruction {
+        Instruction::Nop | Instruction::Iinc { .. } | Instruction::Goto(_) | Instruction::GotoW(_) |
+        Instruction::Ret(_) | Instruction::Return => (0, 0),
+        Instruction::AconstNull | Instruction::IconstM1 | Instruction::Iconst0 | Instruction::Iconst1 |
+        Instruction::Iconst2 | Instruction::Iconst3 | Instruction::Iconst4 | Instruction::Iconst5 |
+        Instruction::Fconst0 | Instruction::Fconst1 | Instruction::Fconst2 | Instruction::Bipush(_) |
+        Instruction::Sipush(_) | Instruction::Iload(_) | Instruction::Fload(_) | Instruction::Aload(_) |
+        Instruction::Iload0 | Instruction::Iload1 | Instruction::Iload2 | Instruction::Iload3 |
+        Instruction::Fload0 | Instruction::Fload1 | Instruction::Fload2 | Instruction::Fload3 |
+        Instruction::Aload0 | Instruction::Aload1 | Instruction::Aload2 | Instruction::Aload3 |
+        Instruction::New(_) | Instruction::Jsr(_) | Instruction::JsrW(_) => (0, 1),
+        Instruction::Lconst0 | Instruction::Lconst1 | Instruction::Dconst0 | Instruction::Dconst1 |
+        Instruction::Lload(_) | Instruction::Dload(_) | Instruction::Lload0 | Instruction::Lload1 |
+        Instruction::Lload2 | Instruction::Lload3 | Instruction::Dload0 | Instruction::Dload1 |
+        Instruction::Dload2 | Instruction::Dload3 => (0, 2),
+        Instruction::Ldc(constant) | Instruction::LdcW(constant) | Instruction::Ldc2W(constant) => (0, constant_slots(constant)?),
+        Instruction::Iaload | Instruction::Faload | Instruction::Aaload | Instruction::Baload |
+        Instruction::Caload | Instruction::Saload => (2, 1),
+        Instruction::Laload | Instruction::Daload => (2, 2),
+        Instruction::Istore(_) | Instruction::Fstore(_) | Instruction::Astore(_) | Instruction::Istore0 |
+        Instruction::Istore1 | Instruction::Istore2 | Instruction::Istore3 | Instruction::Fstore0 |
+        Instruction::Fstore1 | Instruction::Fstore2 | Instruction::Fstore3 | Instruction::Astore0 |
+        Instruction::Astore1 | Instruction::Astore2 | Instruction::Astore3 | Instruction::Pop |
+        Instruction::Ifeq(_) | Instruction::Ifne(_) | Instruction::Iflt(_) | Instruction::Ifge(_) |
+        Instruction::Ifgt(_) | Instruction::Ifle(_) | Instruction::Ifnull(_) | Instruction::Ifnonnull(_) |
+        Instruction::Tableswitch { .. } | Instruction::Lookupswitch { .. } | Instruction::Ireturn |
+        Instruction::Freturn | Instruction::Areturn | Instruction::Athrow | Instruction::Monitorenter |
+        Instruction::Monitorexit => (1, 0),
+        Instruction::Lstore(_) | Instruction::Dstore(_) | Instruction::Lstore0 | Instruction::Lstore1 |
+        Instruction::Lstore2 | Instruction::Lstore3 | Instruction::Dstore0 | Instruction::Dstore1 |
+        Instruction::Dstore2 | Instruction::Dstore3 | Instruction::Pop2 | Instruction::IfIcmpeq(_) |
+        Instruction::IfIcmpne(_) | Instruction::IfIcmplt(_) | Instruction::IfIcmpge(_) |
+        Instruction::IfIcmpgt(_) | Instruction::IfIcmple(_) | Instruction::IfAcmpeq(_) |
+        Instruction::IfAcmpne(_) | Instruction::Lreturn | Instruction::Dreturn => (2, 0),
+        Instruction::Iastore | Instruction::Fastore | Instruction::Aastore | Instruction::Bastore |
+        Instruction::Castore | Instruction::Sastore => (3, 0),
+        Instruction::Lastore | Instruction::Dastore => (4, 0),
+        Instruction::Dup => (1, 2),
+        Instruction::DupX1 => (2, 3),
+        Instruction::DupX2 => (3, 4),
+        Instruction::Dup2 => (2, 4),
+        Instruction::Dup2X1 => (3, 5),
+        Instruction::Dup2X2 => (4, 6),
+        Instruction::Swap => (2, 2),
+        Instruction::Iadd | Instruction::Isub | Instruction::Imul | Instruction::Idiv | Instruction::Irem |
+        Instruction::Ishl | Instruction::Ishr | Instruction::Iushr | Instruction::Iand | Instruction::Ior |
+        Instruction::Ixor | Instruction::Fadd | Instruction::Fsub | Instruction::Fmul | Instruction::Fdiv |
+        Instruction::Frem | Instruction::Fcmpl | Instruction::Fcmpg | Instruction::L2i | Instruction::L2f |
+        Instruction::D2i | Instruction::D2f => (2, 1),
+        Instruction::Ladd | Instruction::Lsub | Instruction::Lmul | Instruction::Ldiv | Instruction::Lrem |
+        Instruction::Land | Instruction::Lor | Instruction::Lxor | Instruction::Dadd | Instruction::Dsub |
+        Instruction::Dmul | Instruction::Ddiv | Instruction::Drem => (4, 2),
+        // The shift distance is an int
+        Instruction::Lshl | Instruction::Lshr | Instruction::Lushr => (3, 2),
+        Instruction::Lcmp | Instruction::Dcmpl | Instruction::Dcmpg => (4, 1),
+        Instruction::Ineg | Instruction::Fneg | Instruction::I2f | Instruction::F2i | Instruction::I2b |
+        Instruction::I2c | Instruction::I2s | Instruction::Newarray(_) | Instruction::Anewarray(_) |
+        Instruction::Arraylength | Instruction::Checkcast(_) | Instruction::Instanceof(_) => (1, 1),
+        Instruction::Lneg | Instruction::Dneg | Instruction::L2d | Instruction::D2l => (2, 2),
+        Instruction::I2l | Instruction::I2d | Instruction::F2l | Instruction::F2d => (1, 2),
+        Instruction::Getstatic(field) => (0, field_slots(&field.descriptor)?),
+        Instruction::Putstatic(field) => (field_slots(&field.descriptor)?, 0),
+        Instruction::Getfield(field) => (1, field_slots(&field.descriptor)?),
+        Instruction::Putfield(field) => (1 + field_slots(&field.descriptor)?, 0),
+        Instruction::Invokevirtual(method) | Instruction::Invokespecial(method) | Instruction::Invokeinterface { method, .. } => {
+            let descriptor = parse_method_descriptor(&method.descriptor)?;
+            (1 + descriptor.parameter_slots() as u32, descriptor.return_slots() as u32)
+        }
+        Instruction::Invokestatic(method) => {
+            let descriptor = parse_method_descriptor(&method.descriptor)?;
+            (descriptor.parameter_slots() as u32, descriptor.return_slots() as u32)
+        }
+        Instruction::Invokedynamic(dynamic) => {
+            let descriptor = parse_method_descriptor(&dynamic.descriptor)?;
+            (descriptor.parameter_slots() as u32, descriptor.return_slots() as u32)
+        }
+        Instruction::Multianewarray { dimensions, .. } => (*dimensions as u32, 1),
+    })
+}
+
+fn field_slots(descriptor: &str) -> Result<u32, ParsingError> {
+    Ok(parse_field_descriptor(descriptor)?.slot_size() as u32)
+}
+
+fn constant_slots(constant: &Constant) -> Result<u32, ParsingError> {
+    match constant {
+        Constant::Long(_) | Constant::Double(_) => Ok(2),
+        Constant::Dynamic(dynamic) => field_slots(&dynamic.descriptor),
+        _ => Ok(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Class, DynamicRef, MemberRef, MemberRefKind};
+
+    fn limits(instructions: &[Instruction], parameter_slots: u16) -> Result<CodeLimits, ParsingError> {
+        let mut pcs: Vec<(u16, Instruction)> = Vec::new();
+        for (pc, instruction) in instructions.iter().enumerate() {
+            pcs.push((pc as u16, instruction.clone()));
+        }
+        compute_code_limits(&pcs, &[], parameter_slots)
+    }
+
+    fn method(kind: MemberRefKind, descriptor: &str) -> MemberRef {
+        MemberRef {
+            kind,
+            class: Class { name: "Test".to_string() },
+            name: "test".to_string(),
+            descriptor: descriptor.to_string(),
+        }
+    }
+
+    fn dynamic(descriptor: &str) -> DynamicRef {
+        DynamicRef { bootstrap_method_attr_index: 0, name: "test".to_string(), descriptor: descriptor.to_string() }
+    }
+
+    #[test]
+    fn category_2_locals() {
+        let code = [Instruction::Lload0, Instruction::Lstore2, Instruction::Dconst1, Instruction::Dstore(4), Instruction::Return];
+        assert_eq!(limits(&code, 2).unwrap(), CodeLimits { max_stack: 2, max_locals: 6 });
+        assert_eq!(limits(&[Instruction::Lload(300), Instruction::Pop2, Instruction::Return], 0).unwrap(), CodeLimits { max_stack: 2, max_locals: 302 });
+        assert_eq!(limits(&[Instruction::Return], 7).unwrap(), CodeLimits { max_stack: 0, max_locals: 7 });
+    }
+
+    #[test]
+    fn category_2_stack_values() {
+        let code = [
+            Instruction::Lconst1, Instruction::Ldc2W(Constant::Double(1.0)), Instruction::Ldc(Constant::Dynamic(dynamic("J"))),
+            Instruction::Pop2, Instruction::Pop2, Instruction::Pop2, Instruction::Return,
+        ];
+        assert_eq!(limits(&code, 0).unwrap(), CodeLimits { max_stack: 6, max_locals: 0 });
+        assert_eq!(limits(&[Instruction::Iconst0, Instruction::Pop2, Instruction::Return], 0).unwrap_err().kind, ParsingErrorKind::StackUnderflow);
+    }
+
+    #[test]
+    fn long_shifts_take_an_int_distance() {
+        let code = [
+            Instruction::Lconst1, Instruction::Iconst2, Instruction::Lshl, Instruction::Iconst1, Instruction::Lushr,
+            Instruction::Iconst3, Instruction::Lshr, Instruction::Pop2, Instruction::Return,
+        ];
+        assert_eq!(limits(&code, 0).unwrap(), CodeLimits { max_stack: 3, max_locals: 0 });
+    }
+
+    #[test]
+    fn dup2_x2() {
+        let code = [
+            Instruction::Iconst0, Instruction::Iconst1, Instruction::Iconst2, Instruction::Iconst3, Instruction::Dup2X2,
+            Instruction::Pop2, Instruction::Pop2, Instruction::Pop2, Instruction::Return,
+        ];
+        assert_eq!(limits(&code, 0).unwrap(), CodeLimits { max_stack: 6, max_locals: 0 });
+        let code = [
+            Instruction::Lconst0, Instruction::Lconst1, Instruction::Dup2X2,
+            Instruction::Pop2, Instruction::Pop2, Instruction::Pop2, Instruction::Return,
+        ];
+        assert_eq!(limits(&code, 0).unwrap(), CodeLimits { max_stack: 6, max_locals: 0 });
+        assert_eq!(limits(&[Instruction::Lconst0, Instruction::Iconst0, Instruction::Dup2X2], 0).unwrap_err().kind, ParsingErrorKind::StackUnderflow);
+    }
+
+    #[test]
+    fn invoke_descriptors() {
+        let code = [
+            Instruction::Lconst0, Instruction::AconstNull, Instruction::Dconst0,
+            Instruction::Invokestatic(method(MemberRefKind::Method, "(JLjava/lang/String;D)J")),
+            Instruction::Aload0, Instruction::Iconst0,
+            Instruction::Invokevirtual(method(MemberRefKind::Method, "(I)V")),
+            Instruction::Aload0,
+            Instruction::Invokeinterface { method: method(MemberRefKind::InterfaceMethod, "()D"), count: 1 },
+            Instruction::Aload0, Instruction::Lload1,
+            Instruction::Invokespecial(method(MemberRefKind::Method, "(J)V")),
+            Instruction::Iconst0, Instruction::Lconst0,
+            Instruction::Invokedynamic(dynamic("(IJ)Ljava/lang/Object;")),
+            Instruction::Pop, Instruction::Pop2, Instruction::Pop2, Instruction::Return,
+        ];
+        assert_eq!(limits(&code, 3).unwrap(), CodeLimits { max_stack: 7, max_locals: 3 });
+
+        let code = [Instruction::Iconst0, Instruction::Invokevirtual(method(MemberRefKind::Method, "(I)V")), Instruction::Return];
+        assert_eq!(limits(&code, 0).unwrap_err(), ParsingError::new(1, ParsingErrorKind::StackUnderflow));
+    }
+
+    #[test]
+    fn handlers_start_with_the_exception_on_the_stack() {
+        let code = [(0, Instruction::Lconst0), (1, Instruction::Pop2), (2, Instruction::Return), (3, Instruction::Astore0), (4, Instruction::Return)];
+        let handler = ExceptionHandler { start_pc: 0, end_pc: 3, handler_pc: 3, catch_type: None };
+        assert_eq!(compute_code_limits(&code, &[handler], 0).unwrap(), CodeLimits { max_stack: 2, max_locals: 1 });
+
+        // Only the handler pushes anything
+        let code = [(0, Instruction::Nop), (1, Instruction::Return), (2, Instruction::Athrow)];
+        let handler = ExceptionHandler { start_pc: 0, end_pc: 1, handler_pc: 2, catch_type: None };
+        assert_eq!(compute_code_limits(&code, &[handler], 0).unwrap(), CodeLimits { max_stack: 1, max_locals: 0 });
+
+        let code = [(0, Instruction::Nop), (1, Instruction::Return), (2, Instruction::Pop2)];
+        let handler = ExceptionHandler { start_pc: 0, end_pc: 1, handler_pc: 2, catch_type: None };
+        assert_eq!(compute_code_limits(&code, &[handler], 0).unwrap_err(), ParsingError::new(2, ParsingErrorKind::StackUnderflow));
+    }
+
+    #[test]
+    fn subroutines() {
+        // The return address is stored in a local variable, and execution continues after the jsr
+        let code = [(0, Instruction::Jsr(4)), (3, Instruction::Return), (4, Instruction::Astore1), (5, Instruction::Ret(1))];
+        assert_eq!(compute_code_limits(&code, &[], 0).unwrap(), CodeLimits { max_stack: 1, max_locals: 2 });
+        let code = [(0, Instruction::Iconst0), (1, Instruction::JsrW(8)), (6, Instruction::Pop), (7, Instruction::Return), (8, Instruction::Astore(3)), (10, Instruction::Ret(3))];
+        assert_eq!(compute_code_limits(&code, &[], 0).unwrap(), CodeLimits { max_stack: 2, max_locals: 4 });
+    }
+
+    #[test]
+    fn inconsistent_stack_heights() {
+        let code = [(0, Instruction::Iload0), (1, Instruction::Ifeq(5)), (4, Instruction::Iconst0), (5, Instruction::Return)];
+        assert_eq!(compute_code_limits(&code, &[], 1).unwrap_err(), ParsingError::new(5, ParsingErrorKind::InconsistentStackHeight { expected: 0, actual: 1 }));
+    }
+
+    #[test]
+    fn control_falls_off_the_end() {
+        assert_eq!(limits(&[Instruction::Iconst0, Instruction::Pop], 0).unwrap_err(), ParsingError::new(1, ParsingErrorKind::FallsOffEnd));
+        let code = [(0, Instruction::Iload0), (1, Instruction::Ifne(0))];
+        assert_eq!(compute_code_limits(&code, &[], 1).unwrap_err(), ParsingError::new(1, ParsingErrorKind::FallsOffEnd));
+        assert_eq!(limits(&[Instruction::Goto(0)], 0).unwrap(), CodeLimits { max_stack: 0, max_locals: 0 });
+    }
+}
@@ -0,0 +1,572 @@
+//! Assembling `Code` attributes from instructions, with symbolic labels instead of absolute pcs.
+
+use crate::constant_pool::ConstantPoolBuilder;
+use crate::instruction::{switch_padding, write_instruction, Instruction};
+use crate::types::{Attribute, Class, ExceptionHandler, LineNumber, WritingError, WritingErrorKind};
+
+/// A position in the code of a [`CodeBuilder`], created by [`CodeBuilder::new_label`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction(Instruction),
+    Branch { instruction: fn(u16) -> Instruction, target: Label },
+    Tableswitch { low: i32, default: Label, targets: Vec<Label> },
+    Lookupswitch { default: Label, pairs: Vec<(i32, Label)> },
+}
+
+#[derive(Debug, Clone)]
+struct Handler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: Option<String>,
+}
+
+/// Emits the instructions of a method and turns them into an `Attribute::Code`.
+///
+/// Branches, switches and exception handlers refer to labels, which are resolved to pcs by
+/// [`CodeBuilder::build`]. Branches that can't reach their target with a 16 bit offset are widened:
+/// `goto` and `jsr` become `goto_w` and `jsr_w`, conditional branches jump over a `goto_w` with the
+/// opposite condition.
+#[derive(Debug, Clone, Default)]
+pub struct CodeBuilder {
+    items: Vec<Item>,
+    /// The index of the item each label is placed before
+    labels: Vec<Option<usize>>,
+    /// A label that was placed more than once
+    misplaced_label: Option<Label>,
+    /// Line numbers and the index of the first item they apply to
+    line_numbers: Vec<(usize, u16)>,
+    handlers: Vec<Handler>,
+}
+
+/// The encoding chosen for a branch
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BranchForm {
+    Short,
+    /// `goto_w` or `jsr_w`
+    Wide,
+    /// The opposite condition jumping over a `goto_w` to the target
+    Inverted,
+}
+
+impl CodeBuilder {
+    pub fn new() -> CodeBuilder {
+        CodeBuilder::default()
+    }
+
+    /// Creates a label that is not placed yet
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Places `label` before the next instruction. Every label has to be placed exactly once.
+    pub fn place_label(&mut self, label: Label) {
+        match self.labels.get_mut(label.0) {
+            Some(position @ None) => *position = Some(self.items.len()),
+            _ => {
+                self.misplaced_label.get_or_insert(label);
+            }
+        }
+    }
+
+    /// Adds an instruction. Branches and switches take absolute pcs here, use
+    /// [`CodeBuilder::branch`] and the switch methods to refer to labels instead.
+    pub fn instruction(&mut self, instruction: Instruction) {
+        self.items.push(Item::Instruction(instruction));
+    }
+
+    /// Adds a branch to `target`, e.g. `builder.branch(Instruction::Ifeq, label)`
+    pub fn branch(&mut self, instruction: fn(u16) -> Instruction, target: Label) {
+        self.items.push(Item::Branch { instruction, target });
+    }
+
+    /// Adds a `tableswitch` that jumps to `targets[i]` for the value `low + i`. The last value has
+    /// to fit in an i32.
+    pub fn tableswitch(&mut self, low: i32, default: Label, targets: Vec<Label>) {
+        self.items.push(Item::Tableswitch { low, default, targets });
+    }
+
+    /// Adds a `lookupswitch`, the pairs are sorted by their key
+    pub fn lookupswitch(&mut self, default: Label, mut pairs: Vec<(i32, Label)>) {
+        pairs.sort_by_key(|(key, _)| *key);
+        self.items.push(Item::Lookupswitch { default, pairs });
+    }
+
+    /// Marks the next instruction as the start of `line` in the source file. Without a next
+    /// instruction, the line number is dropped.
+    pub fn line_number(&mut self, line: u16) {
+        self.line_numbers.push((self.items.len(), line));
+    }
+
+    /// Adds an exception handler at `handler` for the code from `start` until `end`. Handlers are
+    /// searched in the order they were added. `catch_type` is a class name in internal form, `None`
+    /// catches everything.
+    pub fn exception_handler(&mut self, start: Label, end: Label, handler: Label, catch_type: Option<&str>) {
+        self.handlers.push(Handler { start, end, handler, catch_type: catch_type.map(str::to_string) });
+    }
+
+    /// Lays out and encodes the code. Constant pool operands are added to `constant_pool`, which is
+    /// left as it was if the build fails.
+    pub fn build(self, constant_pool: &mut ConstantPoolBuilder, max_stack: u16, max_locals: u16) -> Result<Attribute, WritingError> {
+        if let Some(label) = self.misplaced_label {
+            return Err(WritingError::new(WritingErrorKind::InvalidLabel { label: label.0 }));
+        }
+        let label_items: Vec<usize> = self.labels.iter().enumerate()
+            .map(|(i, position)| position.ok_or_else(|| WritingError::new(WritingErrorKind::InvalidLabel { label: i })))
+            .collect::<Result<_, _>>()?;
+        // Labels of another builder
+        if let Some(label) = self.referenced_labels().find(|label| label.0 >= label_items.len()) {
+            return Err(WritingError::new(WritingErrorKind::InvalidLabel { label: label.0 }));
+        }
+
+        // Measuring and encoding instructions adds their constants, so they go to a copy of the pool
+        // until everything succeeded
+        let mut scratch_pool = constant_pool.clone();
+
+        // Start with short branches and widen the ones that don't reach their target until the
+        // layout doesn't change anymore. Widening only makes the code longer, so this terminates.
+        let mut forms: Vec<BranchForm> = Vec::with_capacity(self.items.len());
+        for (i, item) in self.items.iter().enumerate() {
+            if let Item::Branch { instruction, .. } = item {
+                if !is_branch(&instruction(0)) {
+                    return Err(WritingError::new(WritingErrorKind::InvalidInstruction { mnemonic: instruction(0).mnemonic() })
+                        .with_context(format!("code[{}]", i)));
+                }
+            }
+            forms.push(BranchForm::Short);
+        }
+        let pcs = loop {
+            let pcs = self.layout(&forms, &mut scratch_pool)?;
+            let mut changed = false;
+            for (i, item) in self.items.iter().enumerate() {
+                if let Item::Branch { instruction, target } = item {
+                    let offset = pcs[label_items[target.0]] as i64 - pcs[i] as i64;
+                    if forms[i] == BranchForm::Short && i16::try_from(offset).is_err() {
+                        forms[i] = if has_inverse(&instruction(0)) { BranchForm::Inverted } else { BranchForm::Wide };
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break pcs;
+            }
+        };
+        let code_length = pcs[self.items.len()];
+        if code_length > u16::MAX as usize {
+            return Err(WritingError::new(WritingErrorKind::TooLarge { length: code_length, max: u16::MAX as usize }));
+        }
+        let label_pc = |label: &Label| pcs[label_items[label.0]] as u16;
+
+        let mut code: Vec<u8> = Vec::with_capacity(code_length);
+        for (i, item) in self.items.iter().enumerate() {
+            let pc = pcs[i] as u16;
+            match item {
+                Item::Instruction(instruction) => write_instruction(&mut code, pc, instruction, &mut scratch_pool),
+                Item::Branch { instruction, target } => {
+                    let target = label_pc(target);
+                    match forms[i] {
+                        BranchForm::Short => write_instruction(&mut code, pc, &narrow(&instruction(target)), &mut scratch_pool),
+                        BranchForm::Wide => write_instruction(&mut code, pc, &widen(&instruction(target)), &mut scratch_pool),
+                        BranchForm::Inverted => {
+                            let inverted = inverse(&instruction(0)).expect("only conditional branches are inverted");
+                            write_instruction(&mut code, pc, &inverted(pc + 8), &mut scratch_pool)
+                                .and_then(|_| write_instruction(&mut code, pc + 3, &Instruction::GotoW(target), &mut scratch_pool))
+                        }
+                    }
+                }
+                Item::Tableswitch { low, default, targets } => {
+                    i32::try_from(*low as i64 + targets.len() as i64 - 1)
+                        .map_err(|_| WritingError::new(WritingErrorKind::InvalidInstruction { mnemonic: "tableswitch" }))
+                        .and_then(|high| {
+                            let instruction = Instruction::Tableswitch {
+                                default: label_pc(default),
+                                low: *low,
+                                high,
+                                targets: targets.iter().map(label_pc).collect(),
+                            };
+                            write_instruction(&mut code, pc, &instruction, &mut scratch_pool)
+                        })
+                }
+                Item::Lookupswitch { default, pairs } => {
+                    let instruction = Instruction::Lookupswitch {
+                        default: label_pc(default),
+                        pairs: pairs.iter().map(|(key, target)| (*key, label_pc(target))).collect(),
+                    };
+                    write_instruction(&mut code, pc, &instruction, &mut scratch_pool)
+                }
+            }.map_err(|e| e.with_context(format!("code[{}]", i)))?;
+        }
+
+        let exception_table = self.handlers.iter()
+            .map(|handler| ExceptionHandler {
+                start_pc: label_pc(&handler.start),
+                end_pc: label_pc(&handler.end),
+                handler_pc: label_pc(&handler.handler),
+                catch_type: handler.catch_type.as_ref().map(|name| Class { name: name.clone() }),
+            })
+            .collect();
+        // Line numbers after the last instruction don't mark any code and are dropped
+        let line_number_table: Vec<LineNumber> = self.line_numbers.iter()
+            .filter(|(item, _)| *item < self.items.len())
+            .map(|(item, line)| LineNumber { start_pc: pcs[*item] as u16, line_number: *line })
+            .collect();
+        let mut attributes: Vec<Attribute> = Vec::new();
+        if !line_number_table.is_empty() {
+            attributes.push(Attribute::LineNumberTable { line_number_table });
+        }
+
+        *constant_pool = scratch_pool;
+        Ok(Attribute::Code { max_stack, max_locals, code, exception_table, attributes })
+    }
+
+    fn referenced_labels(&self) -> impl Iterator<Item = &Label> {
+        let items = self.items.iter().flat_map(|item| -> Box<dyn Iterator<Item = &Label>> {
+            match item {
+                Item::Instruction(_) => Box::new(std::iter::empty()),
+                Item::Branch { target, .. } => Box::new(std::iter::once(target)),
+                Item::Tableswitch { default, targets, .. } => Box::new(std::iter::once(default).chain(targets)),
+                Item::Lookupswitch { default, pairs } => Box::new(std::iter::once(default).chain(pairs.iter().map(|(_, target)| target))),
+            }
+        });
+        let handlers = self.handlers.iter().flat_map(|handler| [&handler.start, &handler.end, &handler.handler]);
+        items.chain(handlers)
+    }
+
+    /// The pc of every item with the given branch forms, followed by the length of the code
+    fn layout(&self, forms: &[BranchForm], constant_pool: &mut ConstantPoolBuilder) -> Result<Vec<usize>, WritingError> {
+        let mut pcs: Vec<usize> = Vec::with_capacity(self.items.len() + 1);
+        let mut pc: usize = 0;
+        let mut scratch: Vec<u8> = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            pcs.push(pc);
+            pc += match item {
+                Item::Instruction(instruction) => match instruction {
+                    Instruction::GotoW(_) | Instruction::JsrW(_) => 5,
+                    _ if is_branch(instruction) => 3,
+                    Instruction::Tableswitch { targets, .. } => 1 + switch_padding(pc as u16) + 12 + 4 * targets.len(),
+                    Instruction::Lookupswitch { pairs, .. } => 1 + switch_padding(pc as u16) + 8 + 8 * pairs.len(),
+                    _ => {
+                        // The length of the remaining instructions only depends on their operands
+                        scratch.clear();
+                        write_instruction(&mut scratch, 0, instruction, constant_pool).map_err(|e| e.with_context(format!("code[{}]", i)))?;
+                        scratch.len()
+                    }
+                },
+                Item::Branch { .. } => match forms[i] {
+                    BranchForm::Short => 3,
+                    BranchForm::Wide => 5,
+                    BranchForm::Inverted => 8,
+                },
+                Item::Tableswitch { targets, .. } => 1 + switch_padding(pc as u16) + 12 + 4 * targets.len(),
+                Item::Lookupswitch { pairs, .. } => 1 + switch_padding(pc as u16) + 8 + 8 * pairs.len(),
+            };
+        }
+        pcs.push(pc);
+        Ok(pcs)
+    }
+}
+
+fn is_branch(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Goto(_) | Instruction::Jsr(_) | Instruction::GotoW(_) | Instruction::JsrW(_)) || has_inverse(instruction)
+}
+
+fn has_inverse(instruction: &Instruction) -> bool {
+    inverse(instruction).is_some()
+}
+
+/// The conditional branch that jumps when `instruction` doesn't
+fn inverse(instruction: &Instruction) -> Option<fn(u16) -> Instruction> {
+    Some(match instruction {
+        Instruction::Ifeq(_) => Instruction::Ifne,
+        Instruction::Ifne(_) => Instruction::Ifeq,
+        Instruction::Iflt(_) => Instruction::Ifge,
+        Instruction::Ifge(_) => Instruction::Iflt,
+        Instruction::Ifgt(_) => Instruction::Ifle,
+        Instruction::Ifle(_) => Instruction::Ifgt,
+        Instruction::IfIcmpeq(_) => Instruction::IfIcmpne,
+        Instruction::IfIcmpne(_) => Instruction::IfIcmpeq,
+        Instruction::IfIcmplt(_) => Instruction::IfIcmpge,
+        Instruction::IfIcmpge(_) => Instruction::IfIcmplt,
+        Instruction::IfIcmpgt(_) => Instruction::IfIcmple,
+        Instruction::IfIcmple(_) => Instruction::IfIcmpgt,
+        Instruction::IfAcmpeq(_) => Instruction::IfAcmpne,
+        Instruction::IfAcmpne(_) => Instruction::IfAcmpeq,
+        Instruction::Ifnull(_) => Instruction::Ifnonnull,
+        Instruction::Ifnonnull(_) => Instruction::Ifnull,
+        _ => return None,
+    })
+}
+
+/// The 16 bit form of an unconditional branch
+fn narrow(instruction: &Instruction) -> Instruction {
+    match instruction {
+        Instruction::GotoW(target) => Instruction::Goto(*target),
+        Instruction::JsrW(target) => Instruction::Jsr(*target),
+        _ => instruction.clone(),
+    }
+}
+
+/// The 32 bit form of an unconditional branch
+fn widen(instruction: &Instruction) -> Instruction {
+    match instruction {
+        Instruction::Goto(target) => Instruction::GotoW(*target),
+        Instruction::Jsr(target) => Instruction::JsrW(*target),
+        _ => instruction.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::read_instructions;
+    use crate::types::Constant;
+
+    fn nops(code: &mut CodeBuilder, count: usize) {
+        for _ in 0..count {
+            code.instruction(Instruction::Nop);
+        }
+    }
+
+    fn build(code: CodeBuilder) -> (Vec<u8>, Vec<(u16, Instruction)>) {
+        let mut constant_pool = ConstantPoolBuilder::new();
+        match code.build(&mut constant_pool, 2, 2).unwrap() {
+            Attribute::Code { code, .. } => {
+                let instructions = read_instructions(&code, constant_pool.constant_pool()).unwrap();
+                (code, instructions)
+            }
+            attribute => panic!("{:?}", attribute),
+        }
+    }
+
+    #[test]
+    fn far_gotos_are_widened() {
+        let mut code = CodeBuilder::new();
+        let start = code.new_label();
+        let end = code.new_label();
+        code.place_label(start);
+        code.branch(Instruction::Goto, end);
+        nops(&mut code, 40000);
+        code.place_label(end);
+        code.branch(Instruction::Goto, start);
+        code.instruction(Instruction::Return);
+
+        let (_, instructions) = build(code);
+        assert_eq!(instructions[0], (0, Instruction::GotoW(40005)));
+        assert_eq!(instructions[1], (5, Instruction::Nop));
+        assert_eq!(instructions[40001], (40005, Instruction::GotoW(0)));
+        assert_eq!(instructions[40002], (40010, Instruction::Return));
+    }
+
+    #[test]
+    fn gotos_are_widened_past_32767_bytes() {
+        for (count, expected) in [(32764, Instruction::Goto(32767)), (32765, Instruction::GotoW(32770))] {
+            let mut code = CodeBuilder::new();
+            let end = code.new_label();
+            code.branch(Instruction::Goto, end);
+            nops(&mut code, count);
+            code.place_label(end);
+            code.instruction(Instruction::Return);
+
+            let (_, instructions) = build(code);
+            assert_eq!(instructions[0], (0, expected));
+        }
+    }
+
+    #[test]
+    fn far_conditional_branches_are_inverted() {
+        let mut code = CodeBuilder::new();
+        let end = code.new_label();
+        code.instruction(Instruction::Iload(0));
+        code.branch(Instruction::Ifeq, end);
+        nops(&mut code, 40000);
+        code.place_label(end);
+        code.instruction(Instruction::Aload(1));
+        code.branch(Instruction::Ifnull, end);
+        code.instruction(Instruction::Return);
+
+        let (_, instructions) = build(code);
+        assert_eq!(instructions[..3], [
+            (0, Instruction::Iload(0)),
+            (2, Instruction::Ifne(10)),
+            (5, Instruction::GotoW(40010)),
+        ]);
+        assert_eq!(instructions[3], (10, Instruction::Nop));
+        assert_eq!(instructions[40003..], [
+            (40010, Instruction::Aload(1)),
+            (40012, Instruction::Ifnull(40010)),
+            (40015, Instruction::Return),
+        ]);
+    }
+
+    #[test]
+    fn switches_are_padded_at_every_alignment() {
+        for offset in 0..4u16 {
+            let mut code = CodeBuilder::new();
+            let first = code.new_label();
+            let second = code.new_label();
+            let default = code.new_label();
+            nops(&mut code, offset as usize);
+            code.tableswitch(5, default, vec![first, second]);
+            code.lookupswitch(default, vec![(10, first), (-3, second)]);
+            code.place_label(first);
+            code.instruction(Instruction::Nop);
+            code.place_label(second);
+            code.instruction(Instruction::Nop);
+            code.place_label(default);
+            code.instruction(Instruction::Return);
+
+            // The operands start at the next multiple of 4 after the opcode
+            let lookupswitch_pc = offset + 1 + (3 - offset % 4) + 12 + 2 * 4;
+            let first_pc = lookupswitch_pc + 1 + (3 - lookupswitch_pc % 4) + 8 + 2 * 8;
+            let (_, instructions) = build(code);
+            let offset = offset as usize;
+            assert_eq!(instructions[offset..], [
+                (offset as u16, Instruction::Tableswitch { default: first_pc + 2, low: 5, high: 6, targets: vec![first_pc, first_pc + 1] }),
+                (lookupswitch_pc, Instruction::Lookupswitch { default: first_pc + 2, pairs: vec![(-3, first_pc + 1), (10, first_pc)] }),
+                (first_pc, Instruction::Nop),
+                (first_pc + 1, Instruction::Nop),
+                (first_pc + 2, Instruction::Return),
+            ]);
+        }
+    }
+
+    #[test]
+    fn wide_local_indices() {
+        let mut code = CodeBuilder::new();
+        code.instruction(Instruction::Iload(300));
+        code.instruction(Instruction::Iinc { index: 1, value: 200 });
+        code.instruction(Instruction::Istore(2));
+        code.instruction(Instruction::Return);
+
+        let (bytes, instructions) = build(code);
+        assert_eq!(bytes[0], 0xc4);
+        assert_eq!(bytes[4], 0xc4);
+        assert_eq!(instructions, [
+            (0, Instruction::Iload(300)),
+            (4, Instruction::Iinc { index: 1, value: 200 }),
+            (10, Instruction::Istore(2)),
+            (12, Instruction::Return),
+        ]);
+    }
+
+    #[test]
+    fn handlers_and_line_numbers() {
+        let mut code = CodeBuilder::new();
+        let start = code.new_label();
+        let end = code.new_label();
+        let handler = code.new_label();
+        code.place_label(start);
+        code.line_number(1);
+        code.instruction(Instruction::Iload(0));
+        code.instruction(Instruction::Ireturn);
+        code.place_label(end);
+        code.place_label(handler);
+        code.line_number(2);
+        code.instruction(Instruction::Athrow);
+        code.exception_handler(start, end, handler, Some("java/lang/Exception"));
+
+        let attribute = code.build(&mut ConstantPoolBuilder::new(), 1, 1).unwrap();
+        let (exception_table, attributes) = match attribute {
+            Attribute::Code { exception_table, attributes, .. } => (exception_table, attributes),
+            attribute => panic!("{:?}", attribute),
+        };
+        assert_eq!(exception_table, [ExceptionHandler { start_pc: 0, end_pc: 3, handler_pc: 3, catch_type: Some(Class { name: "java/lang/Exception".to_string() }) }]);
+        assert_eq!(attributes, [Attribute::LineNumberTable { line_number_table: vec![
+            LineNumber { start_pc: 0, line_number: 1 },
+            LineNumber { start_pc: 3, line_number: 2 },
+        ] }]);
+    }
+
+    #[test]
+    fn trailing_line_numbers_are_dropped() {
+        let mut code = CodeBuilder::new();
+        code.line_number(1);
+        code.instruction(Instruction::Return);
+        code.line_number(2);
+        let attribute = code.build(&mut ConstantPoolBuilder::new(), 0, 0).unwrap();
+        let attributes = match attribute {
+            Attribute::Code { attributes, .. } => attributes,
+            attribute => panic!("{:?}", attribute),
+        };
+        assert_eq!(attributes, [Attribute::LineNumberTable { line_number_table: vec![LineNumber { start_pc: 0, line_number: 1 }] }]);
+
+        let mut code = CodeBuilder::new();
+        code.instruction(Instruction::Return);
+        code.line_number(1);
+        let attribute = code.build(&mut ConstantPoolBuilder::new(), 0, 0).unwrap();
+        assert!(matches!(attribute, Attribute::Code { attributes, .. } if attributes.is_empty()));
+    }
+
+    #[test]
+    fn invalid_labels() {
+        let build_error = |code: CodeBuilder| code.build(&mut ConstantPoolBuilder::new(), 0, 0).unwrap_err().kind;
+
+        let mut code = CodeBuilder::new();
+        let label = code.new_label();
+        code.branch(Instruction::Goto, label);
+        assert_eq!(build_error(code), WritingErrorKind::InvalidLabel { label: 0 });
+
+        let mut code = CodeBuilder::new();
+        let label = code.new_label();
+        code.place_label(label);
+        code.instruction(Instruction::Nop);
+        code.place_label(label);
+        code.branch(Instruction::Goto, label);
+        assert_eq!(build_error(code), WritingErrorKind::InvalidLabel { label: 0 });
+
+        let mut other = CodeBuilder::new();
+        other.new_label();
+        let foreign = other.new_label();
+        let mut code = CodeBuilder::new();
+        let label = code.new_label();
+        code.place_label(label);
+        code.instruction(Instruction::Return);
+        code.exception_handler(label, foreign, label, None);
+        assert_eq!(build_error(code), WritingErrorKind::InvalidLabel { label: 1 });
+
+        let mut code = CodeBuilder::new();
+        let label = code.new_label();
+        code.place_label(label);
+        code.branch(Instruction::Iload, label);
+        assert_eq!(build_error(code), WritingErrorKind::InvalidInstruction { mnemonic: "iload" });
+    }
+
+    #[test]
+    fn failed_builds_leave_the_constant_pool_unchanged() {
+        let mut constant_pool = ConstantPoolBuilder::new();
+        constant_pool.utf8("existing").unwrap();
+
+        let mut code = CodeBuilder::new();
+        code.instruction(Instruction::Ldc(Constant::String("new".to_string())));
+        code.instruction(Instruction::Tableswitch { default: 0, low: 1, high: 0, targets: Vec::new() });
+        let error = code.build(&mut constant_pool, 1, 0).unwrap_err();
+        assert_eq!(error.kind, WritingErrorKind::InvalidInstruction { mnemonic: "tableswitch" });
+        assert_eq!(constant_pool.constant_pool().len(), 1);
+
+        let mut code = CodeBuilder::new();
+        let label = code.new_label();
+        code.instruction(Instruction::Ldc(Constant::String("new".to_string())));
+        code.place_label(label);
+        code.tableswitch(i32::MAX, label, vec![label, label]);
+        let error = code.build(&mut constant_pool, 1, 0).unwrap_err();
+        assert_eq!(error.kind, WritingErrorKind::InvalidInstruction { mnemonic: "tableswitch" });
+        assert_eq!(error.context, ["code[1]"]);
+        assert_eq!(constant_pool.constant_pool().len(), 1);
+
+        let mut code = CodeBuilder::new();
+        code.instruction(Instruction::Ldc(Constant::String("new".to_string())));
+        nops(&mut code, u16::MAX as usize);
+        let error = code.build(&mut constant_pool, 1, 0).unwrap_err();
+        assert_eq!(error.kind, WritingErrorKind::TooLarge { length: u16::MAX as usize + 2, max: u16::MAX as usize });
+        assert_eq!(constant_pool.constant_pool().len(), 1);
+
+        let mut code = CodeBuilder::new();
+        code.instruction(Instruction::Ldc(Constant::String("new".to_string())));
+        code.build(&mut constant_pool, 1, 0).unwrap();
+        assert_eq!(constant_pool.constant_pool().len(), 3);
+    }
+}
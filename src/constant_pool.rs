@@ -0,0 +1,258 @@
+//! Building constant pools for generated or modified classes.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::types::{ClassFile, Constant, ConstantPool, ConstantPoolEntry, DynamicRef, MemberRef, MemberRefKind, MethodHandle, WritingError, WritingErrorKind};
+use crate::writer::write_constant_pool_entry;
+
+/// `constant_pool_count` is a u2 that counts one more than the slots of the pool
+pub const MAX_CONSTANT_POOL_SLOTS: usize = 65534;
+
+/// The constant pool indices a parsed class referred to. The references are grouped by the part of
+/// the class they are in, like `methods[main:([Ljava/lang/String;)V]/attributes[Code]`, and kept in
+/// the order they were read. Parts are named by what identifies them rather than by their position,
+/// so adding, removing or changing one part of a class leaves the references of the others intact.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ConstantPoolReferences {
+    pub(crate) indices: BTreeMap<String, Vec<u16>>,
+}
+
+/// Adds entries to a constant pool and returns their indices. Adding a value that is already in the
+/// pool returns the index of the existing entry, entries it refers to, like the Utf8 of a Class, are
+/// added as needed.
+#[derive(Debug, Clone, Default)]
+pub struct ConstantPoolBuilder {
+    constant_pool: ConstantPool,
+    /// Each entry in its class file encoding, mapped to its index
+    indices: HashMap<Vec<u8>, u16>,
+    /// The references of the parsed class being written, and how many of them have been used up in
+    /// each part of it
+    references: ConstantPoolReferences,
+    used_references: HashMap<String, usize>,
+    /// The part of the class being written, in the form of the keys of `references`
+    path: String,
+}
+
+impl ConstantPoolBuilder {
+    pub fn new() -> ConstantPoolBuilder {
+        ConstantPoolBuilder::default()
+    }
+
+    /// Starts from an existing pool, e.g. of a parsed class. Its entries keep their indices and new
+    /// ones are appended. If the pool holds the same value twice, the first entry is used. Utf8
+    /// entries that kept their `raw` bytes only match those exact bytes, never a string value.
+    pub fn from_constant_pool(constant_pool: ConstantPool) -> ConstantPoolBuilder {
+        let mut indices: HashMap<Vec<u8>, u16> = HashMap::new();
+        for (i, entry) in constant_pool.iter().enumerate() {
+            let index = (i + 1) as u16;
+            let mut key: Vec<u8> = Vec::new();
+            if write_constant_pool_entry(&mut key, entry).is_ok() && !key.is_empty() {
+                indices.entry(key).or_insert(index);
+            }
+        }
+        ConstantPoolBuilder { constant_pool, indices, ..ConstantPoolBuilder::default() }
+    }
+
+    /// Starts from the pool of a parsed class, like [`ConstantPoolBuilder::from_constant_pool`], for
+    /// writing it back with [`ConstantPoolBuilder::reference`]
+    pub(crate) fn from_class_file(class_file: &ClassFile) -> ConstantPoolBuilder {
+        let mut constant_pool = ConstantPoolBuilder::from_constant_pool(class_file.constant_pool.clone());
+        constant_pool.references = class_file.constant_pool_references.clone();
+        constant_pool
+    }
+
+    /// Returns the index the parsed class used for its next reference in the current part if `matches`
+    /// accepts the entry there, and otherwise adds the value with `add`. References are written in
+    /// the same order the reader resolved them, so an unmodified class refers to the same entries as
+    /// before, even if the pool holds some value twice.
+    pub(crate) fn reference(&mut self, matches: impl FnOnce(&ConstantPool, u16) -> bool, add: impl FnOnce(&mut ConstantPoolBuilder) -> Result<u16, WritingError>) -> Result<u16, WritingError> {
+        if let Some(indices) = self.references.indices.get(&self.path) {
+            let used = self.used_references.entry(self.path.clone()).or_insert(0);
+            if let Some(&index) = indices.get(*used) {
+                *used += 1;
+                if matches(&self.constant_pool, index) {
+                    return Ok(index);
+                }
+            }
+        }
+        add(self)
+    }
+
+    /// Runs `write` for the part of the class called `key`, like `fields[x:I]` or `attributes[Code]`,
+    /// which is nested in the current one
+    pub(crate) fn in_part<T>(&mut self, key: &str, write: impl FnOnce(&mut ConstantPoolBuilder) -> T) -> T {
+        let length = self.path.len();
+        if length > 0 {
+            self.path.push('/');
+        }
+        self.path.push_str(key);
+        let result = write(self);
+        self.path.truncate(length);
+        result
+    }
+
+    /// The pool built so far
+    pub fn constant_pool(&self) -> &ConstantPool {
+        &self.constant_pool
+    }
+
+    pub fn build(self) -> ConstantPool {
+        self.constant_pool
+    }
+
+    pub fn utf8(&mut self, value: &str) -> Result<u16, WritingError> {
+        self.add(ConstantPoolEntry::Utf8Info { value: value.to_string(), raw: None })
+    }
+
+    pub fn integer(&mut self, value: i32) -> Result<u16, WritingError> {
+        self.add(ConstantPoolEntry::IntegerInfo { value: value as u32 })
+    }
+
+    pub fn float(&mut self, value: f32) -> Result<u16, WritingError> {
+        self.add(ConstantPoolEntry::FloatInfo { value })
+    }
+
+    /// Adds a Long entry, which takes up two slots
+    pub fn long(&mut self, value: i64) -> Result<u16, WritingError> {
+        self.add(ConstantPoolEntry::LongInfo { value: value as u64 })
+    }
+
+    /// Adds a Double entry, which takes up two slots
+    pub fn double(&mut self, value: f64) -> Result<u16, WritingError> {
+        self.add(ConstantPoolEntry::DoubleInfo { value })
+    }
+
+    /// Adds a Class entry for a class name in internal form, e.g. `java/lang/String` or `[I`
+    pub fn class(&mut self, name: &str) -> Result<u16, WritingError> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantPoolEntry::Class { name_index })
+    }
+
+    pub fn string(&mut self, value: &str) -> Result<u16, WritingError> {
+        let string_index = self.utf8(value)?;
+        self.add(ConstantPoolEntry::StringInfo { string_index })
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> Result<u16, WritingError> {
+        let name_index = self.utf8(name)?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(ConstantPoolEntry::NameAndTypeInfo { name_index, descriptor_index })
+    }
+
+    /// Adds a Fieldref, Methodref or InterfaceMethodref entry, depending on the kind of `member_ref`
+    pub fn member_ref(&mut self, member_ref: &MemberRef) -> Result<u16, WritingError> {
+        let class_index = self.class(&member_ref.class.name)?;
+        let name_and_type_index = self.name_and_type(&member_ref.name, &member_ref.descriptor)?;
+        self.add(match member_ref.kind {
+            MemberRefKind::Field => ConstantPoolEntry::Fieldref { class_index, name_and_type_index },
+            MemberRefKind::Method => ConstantPoolEntry::Methodref { class_index, name_and_type_index },
+            MemberRefKind::InterfaceMethod => ConstantPoolEntry::InterfaceMethodref { class_index, name_and_type_index },
+        })
+    }
+
+    pub fn method_handle(&mut self, method_handle: &MethodHandle) -> Result<u16, WritingError> {
+        let reference_index = self.member_ref(&method_handle.reference)?;
+        self.add(ConstantPoolEntry::MethodHandle { reference_kind: method_handle.reference_kind as u8, reference_index })
+    }
+
+    pub fn method_type(&mut self, descriptor: &str) -> Result<u16, WritingError> {
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(ConstantPoolEntry::MethodTypeInfo { descriptor_index })
+    }
+
+    /// Adds a Dynamic entry, a constant computed by a bootstrap method
+    pub fn dynamic(&mut self, dynamic: &DynamicRef) -> Result<u16, WritingError> {
+        let name_and_type_index = self.name_and_type(&dynamic.name, &dynamic.descriptor)?;
+        self.add(ConstantPoolEntry::Dynamic { bootstrap_method_attr_index: dynamic.bootstrap_method_attr_index, name_and_type_index })
+    }
+
+    /// Adds an InvokeDynamic entry, the operand of an `invokedynamic` instruction
+    pub fn invoke_dynamic(&mut self, dynamic: &DynamicRef) -> Result<u16, WritingError> {
+        let name_and_type_index = self.name_and_type(&dynamic.name, &dynamic.descriptor)?;
+        self.add(ConstantPoolEntry::InvokeDynamicInfo { bootstrap_method_attr_index: dynamic.bootstrap_method_attr_index, name_and_type_index })
+    }
+
+    pub fn module(&mut self, name: &str) -> Result<u16, WritingError> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantPoolEntry::Module { name_index })
+    }
+
+    /// Adds a Package entry for a package name in internal form, e.g. `java/util`
+    pub fn package(&mut self, name: &str) -> Result<u16, WritingError> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantPoolEntry::Package { name_index })
+    }
+
+    /// Adds any loadable constant, as used by `ldc` and bootstrap method arguments
+    pub fn constant(&mut self, constant: &Constant) -> Result<u16, WritingError> {
+        match constant {
+            Constant::Integer(value) => self.integer(*value),
+            Constant::Float(value) => self.float(*value),
+            Constant::Long(value) => self.long(*value),
+            Constant::Double(value) => self.double(*value),
+            Constant::String(value) => self.string(value),
+            Constant::Class(class) => self.class(&class.name),
+            Constant::MethodType(descriptor) => self.method_type(descriptor),
+            Constant::MethodHandle(method_handle) => self.method_handle(method_handle),
+            Constant::Dynamic(dynamic) => self.dynamic(dynamic),
+        }
+    }
+
+    fn add(&mut self, entry: ConstantPoolEntry) -> Result<u16, WritingError> {
+        let mut key: Vec<u8> = Vec::new();
+        write_constant_pool_entry(&mut key, &entry)?;
+        if let Some(index) = self.indices.get(&key) {
+            return Ok(*index);
+        }
+
+        // Longs and Doubles take up the slot after them as well
+        let slots = match entry {
+            ConstantPoolEntry::LongInfo { .. } | ConstantPoolEntry::DoubleInfo { .. } => 2,
+            _ => 1,
+        };
+        if self.constant_pool.len() + slots > MAX_CONSTANT_POOL_SLOTS {
+            return Err(WritingError::new(WritingErrorKind::ConstantPoolOverflow));
+        }
+        let index = (self.constant_pool.len() + 1) as u16;
+        self.constant_pool.push(entry);
+        if slots == 2 {
+            self.constant_pool.push(ConstantPoolEntry::Empty);
+        }
+        self.indices.insert(key, index);
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_deduplicated() {
+        let mut constant_pool = ConstantPoolBuilder::new();
+        let class = constant_pool.class("java/lang/String").unwrap();
+        assert_eq!(constant_pool.class("java/lang/String").unwrap(), class);
+        assert_eq!(constant_pool.utf8("java/lang/String").unwrap(), 1);
+        assert_eq!(constant_pool.long(1).unwrap(), 3);
+        assert_eq!(constant_pool.integer(1).unwrap(), 5);
+        assert_eq!(constant_pool.constant_pool()[3], ConstantPoolEntry::Empty);
+    }
+
+    #[test]
+    fn raw_utf8_entries_only_match_their_bytes() {
+        // An unpaired surrogate followed by a byte that can't start a character
+        let raw: Vec<u8> = vec![b'a', 0xED, 0xA0, 0x80, 0xFF];
+        let lossy = crate::mutf8::decode_lossy(&raw);
+        let mut constant_pool = ConstantPoolBuilder::from_constant_pool(vec![
+            ConstantPoolEntry::Utf8Info { value: lossy.clone(), raw: Some(raw.clone()) },
+            ConstantPoolEntry::Utf8Info { value: "a".to_string(), raw: None },
+        ]);
+
+        // Looking up the decoded value adds a canonical entry instead of using the raw one
+        let index = constant_pool.utf8(&lossy).unwrap();
+        assert_eq!(index, 3);
+        assert_eq!(constant_pool.utf8(&lossy).unwrap(), 3);
+        assert_eq!(constant_pool.utf8("a").unwrap(), 2);
+        assert_eq!(constant_pool.add(ConstantPoolEntry::Utf8Info { value: lossy, raw: Some(raw) }).unwrap(), 1);
+    }
+}
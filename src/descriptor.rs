@@ -0,0 +1,216 @@
+//! Parser for field and method descriptors (JVMS §4.3), e.g. `[Ljava/lang/String;` or `(IJ)V`.
+//!
+//! Like the types in [`crate::signature`], the parsed descriptors render as java source with fully
+//! qualified class names, and without packages in the alternate form (`{:#}`).
+
+use std::fmt;
+
+use crate::signature::BaseType;
+use crate::types::{ParsingError, ParsingErrorKind};
+
+/// The type of a field, parameter or return value
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Base(BaseType),
+    /// A class in internal form, e.g. `java/lang/String`
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+/// A parsed method descriptor. `return_type` is `None` for `void` methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    pub return_type: Option<FieldType>,
+}
+
+impl FieldType {
+    /// The number of local variable slots or operand stack entries a value of this type takes up
+    pub fn slot_size(&self) -> u16 {
+        match self {
+            FieldType::Base(BaseType::Long | BaseType::Double) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl MethodDescriptor {
+    /// The number of local variable slots the parameters take up, not counting `this`
+    pub fn parameter_slots(&self) -> u16 {
+        self.parameters.iter().map(FieldType::slot_size).sum()
+    }
+
+    /// The number of operand stack entries the return value takes up, 0 for `void`
+    pub fn return_slots(&self) -> u16 {
+        self.return_type.as_ref().map_or(0, FieldType::slot_size)
+    }
+}
+
+/// A descriptor can't have more array dimensions than this
+pub const MAX_ARRAY_DIMENSIONS: usize = 255;
+
+pub fn parse_field_descriptor(descriptor: &str) -> Result<FieldType, ParsingError> {
+    let mut index = 0;
+    let field_type = read_field_type(descriptor, &mut index)?;
+    if index != descriptor.len() {
+        return Err(invalid_descriptor(descriptor, index));
+    }
+    Ok(field_type)
+}
+
+pub fn parse_method_descriptor(descriptor: &str) -> Result<MethodDescriptor, ParsingError> {
+    let mut index = 0;
+    if !descriptor.starts_with('(') {
+        return Err(invalid_descriptor(descriptor, index));
+    }
+    index += 1;
+    let mut parameters: Vec<FieldType> = Vec::new();
+    while !descriptor[index..].starts_with(')') {
+        parameters.push(read_field_type(descriptor, &mut index)?);
+    }
+    index += 1;
+    let return_type = if &descriptor[index..] == "V" {
+        None
+    } else {
+        Some(parse_field_descriptor(&descriptor[index..]).map_err(|_| invalid_descriptor(descriptor, index))?)
+    };
+    // Parameters take up at most 255 slots, including `this`, which isn't part of the descriptor
+    if parameters.iter().map(|parameter| u32::from(parameter.slot_size())).sum::<u32>() > 255 {
+        return Err(invalid_descriptor(descriptor, 0));
+    }
+    Ok(MethodDescriptor { parameters, return_type })
+}
+
+fn invalid_descriptor(descriptor: &str, at_byte: usize) -> ParsingError {
+    ParsingError::new(at_byte, ParsingErrorKind::InvalidDescriptor { descriptor: descriptor.to_string() })
+}
+
+fn read_field_type(descriptor: &str, index: &mut usize) -> Result<FieldType, ParsingError> {
+    let start = *index;
+    let dimensions = descriptor[start..].len() - descriptor[start..].trim_start_matches('[').len();
+    if dimensions > MAX_ARRAY_DIMENSIONS {
+        return Err(invalid_descriptor(descriptor, start));
+    }
+    *index += dimensions;
+
+    let mut field_type = match descriptor[*index..].chars().next() {
+        Some('L') => {
+            let name_start = *index + 1;
+            let name_length = descriptor[name_start..].find(';').ok_or_else(|| invalid_descriptor(descriptor, *index))?;
+            let name = &descriptor[name_start..name_start + name_length];
+            if !is_valid_class_name(name) {
+                return Err(invalid_descriptor(descriptor, name_start));
+            }
+            *index = name_start + name_length + 1;
+            FieldType::Object(name.to_string())
+        }
+        Some(tag) => {
+            let base_type = BaseType::from_tag(tag).ok_or_else(|| invalid_descriptor(descriptor, *index))?;
+            *index += 1;
+            FieldType::Base(base_type)
+        }
+        None => return Err(invalid_descriptor(descriptor, *index)),
+    };
+
+    for _ in 0..dimensions {
+        field_type = FieldType::Array(Box::new(field_type));
+    }
+    Ok(field_type)
+}
+
+/// Checks that a class name in internal form consists of non-empty identifiers separated by `/`
+fn is_valid_class_name(name: &str) -> bool {
+    name.split('/').all(|identifier| !identifier.is_empty() && !identifier.contains(['.', ';', '[', '<', '>']))
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Base(base_type) => fmt::Display::fmt(base_type, f),
+            FieldType::Object(name) if f.alternate() => f.write_str(name.rsplit('/').next().unwrap_or(name)),
+            FieldType::Object(name) => f.write_str(&name.replace('/', ".")),
+            FieldType::Array(component) => {
+                fmt::Display::fmt(component.as_ref(), f)?;
+                f.write_str("[]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for MethodDescriptor {
+    /// Renders the descriptor like a method declaration without a name, e.g. `void (int, java.lang.String)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.return_type {
+            Some(return_type) => fmt::Display::fmt(return_type, f)?,
+            None => f.write_str("void")?,
+        }
+        f.write_str(" (")?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            fmt::Display::fmt(parameter, f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_descriptors() {
+        let field_type = parse_field_descriptor("[[Ljava/lang/String;").unwrap();
+        assert_eq!(field_type, FieldType::Array(Box::new(FieldType::Array(Box::new(FieldType::Object("java/lang/String".to_string()))))));
+        assert_eq!(field_type.to_string(), "java.lang.String[][]");
+        assert_eq!(format!("{:#}", field_type), "String[][]");
+
+        assert_eq!(parse_field_descriptor("J").unwrap().slot_size(), 2);
+        assert_eq!(parse_field_descriptor("D").unwrap().slot_size(), 2);
+        assert_eq!(parse_field_descriptor("I").unwrap().slot_size(), 1);
+        assert_eq!(parse_field_descriptor("[J").unwrap().slot_size(), 1);
+        assert_eq!(parse_field_descriptor("Ljava/lang/Long;").unwrap().slot_size(), 1);
+
+        assert!(parse_field_descriptor(&format!("{}I", "[".repeat(MAX_ARRAY_DIMENSIONS))).is_ok());
+        assert!(parse_field_descriptor(&format!("[{}I", "[".repeat(MAX_ARRAY_DIMENSIONS))).is_err());
+    }
+
+    #[test]
+    fn method_descriptors() {
+        let descriptor = parse_method_descriptor("(IJ[DLjava/lang/Object;D)V").unwrap();
+        assert_eq!(descriptor.parameters.len(), 5);
+        assert_eq!(descriptor.parameter_slots(), 7);
+        assert_eq!(descriptor.return_slots(), 0);
+        assert_eq!(descriptor.to_string(), "void (int, long, double[], java.lang.Object, double)");
+        assert_eq!(format!("{:#}", descriptor), "void (int, long, double[], Object, double)");
+
+        let descriptor = parse_method_descriptor("()J").unwrap();
+        assert_eq!(descriptor.parameter_slots(), 0);
+        assert_eq!(descriptor.return_slots(), 2);
+        assert_eq!(parse_method_descriptor("()Ljava/lang/String;").unwrap().return_slots(), 1);
+    }
+
+    #[test]
+    fn parameter_slot_limit() {
+        let descriptor = parse_method_descriptor(&format!("({})V", "I".repeat(255))).unwrap();
+        assert_eq!(descriptor.parameter_slots(), 255);
+        assert!(parse_method_descriptor(&format!("({})V", "I".repeat(256))).is_err());
+        assert!(parse_method_descriptor(&format!("({}I)V", "J".repeat(127))).is_ok());
+        assert!(parse_method_descriptor(&format!("({})V", "J".repeat(128))).is_err());
+        // Enough parameters to overflow a u16 slot count
+        assert!(parse_method_descriptor(&format!("({})V", "J".repeat(40000))).is_err());
+    }
+
+    #[test]
+    fn malformed_descriptors() {
+        for descriptor in ["", "V", "[V", "L;", "Ljava/lang/Object", "Ljava//Object;", "Ljava.lang.Object;", "Q", "II", "[", "Ljava/lang/Object;I"] {
+            let error = parse_field_descriptor(descriptor).unwrap_err();
+            assert!(matches!(error.kind, ParsingErrorKind::InvalidDescriptor { .. }), "{:?}", descriptor);
+        }
+        for descriptor in ["", "(I", "()", "I)V", "(V)V", "([V)V", "(L;)V", "(Ljava/lang/Object)V", "()VV", "()V;", "(I)[V", "()Ljava/lang/Object"] {
+            let error = parse_method_descriptor(descriptor).unwrap_err();
+            assert!(matches!(error.kind, ParsingErrorKind::InvalidDescriptor { .. }), "{:?}", descriptor);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Renders classes in a layout close to the output of `javap -c`, with the operands of instructions
+//! resolved to their symbolic form instead of constant pool indices.
+
+use std::fmt::Write;
+
+use crate::instruction::{ArrayType, Instruction, Instructions};
+use crate::types::{AccessFlag, Attribute, ClassFile, Constant, ExceptionHandler, Field, FieldFlag, LineNumber, MemberRef, Method, MethodFlag, MethodHandle, Module, ModuleFlag, ParsingError, ReferenceKind, RequiresFlag};
+
+pub fn disassemble(class_file: &ClassFile) -> Result<String, ParsingError> {
+    let mut out = String::new();
+    if let Some(source_file) = class_file.source_file() {
+        writeln!(out, "Compiled from \"{}\"", source_file).unwrap();
+    }
+    if let Some(module) = class_file.module() {
+        out.push_str(&module_declaration(module));
+        return Ok(out);
+    }
+    writeln!(out, "{} {{", class_header(class_file)).unwrap();
+
+    let mut first = true;
+    for field in &class_file.fields {
+        if !first {
+            out.push('\n');
+        }
+        first = false;
+        writeln!(out, "  {};", field_header(field)).unwrap();
+    }
+    for method in &class_file.methods {
+        if !first {
+            out.push('\n');
+        }
+        first = false;
+        writeln!(out, "  {};", method_header(class_file, method)).unwrap();
+        for attribute in &method.attributes {
+            if let Attribute::Code { max_stack, max_locals, code, exception_table, attributes } = attribute {
+                write_code(&mut out, class_file, *max_stack, *max_locals, code, exception_table, attributes)?;
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// Renders a module descriptor the way it would be written in `module-info.java`
+pub fn module_declaration(module: &Module) -> String {
+    let mut out = String::new();
+    if module.flags.contains(&ModuleFlag::AccOpen) {
+        out.push_str("open ");
+    }
+    write!(out, "module {}", module.name).unwrap();
+    if let Some(version) = &module.version {
+        write!(out, "@{}", version).unwrap();
+    }
+    out.push_str(" {\n");
+
+    for requires in &module.requires {
+        out.push_str("  requires ");
+        if requires.flags.contains(&RequiresFlag::AccTransitive) {
+            out.push_str("transitive ");
+        }
+        if requires.flags.contains(&RequiresFlag::AccStaticPhase) {
+            out.push_str("static ");
+        }
+        writeln!(out, "{};", requires.module).unwrap();
+    }
+    for (directive, package, to) in module.exports.iter().map(|exports| ("exports", &exports.package, &exports.to))
+        .chain(module.opens.iter().map(|opens| ("opens", &opens.package, &opens.to))) {
+        write!(out, "  {} {}", directive, package.replace('/', ".")).unwrap();
+        if !to.is_empty() {
+            write!(out, " to {}", to.join(", ")).unwrap();
+        }
+        out.push_str(";\n");
+    }
+    for service in &module.uses {
+        writeln!(out, "  uses {};", service.name.replace('/', ".")).unwrap();
+    }
+    for provides in &module.provides {
+        let implementations: Vec<String> = provides.with.iter().map(|class| class.name.replace('/', ".")).collect();
+        writeln!(out, "  provides {} with {};", provides.service.name.replace('/', "."), implementations.join(", ")).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn class_header(class_file: &ClassFile) -> String {
+    let flags = &class_file.access_flags;
+    let is_interface = flags.contains(&AccessFlag::AccInterface);
+    let mut header = String::new();
+    if flags.contains(&AccessFlag::AccPublic) {
+        header.push_str("public ");
+    }
+    if flags.contains(&AccessFlag::AccFinal) {
+        header.push_str("final ");
+    }
+    if flags.contains(&AccessFlag::AccAbstract) && !is_interface {
+        header.push_str("abstract ");
+    }
+    header.push_str(if is_interface { "interface " } else { "class " });
+    header.push_str(&class_file.this_class.name.replace('/', "."));
+
+    if let Some(super_class) = &class_file.super_class {
+        if super_class.name != "java/lang/Object" {
+            header.push_str(" extends ");
+            header.push_str(&super_class.name.replace('/', "."));
+        }
+    }
+    if !class_file.interfaces.is_empty() {
+        header.push_str(if is_interface { " extends " } else { " implements " });
+        let interfaces: Vec<String> = class_file.interfaces.iter().map(|class| class.name.replace('/', ".")).collect();
+        header.push_str(&interfaces.join(", "));
+    }
+    header
+}
+
+fn field_header(field: &Field) -> String {
+    let flags = &field.access_flags;
+    let mut header = String::new();
+    for (flag, modifier) in [
+        (FieldFlag::AccPublic, "public "),
+        (FieldFlag::AccPrivate, "private "),
+        (FieldFlag::AccProtected, "protected "),
+        (FieldFlag::AccStatic, "static "),
+        (FieldFlag::AccFinal, "final "),
+        (FieldFlag::AccVolatile, "volatile "),
+        (FieldFlag::AccTransient, "transient "),
+    ] {
+        if flags.contains(&flag) {
+            header.push_str(modifier);
+        }
+    }
+    header.push_str(&field.type_name());
+    header.push(' ');
+    header.push_str(&field.name);
+    header
+}
+
+fn method_header(class_file: &ClassFile, method: &Method) -> String {
+    let flags = &method.access_flags;
+    if method.name == "<clinit>" {
+        return String::from("static {}");
+    }
+    let mut header = String::new();
+    for (flag, modifier) in [
+        (MethodFlag::AccPublic, "public "),
+        (MethodFlag::AccPrivate, "private "),
+        (MethodFlag::AccProtected, "protected "),
+        (MethodFlag::AccStatic, "static "),
+        (MethodFlag::AccFinal, "final "),
+        (MethodFlag::AccSynchronized, "synchronized "),
+        (MethodFlag::AccNative, "native "),
+        (MethodFlag::AccAbstract, "abstract "),
+    ] {
+        if flags.contains(&flag) {
+            header.push_str(modifier);
+        }
+    }
+
+    if method.name == "<init>" {
+        header.push_str(&class_file.this_class.name.replace('/', "."));
+    } else {
+        header.push_str(&method.return_type_name());
+        header.push(' ');
+        header.push_str(&method.name);
+    }
+    header.push('(');
+    header.push_str(&method.parameter_type_names().join(", "));
+    header.push(')');
+
+    for attribute in &method.attributes {
+        if let Attribute::Exceptions { exceptions } = attribute {
+            if !exceptions.is_empty() {
+                let exceptions: Vec<String> = exceptions.iter().map(|class| class.name.replace('/', ".")).collect();
+                header.push_str(" throws ");
+                header.push_str(&exceptions.join(", "));
+            }
+        }
+    }
+    header
+}
+
+fn write_code(
+    out: &mut String,
+    class_file: &ClassFile,
+    max_stack: u16,
+    max_locals: u16,
+    code: &[u8],
+    exception_table: &[ExceptionHandler],
+    attributes: &[Attribute],
+) -> Result<(), ParsingError> {
+    writeln!(out, "    Code:").unwrap();
+    writeln!(out, "      stack={}, locals={}", max_stack, max_locals).unwrap();
+
+    let line_numbers: Vec<&LineNumber> = attributes.iter()
+        .filter_map(|attribute| match attribute {
+            Attribute::LineNumberTable { line_number_table } => Some(line_number_table),
+            _ => None
+        })
+        .flatten()
+        .collect();
+
+    for result in Instructions::new(code, &class_file.constant_pool) {
+        let (pc, instruction) = result.map_err(|e| e.with_context("code"))?;
+        for line_number in line_numbers.iter().filter(|line_number| line_number.start_pc == pc) {
+            writeln!(out, "      // line {}", line_number.line_number).unwrap();
+        }
+        write_instruction(out, class_file, pc, &instruction);
+    }
+
+    if !exception_table.is_empty() {
+        writeln!(out, "    Exception table:").unwrap();
+        writeln!(out, "       from    to  target type").unwrap();
+        for handler in exception_table {
+            let catch_type = match &handler.catch_type {
+                Some(class) => format!("Class {}", class.name),
+                None => String::from("any"),
+            };
+            writeln!(out, "    {:>6}{:>6}{:>6}   {}", handler.start_pc, handler.end_pc, handler.handler_pc, catch_type).unwrap();
+        }
+    }
+    Ok(())
+}
+
+fn write_instruction(out: &mut String, class_file: &ClassFile, pc: u16, instruction: &Instruction) {
+    let operand = match instruction {
+        Instruction::Bipush(value) => value.to_string(),
+        Instruction::Sipush(value) => value.to_string(),
+        Instruction::Ldc(constant) | Instruction::LdcW(constant) | Instruction::Ldc2W(constant) => format_constant(constant),
+        Instruction::Iload(index) | Instruction::Lload(index) | Instruction::Fload(index) | Instruction::Dload(index) | Instruction::Aload(index) |
+        Instruction::Istore(index) | Instruction::Lstore(index) | Instruction::Fstore(index) | Instruction::Dstore(index) | Instruction::Astore(index) |
+        Instruction::Ret(index) => index.to_string(),
+        Instruction::Iinc { index, value } => format!("{}, {}", index, value),
+        Instruction::Ifeq(target) | Instruction::Ifne(target) | Instruction::Iflt(target) | Instruction::Ifge(target) |
+        Instruction::Ifgt(target) | Instruction::Ifle(target) | Instruction::IfIcmpeq(target) | Instruction::IfIcmpne(target) |
+        Instruction::IfIcmplt(target) | Instruction::IfIcmpge(target) | Instruction::IfIcmpgt(target) | Instruction::IfIcmple(target) |
+        Instruction::IfAcmpeq(target) | Instruction::IfAcmpne(target) | Instruction::Goto(target) | Instruction::Jsr(target) |
+        Instruction::Ifnull(target) | Instruction::Ifnonnull(target) | Instruction::GotoW(target) | Instruction::JsrW(target) => target.to_string(),
+        Instruction::Tableswitch { default, low, high, targets } => {
+            writeln!(out, "{:>8}: {:<13} {{ // {} to {}", pc, instruction.mnemonic(), low, high).unwrap();
+            for (key, target) in (*low..=*high).zip(targets) {
+                writeln!(out, "{:>22}: {}", key, target).unwrap();
+            }
+            writeln!(out, "{:>22}: {}", "default", default).unwrap();
+            writeln!(out, "          }}").unwrap();
+            return;
+        }
+        Instruction::Lookupswitch { default, pairs } => {
+            writeln!(out, "{:>8}: {:<13} {{ // {}", pc, instruction.mnemonic(), pairs.len()).unwrap();
+            for (key, target) in pairs {
+                writeln!(out, "{:>22}: {}", key, target).unwrap();
+            }
+            writeln!(out, "{:>22}: {}", "default", default).unwrap();
+            writeln!(out, "          }}").unwrap();
+            return;
+        }
+        Instruction::Getstatic(member) | Instruction::Putstatic(member) | Instruction::Getfield(member) | Instruction::Putfield(member) |
+        Instruction::Invokevirtual(member) | Instruction::Invokespecial(member) | Instruction::Invokestatic(member) => format_member(class_file, member),
+        Instruction::Invokeinterface { method, count } => format!("{}, {}", format_member(class_file, method), count),
+        Instruction::Invokedynamic(dynamic) => format!("#{}:{}:{}", dynamic.bootstrap_method_attr_index, dynamic.name, dynamic.descriptor),
+        Instruction::New(class) | Instruction::Anewarray(class) | Instruction::Checkcast(class) | Instruction::Instanceof(class) => class.name.clone(),
+        Instruction::Newarray(array_type) => format_array_type(*array_type).to_string(),
+        Instruction::Multianewarray { class, dimensions } => format!("{}, {}", class.name, dimensions),
+        _ => String::new()
+    };
+
+    if operand.is_empty() {
+        writeln!(out, "{:>8}: {}", pc, instruction.mnemonic()).unwrap();
+    } else {
+        writeln!(out, "{:>8}: {:<13} {}", pc, instruction.mnemonic(), operand).unwrap();
+    }
+}
+
+/// Formats a member like javap does, leaving out the class if it is the class being disassembled
+fn format_member(class_file: &ClassFile, member: &MemberRef) -> String {
+    let name = if member.name.starts_with('<') { format!("\"{}\"", member.name) } else { member.name.clone() };
+    if member.class.name == class_file.this_class.name {
+        format!("{}:{}", name, member.descriptor)
+    } else {
+        format!("{}.{}:{}", member.class.name, name, member.descriptor)
+    }
+}
+
+/// Formats a loadable constant like javap does, e.g. `String hello` or `long 5l`
+pub fn format_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(value) => format!("int {}", value),
+        Constant::Float(value) => format!("float {}f", format_float(*value)),
+        Constant::Long(value) => format!("long {}l", value),
+        Constant::Double(value) => format!("double {}d", format_double(*value)),
+        Constant::String(value) => format!("String {}", value.escape_debug()),
+        Constant::Class(class) => format!("class {}", class.name),
+        Constant::MethodType(descriptor) => format!("MethodType {}", descriptor),
+        Constant::MethodHandle(handle) => format!("MethodHandle {}", format_method_handle(handle)),
+        Constant::Dynamic(dynamic) => format!("Dynamic #{}:{}:{}", dynamic.bootstrap_method_attr_index, dynamic.name, dynamic.descriptor),
+    }
+}
+
+fn format_float(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        format_double(value as f64)
+    }
+}
+
+fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Formats a method handle like javap does, e.g. `REF_invokeStatic java/lang/Math.abs:(I)I`
+pub fn format_method_handle(handle: &MethodHandle) -> String {
+    let kind = match handle.reference_kind {
+        ReferenceKind::GetField => "REF_getField",
+        ReferenceKind::GetStatic => "REF_getStatic",
+        ReferenceKind::PutField => "REF_putField",
+        ReferenceKind::PutStatic => "REF_putStatic",
+        ReferenceKind::InvokeVirtual => "REF_invokeVirtual",
+        ReferenceKind::InvokeStatic => "REF_invokeStatic",
+        ReferenceKind::InvokeSpecial => "REF_invokeSpecial",
+        ReferenceKind::NewInvokeSpecial => "REF_newInvokeSpecial",
+        ReferenceKind::InvokeInterface => "REF_invokeInterface",
+    };
+    let reference = &handle.reference;
+    format!("{} {}.{}:{}", kind, reference.class.name, reference.name, reference.descriptor)
+}
+
+fn format_array_type(array_type: ArrayType) -> &'static str {
+    match array_type {
+        ArrayType::Boolean => "boolean",
+        ArrayType::Char => "char",
+        ArrayType::Float => "float",
+        ArrayType::Double => "double",
+        ArrayType::Byte => "byte",
+        ArrayType::Short => "short",
+        ArrayType::Int => "int",
+        ArrayType::Long => "long",
+    }
+}
@@ -0,0 +1,1333 @@
+//! Decoding and encoding of the bytecode stored in `Code` attributes.
+
+use crate::constant_pool::ConstantPoolBuilder;
+use crate::reader::{read_class_from_constant_pool, read_constant_from_constant_pool, read_dynamic_from_constant_pool, read_member_ref_from_constant_pool, read_u1, read_u2, read_u4};
+use crate::types::{Class, Constant, ConstantPool, DynamicRef, MemberRef, ParsingError, ParsingErrorKind, WritingError, WritingErrorKind};
+use crate::writer::{write_u1, write_u2, write_u4};
+
+/// A single JVM instruction.
+///
+/// Constant pool operands are resolved and branch targets are absolute pcs. Local variable indices
+/// are always 16 bits wide, so an instruction prefixed with `wide` decodes to the same variant as
+/// its short form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Nop,
+    AconstNull,
+    IconstM1,
+    Iconst0,
+    Iconst1,
+    Iconst2,
+    Iconst3,
+    Iconst4,
+    Iconst5,
+    Lconst0,
+    Lconst1,
+    Fconst0,
+    Fconst1,
+    Fconst2,
+    Dconst0,
+    Dconst1,
+    Bipush(i8),
+    Sipush(i16),
+    Ldc(Constant),
+    LdcW(Constant),
+    Ldc2W(Constant),
+    Iload(u16),
+    Lload(u16),
+    Fload(u16),
+    Dload(u16),
+    Aload(u16),
+    Iload0,
+    Iload1,
+    Iload2,
+    Iload3,
+    Lload0,
+    Lload1,
+    Lload2,
+    Lload3,
+    Fload0,
+    Fload1,
+    Fload2,
+    Fload3,
+    Dload0,
+    Dload1,
+    Dload2,
+    Dload3,
+    Aload0,
+    Aload1,
+    Aload2,
+    Aload3,
+    Iaload,
+    Laload,
+    Faload,
+    Daload,
+    Aaload,
+    Baload,
+    Caload,
+    Saload,
+    Istore(u16),
+    Lstore(u16),
+    Fstore(u16),
+    Dstore(u16),
+    Astore(u16),
+    Istore0,
+    Istore1,
+    Istore2,
+    Istore3,
+    Lstore0,
+    Lstore1,
+    Lstore2,
+    Lstore3,
+    Fstore0,
+    Fstore1,
+    Fstore2,
+    Fstore3,
+    Dstore0,
+    Dstore1,
+    Dstore2,
+    Dstore3,
+    Astore0,
+    Astore1,
+    Astore2,
+    Astore3,
+    Iastore,
+    Lastore,
+    Fastore,
+    Dastore,
+    Aastore,
+    Bastore,
+    Castore,
+    Sastore,
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+    Iadd,
+    Ladd,
+    Fadd,
+    Dadd,
+    Isub,
+    Lsub,
+    Fsub,
+    Dsub,
+    Imul,
+    Lmul,
+    Fmul,
+    Dmul,
+    Idiv,
+    Ldiv,
+    Fdiv,
+    Ddiv,
+    Irem,
+    Lrem,
+    Frem,
+    Drem,
+    Ineg,
+    Lneg,
+    Fneg,
+    Dneg,
+    Ishl,
+    Lshl,
+    Ishr,
+    Lshr,
+    Iushr,
+    Lushr,
+    Iand,
+    Land,
+    Ior,
+    Lor,
+    Ixor,
+    Lxor,
+    Iinc { index: u16, value: i16 },
+    I2l,
+    I2f,
+    I2d,
+    L2i,
+    L2f,
+    L2d,
+    F2i,
+    F2l,
+    F2d,
+    D2i,
+    D2l,
+    D2f,
+    I2b,
+    I2c,
+    I2s,
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+    Ifeq(u16),
+    Ifne(u16),
+    Iflt(u16),
+    Ifge(u16),
+    Ifgt(u16),
+    Ifle(u16),
+    IfIcmpeq(u16),
+    IfIcmpne(u16),
+    IfIcmplt(u16),
+    IfIcmpge(u16),
+    IfIcmpgt(u16),
+    IfIcmple(u16),
+    IfAcmpeq(u16),
+    IfAcmpne(u16),
+    Goto(u16),
+    Jsr(u16),
+    Ret(u16),
+    Tableswitch { default: u16, low: i32, high: i32, targets: Vec<u16> },
+    Lookupswitch { default: u16, pairs: Vec<(i32, u16)> },
+    Ireturn,
+    Lreturn,
+    Freturn,
+    Dreturn,
+    Areturn,
+    Return,
+    Getstatic(MemberRef),
+    Putstatic(MemberRef),
+    Getfield(MemberRef),
+    Putfield(MemberRef),
+    Invokevirtual(MemberRef),
+    Invokespecial(MemberRef),
+    Invokestatic(MemberRef),
+    Invokeinterface { method: MemberRef, count: u8 },
+    Invokedynamic(DynamicRef),
+    New(Class),
+    Newarray(ArrayType),
+    Anewarray(Class),
+    Arraylength,
+    Athrow,
+    Checkcast(Class),
+    Instanceof(Class),
+    Monitorenter,
+    Monitorexit,
+    Multianewarray { class: Class, dimensions: u8 },
+    Ifnull(u16),
+    Ifnonnull(u16),
+    GotoW(u16),
+    JsrW(u16),
+}
+
+/// The element type operand of `newarray`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Nop => 0x00,
+            Instruction::AconstNull => 0x01,
+            Instruction::IconstM1 => 0x02,
+            Instruction::Iconst0 => 0x03,
+            Instruction::Iconst1 => 0x04,
+            Instruction::Iconst2 => 0x05,
+            Instruction::Iconst3 => 0x06,
+            Instruction::Iconst4 => 0x07,
+            Instruction::Iconst5 => 0x08,
+            Instruction::Lconst0 => 0x09,
+            Instruction::Lconst1 => 0x0a,
+            Instruction::Fconst0 => 0x0b,
+            Instruction::Fconst1 => 0x0c,
+            Instruction::Fconst2 => 0x0d,
+            Instruction::Dconst0 => 0x0e,
+            Instruction::Dconst1 => 0x0f,
+            Instruction::Bipush(_) => 0x10,
+            Instruction::Sipush(_) => 0x11,
+            Instruction::Ldc(_) => 0x12,
+            Instruction::LdcW(_) => 0x13,
+            Instruction::Ldc2W(_) => 0x14,
+            Instruction::Iload(_) => 0x15,
+            Instruction::Lload(_) => 0x16,
+            Instruction::Fload(_) => 0x17,
+            Instruction::Dload(_) => 0x18,
+            Instruction::Aload(_) => 0x19,
+            Instruction::Iload0 => 0x1a,
+            Instruction::Iload1 => 0x1b,
+            Instruction::Iload2 => 0x1c,
+            Instruction::Iload3 => 0x1d,
+            Instruction::Lload0 => 0x1e,
+            Instruction::Lload1 => 0x1f,
+            Instruction::Lload2 => 0x20,
+            Instruction::Lload3 => 0x21,
+            Instruction::Fload0 => 0x22,
+            Instruction::Fload1 => 0x23,
+            Instruction::Fload2 => 0x24,
+            Instruction::Fload3 => 0x25,
+            Instruction::Dload0 => 0x26,
+            Instruction::Dload1 => 0x27,
+            Instruction::Dload2 => 0x28,
+            Instruction::Dload3 => 0x29,
+            Instruction::Aload0 => 0x2a,
+            Instruction::Aload1 => 0x2b,
+            Instruction::Aload2 => 0x2c,
+            Instruction::Aload3 => 0x2d,
+            Instruction::Iaload => 0x2e,
+            Instruction::Laload => 0x2f,
+            Instruction::Faload => 0x30,
+            Instruction::Daload => 0x31,
+            Instruction::Aaload => 0x32,
+            Instruction::Baload => 0x33,
+            Instruction::Caload => 0x34,
+            Instruction::Saload => 0x35,
+            Instruction::Istore(_) => 0x36,
+            Instruction::Lstore(_) => 0x37,
+            Instruction::Fstore(_) => 0x38,
+            Instruction::Dstore(_) => 0x39,
+            Instruction::Astore(_) => 0x3a,
+            Instruction::Istore0 => 0x3b,
+            Instruction::Istore1 => 0x3c,
+            Instruction::Istore2 => 0x3d,
+            Instruction::Istore3 => 0x3e,
+            Instruction::Lstore0 => 0x3f,
+            Instruction::Lstore1 => 0x40,
+            Instruction::Lstore2 => 0x41,
+            Instruction::Lstore3 => 0x42,
+            Instruction::Fstore0 => 0x43,
+            Instruction::Fstore1 => 0x44,
+            Instruction::Fstore2 => 0x45,
+            Instruction::Fstore3 => 0x46,
+            Instruction::Dstore0 => 0x47,
+            Instruction::Dstore1 => 0x48,
+            Instruction::Dstore2 => 0x49,
+            Instruction::Dstore3 => 0x4a,
+            Instruction::Astore0 => 0x4b,
+            Instruction::Astore1 => 0x4c,
+            Instruction::Astore2 => 0x4d,
+            Instruction::Astore3 => 0x4e,
+            Instruction::Iastore => 0x4f,
+            Instruction::Lastore => 0x50,
+            Instruction::Fastore => 0x51,
+            Instruction::Dastore => 0x52,
+            Instruction::Aastore => 0x53,
+            Instruction::Bastore => 0x54,
+            Instruction::Castore => 0x55,
+            Instruction::Sastore => 0x56,
+            Instruction::Pop => 0x57,
+            Instruction::Pop2 => 0x58,
+            Instruction::Dup => 0x59,
+            Instruction::DupX1 => 0x5a,
+            Instruction::DupX2 => 0x5b,
+            Instruction::Dup2 => 0x5c,
+            Instruction::Dup2X1 => 0x5d,
+            Instruction::Dup2X2 => 0x5e,
+            Instruction::Swap => 0x5f,
+            Instruction::Iadd => 0x60,
+            Instruction::Ladd => 0x61,
+            Instruction::Fadd => 0x62,
+            Instruction::Dadd => 0x63,
+            Instruction::Isub => 0x64,
+            Instruction::Lsub => 0x65,
+            Instruction::Fsub => 0x66,
+            Instruction::Dsub => 0x67,
+            Instruction::Imul => 0x68,
+            Instruction::Lmul => 0x69,
+            Instruction::Fmul => 0x6a,
+            Instruction::Dmul => 0x6b,
+            Instruction::Idiv => 0x6c,
+            Instruction::Ldiv => 0x6d,
+            Instruction::Fdiv => 0x6e,
+            Instruction::Ddiv => 0x6f,
+            Instruction::Irem => 0x70,
+            Instruction::Lrem => 0x71,
+            Instruction::Frem => 0x72,
+            Instruction::Drem => 0x73,
+            Instruction::Ineg => 0x74,
+            Instruction::Lneg => 0x75,
+            Instruction::Fneg => 0x76,
+            Instruction::Dneg => 0x77,
+            Instruction::Ishl => 0x78,
+            Instruction::Lshl => 0x79,
+            Instruction::Ishr => 0x7a,
+            Instruction::Lshr => 0x7b,
+            Instruction::Iushr => 0x7c,
+            Instruction::Lushr => 0x7d,
+            Instruction::Iand => 0x7e,
+            Instruction::Land => 0x7f,
+            Instruction::Ior => 0x80,
+            Instruction::Lor => 0x81,
+            Instruction::Ixor => 0x82,
+            Instruction::Lxor => 0x83,
+            Instruction::Iinc { .. } => 0x84,
+            Instruction::I2l => 0x85,
+            Instruction::I2f => 0x86,
+            Instruction::I2d => 0x87,
+            Instruction::L2i => 0x88,
+            Instruction::L2f => 0x89,
+            Instruction::L2d => 0x8a,
+            Instruction::F2i => 0x8b,
+            Instruction::F2l => 0x8c,
+            Instruction::F2d => 0x8d,
+            Instruction::D2i => 0x8e,
+            Instruction::D2l => 0x8f,
+            Instruction::D2f => 0x90,
+            Instruction::I2b => 0x91,
+            Instruction::I2c => 0x92,
+            Instruction::I2s => 0x93,
+            Instruction::Lcmp => 0x94,
+            Instruction::Fcmpl => 0x95,
+            Instruction::Fcmpg => 0x96,
+            Instruction::Dcmpl => 0x97,
+            Instruction::Dcmpg => 0x98,
+            Instruction::Ifeq(_) => 0x99,
+            Instruction::Ifne(_) => 0x9a,
+            Instruction::Iflt(_) => 0x9b,
+            Instruction::Ifge(_) => 0x9c,
+            Instruction::Ifgt(_) => 0x9d,
+            Instruction::Ifle(_) => 0x9e,
+            Instruction::IfIcmpeq(_) => 0x9f,
+            Instruction::IfIcmpne(_) => 0xa0,
+            Instruction::IfIcmplt(_) => 0xa1,
+            Instruction::IfIcmpge(_) => 0xa2,
+            Instruction::IfIcmpgt(_) => 0xa3,
+            Instruction::IfIcmple(_) => 0xa4,
+            Instruction::IfAcmpeq(_) => 0xa5,
+            Instruction::IfAcmpne(_) => 0xa6,
+            Instruction::Goto(_) => 0xa7,
+            Instruction::Jsr(_) => 0xa8,
+            Instruction::Ret(_) => 0xa9,
+            Instruction::Tableswitch { .. } => 0xaa,
+            Instruction::Lookupswitch { .. } => 0xab,
+            Instruction::Ireturn => 0xac,
+            Instruction::Lreturn => 0xad,
+            Instruction::Freturn => 0xae,
+            Instruction::Dreturn => 0xaf,
+            Instruction::Areturn => 0xb0,
+            Instruction::Return => 0xb1,
+            Instruction::Getstatic(_) => 0xb2,
+            Instruction::Putstatic(_) => 0xb3,
+            Instruction::Getfield(_) => 0xb4,
+            Instruction::Putfield(_) => 0xb5,
+            Instruction::Invokevirtual(_) => 0xb6,
+            Instruction::Invokespecial(_) => 0xb7,
+            Instruction::Invokestatic(_) => 0xb8,
+            Instruction::Invokeinterface { .. } => 0xb9,
+            Instruction::Invokedynamic(_) => 0xba,
+            Instruction::New(_) => 0xbb,
+            Instruction::Newarray(_) => 0xbc,
+            Instruction::Anewarray(_) => 0xbd,
+            Instruction::Arraylength => 0xbe,
+            Instruction::Athrow => 0xbf,
+            Instruction::Checkcast(_) => 0xc0,
+            Instruction::Instanceof(_) => 0xc1,
+            Instruction::Monitorenter => 0xc2,
+            Instruction::Monitorexit => 0xc3,
+            Instruction::Multianewarray { .. } => 0xc5,
+            Instruction::Ifnull(_) => 0xc6,
+            Instruction::Ifnonnull(_) => 0xc7,
+            Instruction::GotoW(_) => 0xc8,
+            Instruction::JsrW(_) => 0xc9,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Nop => "nop",
+            Instruction::AconstNull => "aconst_null",
+            Instruction::IconstM1 => "iconst_m1",
+            Instruction::Iconst0 => "iconst_0",
+            Instruction::Iconst1 => "iconst_1",
+            Instruction::Iconst2 => "iconst_2",
+            Instruction::Iconst3 => "iconst_3",
+            Instruction::Iconst4 => "iconst_4",
+            Instruction::Iconst5 => "iconst_5",
+            Instruction::Lconst0 => "lconst_0",
+            Instruction::Lconst1 => "lconst_1",
+            Instruction::Fconst0 => "fconst_0",
+            Instruction::Fconst1 => "fconst_1",
+            Instruction::Fconst2 => "fconst_2",
+            Instruction::Dconst0 => "dconst_0",
+            Instruction::Dconst1 => "dconst_1",
+            Instruction::Bipush(_) => "bipush",
+            Instruction::Sipush(_) => "sipush",
+            Instruction::Ldc(_) => "ldc",
+            Instruction::LdcW(_) => "ldc_w",
+            Instruction::Ldc2W(_) => "ldc2_w",
+            Instruction::Iload(_) => "iload",
+            Instruction::Lload(_) => "lload",
+            Instruction::Fload(_) => "fload",
+            Instruction::Dload(_) => "dload",
+            Instruction::Aload(_) => "aload",
+            Instruction::Iload0 => "iload_0",
+            Instruction::Iload1 => "iload_1",
+            Instruction::Iload2 => "iload_2",
+            Instruction::Iload3 => "iload_3",
+            Instruction::Lload0 => "lload_0",
+            Instruction::Lload1 => "lload_1",
+            Instruction::Lload2 => "lload_2",
+            Instruction::Lload3 => "lload_3",
+            Instruction::Fload0 => "fload_0",
+            Instruction::Fload1 => "fload_1",
+            Instruction::Fload2 => "fload_2",
+            Instruction::Fload3 => "fload_3",
+            Instruction::Dload0 => "dload_0",
+            Instruction::Dload1 => "dload_1",
+            Instruction::Dload2 => "dload_2",
+            Instruction::Dload3 => "dload_3",
+            Instruction::Aload0 => "aload_0",
+            Instruction::Aload1 => "aload_1",
+            Instruction::Aload2 => "aload_2",
+            Instruction::Aload3 => "aload_3",
+            Instruction::Iaload => "iaload",
+            Instruction::Laload => "laload",
+            Instruction::Faload => "faload",
+            Instruction::Daload => "daload",
+            Instruction::Aaload => "aaload",
+            Instruction::Baload => "baload",
+            Instruction::Caload => "caload",
+            Instruction::Saload => "saload",
+            Instruction::Istore(_) => "istore",
+            Instruction::Lstore(_) => "lstore",
+            Instruction::Fstore(_) => "fstore",
+            Instruction::Dstore(_) => "dstore",
+            Instruction::Astore(_) => "astore",
+            Instruction::Istore0 => "istore_0",
+            Instruction::Istore1 => "istore_1",
+            Instruction::Istore2 => "istore_2",
+            Instruction::Istore3 => "istore_3",
+            Instruction::Lstore0 => "lstore_0",
+            Instruction::Lstore1 => "lstore_1",
+            Instruction::Lstore2 => "lstore_2",
+            Instruction::Lstore3 => "lstore_3",
+            Instruction::Fstore0 => "fstore_0",
+            Instruction::Fstore1 => "fstore_1",
+            Instruction::Fstore2 => "fstore_2",
+            Instruction::Fstore3 => "fstore_3",
+            Instruction::Dstore0 => "dstore_0",
+            Instruction::Dstore1 => "dstore_1",
+            Instruction::Dstore2 => "dstore_2",
+            Instruction::Dstore3 => "dstore_3",
+            Instruction::Astore0 => "astore_0",
+            Instruction::Astore1 => "astore_1",
+            Instruction::Astore2 => "astore_2",
+            Instruction::Astore3 => "astore_3",
+            Instruction::Iastore => "iastore",
+            Instruction::Lastore => "lastore",
+            Instruction::Fastore => "fastore",
+            Instruction::Dastore => "dastore",
+            Instruction::Aastore => "aastore",
+            Instruction::Bastore => "bastore",
+            Instruction::Castore => "castore",
+            Instruction::Sastore => "sastore",
+            Instruction::Pop => "pop",
+            Instruction::Pop2 => "pop2",
+            Instruction::Dup => "dup",
+            Instruction::DupX1 => "dup_x1",
+            Instruction::DupX2 => "dup_x2",
+            Instruction::Dup2 => "dup2",
+            Instruction::Dup2X1 => "dup2_x1",
+            Instruction::Dup2X2 => "dup2_x2",
+            Instruction::Swap => "swap",
+            Instruction::Iadd => "iadd",
+            Instruction::Ladd => "ladd",
+            Instruction::Fadd => "fadd",
+            Instruction::Dadd => "dadd",
+            Instruction::Isub => "isub",
+            Instruction::Lsub => "lsub",
+            Instruction::Fsub => "fsub",
+            Instruction::Dsub => "dsub",
+            Instruction::Imul => "imul",
+            Instruction::Lmul => "lmul",
+            Instruction::Fmul => "fmul",
+            Instruction::Dmul => "dmul",
+            Instruction::Idiv => "idiv",
+            Instruction::Ldiv => "ldiv",
+            Instruction::Fdiv => "fdiv",
+            Instruction::Ddiv => "ddiv",
+            Instruction::Irem => "irem",
+            Instruction::Lrem => "lrem",
+            Instruction::Frem => "frem",
+            Instruction::Drem => "drem",
+            Instruction::Ineg => "ineg",
+            Instruction::Lneg => "lneg",
+            Instruction::Fneg => "fneg",
+            Instruction::Dneg => "dneg",
+            Instruction::Ishl => "ishl",
+            Instruction::Lshl => "lshl",
+            Instruction::Ishr => "ishr",
+            Instruction::Lshr => "lshr",
+            Instruction::Iushr => "iushr",
+            Instruction::Lushr => "lushr",
+            Instruction::Iand => "iand",
+            Instruction::Land => "land",
+            Instruction::Ior => "ior",
+            Instruction::Lor => "lor",
+            Instruction::Ixor => "ixor",
+            Instruction::Lxor => "lxor",
+            Instruction::Iinc { .. } => "iinc",
+            Instruction::I2l => "i2l",
+            Instruction::I2f => "i2f",
+            Instruction::I2d => "i2d",
+            Instruction::L2i => "l2i",
+            Instruction::L2f => "l2f",
+            Instruction::L2d => "l2d",
+            Instruction::F2i => "f2i",
+            Instruction::F2l => "f2l",
+            Instruction::F2d => "f2d",
+            Instruction::D2i => "d2i",
+            Instruction::D2l => "d2l",
+            Instruction::D2f => "d2f",
+            Instruction::I2b => "i2b",
+            Instruction::I2c => "i2c",
+            Instruction::I2s => "i2s",
+            Instruction::Lcmp => "lcmp",
+            Instruction::Fcmpl => "fcmpl",
+            Instruction::Fcmpg => "fcmpg",
+            Instruction::Dcmpl => "dcmpl",
+            Instruction::Dcmpg => "dcmpg",
+            Instruction::Ifeq(_) => "ifeq",
+            Instruction::Ifne(_) => "ifne",
+            Instruction::Iflt(_) => "iflt",
+            Instruction::Ifge(_) => "ifge",
+            Instruction::Ifgt(_) => "ifgt",
+            Instruction::Ifle(_) => "ifle",
+            Instruction::IfIcmpeq(_) => "if_icmpeq",
+            Instruction::IfIcmpne(_) => "if_icmpne",
+            Instruction::IfIcmplt(_) => "if_icmplt",
+            Instruction::IfIcmpge(_) => "if_icmpge",
+            Instruction::IfIcmpgt(_) => "if_icmpgt",
+            Instruction::IfIcmple(_) => "if_icmple",
+            Instruction::IfAcmpeq(_) => "if_acmpeq",
+            Instruction::IfAcmpne(_) => "if_acmpne",
+            Instruction::Goto(_) => "goto",
+            Instruction::Jsr(_) => "jsr",
+            Instruction::Ret(_) => "ret",
+            Instruction::Tableswitch { .. } => "tableswitch",
+            Instruction::Lookupswitch { .. } => "lookupswitch",
+            Instruction::Ireturn => "ireturn",
+            Instruction::Lreturn => "lreturn",
+            Instruction::Freturn => "freturn",
+            Instruction::Dreturn => "dreturn",
+            Instruction::Areturn => "areturn",
+            Instruction::Return => "return",
+            Instruction::Getstatic(_) => "getstatic",
+            Instruction::Putstatic(_) => "putstatic",
+            Instruction::Getfield(_) => "getfield",
+            Instruction::Putfield(_) => "putfield",
+            Instruction::Invokevirtual(_) => "invokevirtual",
+            Instruction::Invokespecial(_) => "invokespecial",
+            Instruction::Invokestatic(_) => "invokestatic",
+            Instruction::Invokeinterface { .. } => "invokeinterface",
+            Instruction::Invokedynamic(_) => "invokedynamic",
+            Instruction::New(_) => "new",
+            Instruction::Newarray(_) => "newarray",
+            Instruction::Anewarray(_) => "anewarray",
+            Instruction::Arraylength => "arraylength",
+            Instruction::Athrow => "athrow",
+            Instruction::Checkcast(_) => "checkcast",
+            Instruction::Instanceof(_) => "instanceof",
+            Instruction::Monitorenter => "monitorenter",
+            Instruction::Monitorexit => "monitorexit",
+            Instruction::Multianewarray { .. } => "multianewarray",
+            Instruction::Ifnull(_) => "ifnull",
+            Instruction::Ifnonnull(_) => "ifnonnull",
+            Instruction::GotoW(_) => "goto_w",
+            Instruction::JsrW(_) => "jsr_w",
+        }
+    }
+}
+
+/// Iterator over the instructions of a code array, yielding each instruction together with its pc.
+///
+/// Errors report the offset into the code array as `at_byte`. Iteration stops after the first error.
+pub struct Instructions<'a> {
+    code: &'a [u8],
+    constant_pool: &'a ConstantPool,
+    index: usize,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub fn new(code: &'a [u8], constant_pool: &'a ConstantPool) -> Instructions<'a> {
+        Instructions { code, constant_pool, index: 0, failed: false }
+    }
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<(u16, Instruction), ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.index >= self.code.len() {
+            return None;
+        }
+        let pc = self.index;
+        let result = if pc > u16::MAX as usize {
+            Err(ParsingError::new(pc, ParsingErrorKind::CodeTooLong { length: self.code.len() }))
+        } else {
+            read_instruction(self.code, &mut self.index, self.constant_pool).map(|instruction| (pc as u16, instruction))
+        };
+        if result.is_err() {
+            self.failed = true;
+        }
+        Some(result)
+    }
+}
+
+/// Decodes a whole code array
+pub fn read_instructions(code: &[u8], constant_pool: &ConstantPool) -> Result<Vec<(u16, Instruction)>, ParsingError> {
+    Instructions::new(code, constant_pool).collect()
+}
+
+fn read_instruction(code: &[u8], index: &mut usize, constant_pool: &ConstantPool) -> Result<Instruction, ParsingError> {
+    let pc = *index;
+    let opcode = read_u1(code, index)?;
+    let instruction = match opcode {
+        0x00 => Instruction::Nop,
+        0x01 => Instruction::AconstNull,
+        0x02 => Instruction::IconstM1,
+        0x03 => Instruction::Iconst0,
+        0x04 => Instruction::Iconst1,
+        0x05 => Instruction::Iconst2,
+        0x06 => Instruction::Iconst3,
+        0x07 => Instruction::Iconst4,
+        0x08 => Instruction::Iconst5,
+        0x09 => Instruction::Lconst0,
+        0x0a => Instruction::Lconst1,
+        0x0b => Instruction::Fconst0,
+        0x0c => Instruction::Fconst1,
+        0x0d => Instruction::Fconst2,
+        0x0e => Instruction::Dconst0,
+        0x0f => Instruction::Dconst1,
+        0x10 => Instruction::Bipush(read_u1(code, index)? as i8),
+        0x11 => Instruction::Sipush(read_u2(code, index)? as i16),
+        0x12 => Instruction::Ldc(read_constant(code, index, constant_pool, false)?),
+        0x13 => Instruction::LdcW(read_constant(code, index, constant_pool, true)?),
+        0x14 => Instruction::Ldc2W(read_constant(code, index, constant_pool, true)?),
+        0x15 => Instruction::Iload(read_u1(code, index)? as u16),
+        0x16 => Instruction::Lload(read_u1(code, index)? as u16),
+        0x17 => Instruction::Fload(read_u1(code, index)? as u16),
+        0x18 => Instruction::Dload(read_u1(code, index)? as u16),
+        0x19 => Instruction::Aload(read_u1(code, index)? as u16),
+        0x1a => Instruction::Iload0,
+        0x1b => Instruction::Iload1,
+        0x1c => Instruction::Iload2,
+        0x1d => Instruction::Iload3,
+        0x1e => Instruction::Lload0,
+        0x1f => Instruction::Lload1,
+        0x20 => Instruction::Lload2,
+        0x21 => Instruction::Lload3,
+        0x22 => Instruction::Fload0,
+        0x23 => Instruction::Fload1,
+        0x24 => Instruction::Fload2,
+        0x25 => Instruction::Fload3,
+        0x26 => Instruction::Dload0,
+        0x27 => Instruction::Dload1,
+        0x28 => Instruction::Dload2,
+        0x29 => Instruction::Dload3,
+        0x2a => Instruction::Aload0,
+        0x2b => Instruction::Aload1,
+        0x2c => Instruction::Aload2,
+        0x2d => Instruction::Aload3,
+        0x2e => Instruction::Iaload,
+        0x2f => Instruction::Laload,
+        0x30 => Instruction::Faload,
+        0x31 => Instruction::Daload,
+        0x32 => Instruction::Aaload,
+        0x33 => Instruction::Baload,
+        0x34 => Instruction::Caload,
+        0x35 => Instruction::Saload,
+        0x36 => Instruction::Istore(read_u1(code, index)? as u16),
+        0x37 => Instruction::Lstore(read_u1(code, index)? as u16),
+        0x38 => Instruction::Fstore(read_u1(code, index)? as u16),
+        0x39 => Instruction::Dstore(read_u1(code, index)? as u16),
+        0x3a => Instruction::Astore(read_u1(code, index)? as u16),
+        0x3b => Instruction::Istore0,
+        0x3c => Instruction::Istore1,
+        0x3d => Instruction::Istore2,
+        0x3e => Instruction::Istore3,
+        0x3f => Instruction::Lstore0,
+        0x40 => Instruction::Lstore1,
+        0x41 => Instruction::Lstore2,
+        0x42 => Instruction::Lstore3,
+        0x43 => Instruction::Fstore0,
+        0x44 => Instruction::Fstore1,
+        0x45 => Instruction::Fstore2,
+        0x46 => Instruction::Fstore3,
+        0x47 => Instruction::Dstore0,
+        0x48 => Instruction::Dstore1,
+        0x49 => Instruction::Dstore2,
+        0x4a => Instruction::Dstore3,
+        0x4b => Instruction::Astore0,
+        0x4c => Instruction::Astore1,
+        0x4d => Instruction::Astore2,
+        0x4e => Instruction::Astore3,
+        0x4f => Instruction::Iastore,
+        0x50 => Instruction::Lastore,
+        0x51 => Instruction::Fastore,
+        0x52 => Instruction::Dastore,
+        0x53 => Instruction::Aastore,
+        0x54 => Instruction::Bastore,
+        0x55 => Instruction::Castore,
+        0x56 => Instruction::Sastore,
+        0x57 => Instruction::Pop,
+        0x58 => Instruction::Pop2,
+        0x59 => Instruction::Dup,
+        0x5a => Instruction::DupX1,
+        0x5b => Instruction::DupX2,
+        0x5c => Instruction::Dup2,
+        0x5d => Instruction::Dup2X1,
+        0x5e => Instruction::Dup2X2,
+        0x5f => Instruction::Swap,
+        0x60 => Instruction::Iadd,
+        0x61 => Instruction::Ladd,
+        0x62 => Instruction::Fadd,
+        0x63 => Instruction::Dadd,
+        0x64 => Instruction::Isub,
+        0x65 => Instruction::Lsub,
+        0x66 => Instruction::Fsub,
+        0x67 => Instruction::Dsub,
+        0x68 => Instruction::Imul,
+        0x69 => Instruction::Lmul,
+        0x6a => Instruction::Fmul,
+        0x6b => Instruction::Dmul,
+        0x6c => Instruction::Idiv,
+        0x6d => Instruction::Ldiv,
+        0x6e => Instruction::Fdiv,
+        0x6f => Instruction::Ddiv,
+        0x70 => Instruction::Irem,
+        0x71 => Instruction::Lrem,
+        0x72 => Instruction::Frem,
+        0x73 => Instruction::Drem,
+        0x74 => Instruction::Ineg,
+        0x75 => Instruction::Lneg,
+        0x76 => Instruction::Fneg,
+        0x77 => Instruction::Dneg,
+        0x78 => Instruction::Ishl,
+        0x79 => Instruction::Lshl,
+        0x7a => Instruction::Ishr,
+        0x7b => Instruction::Lshr,
+        0x7c => Instruction::Iushr,
+        0x7d => Instruction::Lushr,
+        0x7e => Instruction::Iand,
+        0x7f => Instruction::Land,
+        0x80 => Instruction::Ior,
+        0x81 => Instruction::Lor,
+        0x82 => Instruction::Ixor,
+        0x83 => Instruction::Lxor,
+        0x84 => Instruction::Iinc { index: read_u1(code, index)? as u16, value: read_u1(code, index)? as i8 as i16 },
+        0x85 => Instruction::I2l,
+        0x86 => Instruction::I2f,
+        0x87 => Instruction::I2d,
+        0x88 => Instruction::L2i,
+        0x89 => Instruction::L2f,
+        0x8a => Instruction::L2d,
+        0x8b => Instruction::F2i,
+        0x8c => Instruction::F2l,
+        0x8d => Instruction::F2d,
+        0x8e => Instruction::D2i,
+        0x8f => Instruction::D2l,
+        0x90 => Instruction::D2f,
+        0x91 => Instruction::I2b,
+        0x92 => Instruction::I2c,
+        0x93 => Instruction::I2s,
+        0x94 => Instruction::Lcmp,
+        0x95 => Instruction::Fcmpl,
+        0x96 => Instruction::Fcmpg,
+        0x97 => Instruction::Dcmpl,
+        0x98 => Instruction::Dcmpg,
+        0x99 => Instruction::Ifeq(read_branch_target(code, index, pc)?),
+        0x9a => Instruction::Ifne(read_branch_target(code, index, pc)?),
+        0x9b => Instruction::Iflt(read_branch_target(code, index, pc)?),
+        0x9c => Instruction::Ifge(read_branch_target(code, index, pc)?),
+        0x9d => Instruction::Ifgt(read_branch_target(code, index, pc)?),
+        0x9e => Instruction::Ifle(read_branch_target(code, index, pc)?),
+        0x9f => Instruction::IfIcmpeq(read_branch_target(code, index, pc)?),
+        0xa0 => Instruction::IfIcmpne(read_branch_target(code, index, pc)?),
+        0xa1 => Instruction::IfIcmplt(read_branch_target(code, index, pc)?),
+        0xa2 => Instruction::IfIcmpge(read_branch_target(code, index, pc)?),
+        0xa3 => Instruction::IfIcmpgt(read_branch_target(code, index, pc)?),
+        0xa4 => Instruction::IfIcmple(read_branch_target(code, index, pc)?),
+        0xa5 => Instruction::IfAcmpeq(read_branch_target(code, index, pc)?),
+        0xa6 => Instruction::IfAcmpne(read_branch_target(code, index, pc)?),
+        0xa7 => Instruction::Goto(read_branch_target(code, index, pc)?),
+        0xa8 => Instruction::Jsr(read_branch_target(code, index, pc)?),
+        0xa9 => Instruction::Ret(read_u1(code, index)? as u16),
+        0xaa => read_tableswitch(code, index, pc)?,
+        0xab => read_lookupswitch(code, index, pc)?,
+        0xac => Instruction::Ireturn,
+        0xad => Instruction::Lreturn,
+        0xae => Instruction::Freturn,
+        0xaf => Instruction::Dreturn,
+        0xb0 => Instruction::Areturn,
+        0xb1 => Instruction::Return,
+        0xb2 => Instruction::Getstatic(read_member_ref(code, index, constant_pool)?),
+        0xb3 => Instruction::Putstatic(read_member_ref(code, index, constant_pool)?),
+        0xb4 => Instruction::Getfield(read_member_ref(code, index, constant_pool)?),
+        0xb5 => Instruction::Putfield(read_member_ref(code, index, constant_pool)?),
+        0xb6 => Instruction::Invokevirtual(read_member_ref(code, index, constant_pool)?),
+        0xb7 => Instruction::Invokespecial(read_member_ref(code, index, constant_pool)?),
+        0xb8 => Instruction::Invokestatic(read_member_ref(code, index, constant_pool)?),
+        0xb9 => {
+            let method = read_member_ref(code, index, constant_pool)?;
+            let count = read_u1(code, index)?;
+            read_u1(code, index)?;
+            Instruction::Invokeinterface { method, count }
+        },
+        0xba => {
+            let at_byte = *index;
+            let dynamic_index = read_u2(code, index)?;
+            read_u2(code, index)?;
+            Instruction::Invokedynamic(read_dynamic_from_constant_pool(constant_pool, dynamic_index, at_byte)?)
+        },
+        0xbb => Instruction::New(read_class(code, index, constant_pool)?),
+        0xbc => Instruction::Newarray(read_array_type(code, index)?),
+        0xbd => Instruction::Anewarray(read_class(code, index, constant_pool)?),
+        0xbe => Instruction::Arraylength,
+        0xbf => Instruction::Athrow,
+        0xc0 => Instruction::Checkcast(read_class(code, index, constant_pool)?),
+        0xc1 => Instruction::Instanceof(read_class(code, index, constant_pool)?),
+        0xc2 => Instruction::Monitorenter,
+        0xc3 => Instruction::Monitorexit,
+        0xc5 => Instruction::Multianewarray { class: read_class(code, index, constant_pool)?, dimensions: read_u1(code, index)? },
+        0xc6 => Instruction::Ifnull(read_branch_target(code, index, pc)?),
+        0xc7 => Instruction::Ifnonnull(read_branch_target(code, index, pc)?),
+        0xc8 => Instruction::GotoW(read_wide_branch_target(code, index, pc)?),
+        0xc9 => Instruction::JsrW(read_wide_branch_target(code, index, pc)?),
+        0xc4 => read_wide_instruction(code, index)?,
+        _ => return Err(ParsingError::new(pc, ParsingErrorKind::UnknownOpcode { opcode }))
+    };
+    Ok(instruction)
+}
+
+fn read_wide_instruction(code: &[u8], index: &mut usize) -> Result<Instruction, ParsingError> {
+    let at_byte = *index;
+    let opcode = read_u1(code, index)?;
+    let instruction = match opcode {
+        0x15 => Instruction::Iload(read_u2(code, index)?),
+        0x16 => Instruction::Lload(read_u2(code, index)?),
+        0x17 => Instruction::Fload(read_u2(code, index)?),
+        0x18 => Instruction::Dload(read_u2(code, index)?),
+        0x19 => Instruction::Aload(read_u2(code, index)?),
+        0x36 => Instruction::Istore(read_u2(code, index)?),
+        0x37 => Instruction::Lstore(read_u2(code, index)?),
+        0x38 => Instruction::Fstore(read_u2(code, index)?),
+        0x39 => Instruction::Dstore(read_u2(code, index)?),
+        0x3a => Instruction::Astore(read_u2(code, index)?),
+        0xa9 => Instruction::Ret(read_u2(code, index)?),
+        0x84 => Instruction::Iinc { index: read_u2(code, index)?, value: read_u2(code, index)? as i16 },
+        _ => return Err(ParsingError::new(at_byte, ParsingErrorKind::UnknownOpcode { opcode }))
+    };
+    Ok(instruction)
+}
+
+fn branch_target(pc: usize, offset: i32, at_byte: usize) -> Result<u16, ParsingError> {
+    let target = pc as i64 + offset as i64;
+    if target < 0 || target > u16::MAX as i64 {
+        Err(ParsingError::new(at_byte, ParsingErrorKind::InvalidBranchTarget { offset }))
+    } else {
+        Ok(target as u16)
+    }
+}
+
+fn read_branch_target(code: &[u8], index: &mut usize, pc: usize) -> Result<u16, ParsingError> {
+    let at_byte = *index;
+    let offset = read_u2(code, index)? as i16;
+    branch_target(pc, offset as i32, at_byte)
+}
+
+fn read_wide_branch_target(code: &[u8], index: &mut usize, pc: usize) -> Result<u16, ParsingError> {
+    let at_byte = *index;
+    let offset = read_u4(code, index)? as i32;
+    branch_target(pc, offset, at_byte)
+}
+
+/// Skips the padding that aligns the operands of a switch to a multiple of four bytes from the start of the code
+fn skip_switch_padding(code: &[u8], index: &mut usize) -> Result<(), ParsingError> {
+    while *index % 4 != 0 {
+        read_u1(code, index)?;
+    }
+    Ok(())
+}
+
+fn read_tableswitch(code: &[u8], index: &mut usize, pc: usize) -> Result<Instruction, ParsingError> {
+    skip_switch_padding(code, index)?;
+    let default = read_wide_branch_target(code, index, pc)?;
+    let at_byte = *index;
+    let low = read_u4(code, index)? as i32;
+    let high = read_u4(code, index)? as i32;
+    if low > high {
+        return Err(ParsingError::new(at_byte, ParsingErrorKind::InvalidSwitch));
+    }
+    let count = (high as i64 - low as i64 + 1) as usize;
+    if count > (code.len() - *index) / 4 {
+        return Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: count * 4 }));
+    }
+    let mut targets: Vec<u16> = Vec::with_capacity(count);
+    for _ in 0..count {
+        targets.push(read_wide_branch_target(code, index, pc)?);
+    }
+    Ok(Instruction::Tableswitch { default, low, high, targets })
+}
+
+fn read_lookupswitch(code: &[u8], index: &mut usize, pc: usize) -> Result<Instruction, ParsingError> {
+    skip_switch_padding(code, index)?;
+    let default = read_wide_branch_target(code, index, pc)?;
+    let at_byte = *index;
+    let npairs = read_u4(code, index)? as i32;
+    if npairs < 0 {
+        return Err(ParsingError::new(at_byte, ParsingErrorKind::InvalidSwitch));
+    }
+    let count = npairs as usize;
+    if count > (code.len() - *index) / 8 {
+        return Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: count * 8 }));
+    }
+    let mut pairs: Vec<(i32, u16)> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let key = read_u4(code, index)? as i32;
+        pairs.push((key, read_wide_branch_target(code, index, pc)?));
+    }
+    Ok(Instruction::Lookupswitch { default, pairs })
+}
+
+fn read_constant(code: &[u8], index: &mut usize, constant_pool: &ConstantPool, wide: bool) -> Result<Constant, ParsingError> {
+    let at_byte = *index;
+    let constant_index = if wide { read_u2(code, index)? } else { read_u1(code, index)? as u16 };
+    read_constant_from_constant_pool(constant_pool, constant_index, at_byte)
+}
+
+fn read_member_ref(code: &[u8], index: &mut usize, constant_pool: &ConstantPool) -> Result<MemberRef, ParsingError> {
+    let at_byte = *index;
+    let member_index = read_u2(code, index)?;
+    read_member_ref_from_constant_pool(constant_pool, member_index, at_byte)
+}
+
+fn read_class(code: &[u8], index: &mut usize, constant_pool: &ConstantPool) -> Result<Class, ParsingError> {
+    let at_byte = *index;
+    let class_index = read_u2(code, index)?;
+    read_class_from_constant_pool(constant_pool, class_index, at_byte)
+}
+
+fn read_array_type(code: &[u8], index: &mut usize) -> Result<ArrayType, ParsingError> {
+    let at_byte = *index;
+    let atype = read_u1(code, index)?;
+    match atype {
+        4 => Ok(ArrayType::Boolean),
+        5 => Ok(ArrayType::Char),
+        6 => Ok(ArrayType::Float),
+        7 => Ok(ArrayType::Double),
+        8 => Ok(ArrayType::Byte),
+        9 => Ok(ArrayType::Short),
+        10 => Ok(ArrayType::Int),
+        11 => Ok(ArrayType::Long),
+        _ => Err(ParsingError::new(at_byte, ParsingErrorKind::UnknownArrayType { atype }))
+    }
+}
+
+/// Whether a constant takes up two operand stack entries, which only `ldc2_w` can load
+fn is_category_2(constant: &Constant) -> bool {
+    match constant {
+        Constant::Long(_) | Constant::Double(_) => true,
+        Constant::Dynamic(dynamic) => matches!(dynamic.descriptor.as_str(), "J" | "D"),
+        _ => false,
+    }
+}
+
+/// Encodes a whole code array, the inverse of [`read_instructions`]. Each instruction is placed
+/// right after the previous one, so branch targets have to match that layout.
+pub fn write_instructions(instructions: &[Instruction], constant_pool: &mut ConstantPoolBuilder) -> Result<Vec<u8>, WritingError> {
+    let mut code: Vec<u8> = Vec::new();
+    for instruction in instructions {
+        let pc = u16::try_from(code.len()).map_err(|_| WritingError::new(WritingErrorKind::TooLarge { length: code.len(), max: u16::MAX as usize }))?;
+        write_instruction(&mut code, pc, instruction, constant_pool)?;
+    }
+    Ok(code)
+}
+
+/// Encodes a single instruction that starts at `pc`. Its constant pool operands are added to
+/// `constant_pool`. Local variable indices above 255 get a `wide` prefix, and `ldc` becomes `ldc_w`
+/// if its constant has an index above 255. Long and Double constants, including Dynamic ones, are
+/// always loaded with `ldc2_w`, and other constants never are.
+pub fn write_instruction(code: &mut Vec<u8>, pc: u16, instruction: &Instruction, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    let opcode = instruction.opcode();
+    match instruction {
+        Instruction::Bipush(value) => {
+            write_u1(code, opcode);
+            write_u1(code, *value as u8);
+        }
+        Instruction::Sipush(value) => {
+            write_u1(code, opcode);
+            write_u2(code, *value as u16);
+        }
+        Instruction::Ldc(constant) | Instruction::LdcW(constant) | Instruction::Ldc2W(constant) => {
+            let constant_index = constant_pool.constant(constant)?;
+            let narrow_index = u8::try_from(constant_index).ok().filter(|_| !matches!(instruction, Instruction::LdcW(_)));
+            if is_category_2(constant) {
+                write_u1(code, 0x14);
+                write_u2(code, constant_index);
+            } else if let Some(constant_index) = narrow_index {
+                write_u1(code, 0x12);
+                write_u1(code, constant_index);
+            } else {
+                write_u1(code, 0x13);
+                write_u2(code, constant_index);
+            }
+        }
+        Instruction::Iload(variable) | Instruction::Lload(variable) | Instruction::Fload(variable) |
+        Instruction::Dload(variable) | Instruction::Aload(variable) | Instruction::Istore(variable) |
+        Instruction::Lstore(variable) | Instruction::Fstore(variable) | Instruction::Dstore(variable) |
+        Instruction::Astore(variable) | Instruction::Ret(variable) => {
+            match u8::try_from(*variable) {
+                Ok(variable) => {
+                    write_u1(code, opcode);
+                    write_u1(code, variable);
+                }
+                Err(_) => {
+                    write_u1(code, 0xc4);
+                    write_u1(code, opcode);
+                    write_u2(code, *variable);
+                }
+            }
+        }
+        Instruction::Iinc { index, value } => {
+            match (u8::try_from(*index), i8::try_from(*value)) {
+                (Ok(index), Ok(value)) => {
+                    write_u1(code, opcode);
+                    write_u1(code, index);
+                    write_u1(code, value as u8);
+                }
+                _ => {
+                    write_u1(code, 0xc4);
+                    write_u1(code, opcode);
+                    write_u2(code, *index);
+                    write_u2(code, *value as u16);
+                }
+            }
+        }
+        Instruction::Ifeq(target) | Instruction::Ifne(target) | Instruction::Iflt(target) |
+        Instruction::Ifge(target) | Instruction::Ifgt(target) | Instruction::Ifle(target) |
+        Instruction::IfIcmpeq(target) | Instruction::IfIcmpne(target) | Instruction::IfIcmplt(target) |
+        Instruction::IfIcmpge(target) | Instruction::IfIcmpgt(target) | Instruction::IfIcmple(target) |
+        Instruction::IfAcmpeq(target) | Instruction::IfAcmpne(target) | Instruction::Goto(target) |
+        Instruction::Jsr(target) | Instruction::Ifnull(target) | Instruction::Ifnonnull(target) => {
+            let offset = i16::try_from(wide_branch_offset(pc, *target))
+                .map_err(|_| WritingError::new(WritingErrorKind::BranchOutOfRange { pc, target: *target }))?;
+            write_u1(code, opcode);
+            write_u2(code, offset as u16);
+        }
+        Instruction::GotoW(target) | Instruction::JsrW(target) => {
+            write_u1(code, opcode);
+            write_u4(code, wide_branch_offset(pc, *target) as u32);
+        }
+        Instruction::Tableswitch { default, low, high, targets } => {
+            if high < low || targets.len() as i64 != *high as i64 - *low as i64 + 1 {
+                return Err(WritingError::new(WritingErrorKind::InvalidInstruction { mnemonic: instruction.mnemonic() }));
+            }
+            write_u1(code, opcode);
+            write_switch_padding(code, pc);
+            write_u4(code, wide_branch_offset(pc, *default) as u32);
+            write_u4(code, *low as u32);
+            write_u4(code, *high as u32);
+            for target in targets {
+                write_u4(code, wide_branch_offset(pc, *target) as u32);
+            }
+        }
+        Instruction::Lookupswitch { default, pairs } => {
+            write_u1(code, opcode);
+            write_switch_padding(code, pc);
+            write_u4(code, wide_branch_offset(pc, *default) as u32);
+            write_u4(code, pairs.len() as u32);
+            for (key, target) in pairs {
+                write_u4(code, *key as u32);
+                write_u4(code, wide_branch_offset(pc, *target) as u32);
+            }
+        }
+        Instruction::Getstatic(member_ref) | Instruction::Putstatic(member_ref) | Instruction::Getfield(member_ref) |
+        Instruction::Putfield(member_ref) | Instruction::Invokevirtual(member_ref) | Instruction::Invokespecial(member_ref) |
+        Instruction::Invokestatic(member_ref) => {
+            write_u1(code, opcode);
+            write_u2(code, constant_pool.member_ref(member_ref)?);
+        }
+        Instruction::Invokeinterface { method, count } => {
+            write_u1(code, opcode);
+            write_u2(code, constant_pool.member_ref(method)?);
+            write_u1(code, *count);
+            write_u1(code, 0);
+        }
+        Instruction::Invokedynamic(dynamic) => {
+            write_u1(code, opcode);
+            write_u2(code, constant_pool.invoke_dynamic(dynamic)?);
+            write_u2(code, 0);
+        }
+        Instruction::New(class) | Instruction::Anewarray(class) | Instruction::Checkcast(class) | Instruction::Instanceof(class) => {
+            write_u1(code, opcode);
+            write_u2(code, constant_pool.class(&class.name)?);
+        }
+        Instruction::Newarray(array_type) => {
+            write_u1(code, opcode);
+            write_u1(code, *array_type as u8);
+        }
+        Instruction::Multianewarray { class, dimensions } => {
+            write_u1(code, opcode);
+            write_u2(code, constant_pool.class(&class.name)?);
+            write_u1(code, *dimensions);
+        }
+        // All other instructions consist of just their opcode
+        _ => write_u1(code, opcode),
+    }
+    Ok(())
+}
+
+fn wide_branch_offset(pc: u16, target: u16) -> i32 {
+    target as i32 - pc as i32
+}
+
+/// Pads the code after the opcode of a switch at `pc`, like [`skip_switch_padding`] skips it
+fn write_switch_padding(code: &mut Vec<u8>, pc: u16) {
+    for _ in 0..switch_padding(pc) {
+        write_u1(code, 0);
+    }
+}
+
+/// The number of padding bytes after the opcode of a switch at `pc`
+pub(crate) fn switch_padding(pc: u16) -> usize {
+    (4 - (pc as usize + 1) % 4) % 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ConstantPoolEntry, DynamicRef};
+
+    fn decode(code: &[u8]) -> Result<Vec<(u16, Instruction)>, ParsingError> {
+        read_instructions(code, &Vec::new())
+    }
+
+    #[test]
+    fn switch_padding_at_every_alignment() {
+        for pc in 0..4u16 {
+            let padding = switch_padding(pc);
+            assert_eq!((pc as usize + 1 + padding) % 4, 0);
+
+            // tableswitch 1 to 2 jumping to the return after it
+            let end = pc as i32 + 1 + padding as i32 + 20;
+            let mut code: Vec<u8> = vec![0x00; pc as usize];
+            code.push(0xaa);
+            code.resize(code.len() + padding, 0);
+            for value in [end - pc as i32, 1, 2, end - pc as i32, end - pc as i32] {
+                code.extend_from_slice(&value.to_be_bytes());
+            }
+            code.push(0xb1);
+            let instructions = decode(&code).unwrap();
+            assert_eq!(instructions[pc as usize], (pc, Instruction::Tableswitch { default: end as u16, low: 1, high: 2, targets: vec![end as u16; 2] }));
+            assert_eq!(instructions.last(), Some(&(end as u16, Instruction::Return)));
+
+            // lookupswitch with the keys 5 and 7
+            let end = pc as i32 + 1 + padding as i32 + 24;
+            let mut code: Vec<u8> = vec![0x00; pc as usize];
+            code.push(0xab);
+            code.resize(code.len() + padding, 0);
+            for value in [end - pc as i32, 2, 5, end - pc as i32, 7, -(pc as i32)] {
+                code.extend_from_slice(&value.to_be_bytes());
+            }
+            code.push(0xb1);
+            let instructions = decode(&code).unwrap();
+            assert_eq!(instructions[pc as usize], (pc, Instruction::Lookupswitch { default: end as u16, pairs: vec![(5, end as u16), (7, 0)] }));
+            assert_eq!(instructions.last(), Some(&(end as u16, Instruction::Return)));
+        }
+    }
+
+    #[test]
+    fn wide_instructions() {
+        let wide_forms = [
+            (0x15, Instruction::Iload as fn(u16) -> Instruction), (0x16, Instruction::Lload), (0x17, Instruction::Fload),
+            (0x18, Instruction::Dload), (0x19, Instruction::Aload), (0x36, Instruction::Istore),
+            (0x37, Instruction::Lstore), (0x38, Instruction::Fstore), (0x39, Instruction::Dstore),
+            (0x3a, Instruction::Astore), (0xa9, Instruction::Ret),
+        ];
+        for (opcode, instruction) in wide_forms {
+            assert_eq!(decode(&[0xc4, opcode, 0x01, 0x02, 0xb1]).unwrap(), vec![(0, instruction(0x0102)), (4, Instruction::Return)]);
+            // The short form decodes to the same variant
+            assert_eq!(decode(&[opcode, 0x05]).unwrap(), vec![(0, instruction(5))]);
+        }
+        assert_eq!(decode(&[0xc4, 0x84, 0x01, 0x02, 0xff, 0xfe, 0xb1]).unwrap(), vec![
+            (0, Instruction::Iinc { index: 0x0102, value: -2 }),
+            (6, Instruction::Return),
+        ]);
+        assert_eq!(decode(&[0x84, 0x01, 0xff]).unwrap(), vec![(0, Instruction::Iinc { index: 1, value: -1 })]);
+
+        let error = decode(&[0xc4, 0x60, 0x00, 0x00]).unwrap_err();
+        assert_eq!((error.at_byte, error.kind), (1, ParsingErrorKind::UnknownOpcode { opcode: 0x60 }));
+    }
+
+    #[test]
+    fn truncated_operands() {
+        // sipush, wide iinc, tableswitch 0 to 0, goto_w, return
+        let code: Vec<u8> = vec![
+            0x11, 0x01, 0x02,
+            0xc4, 0x84, 0x00, 0x01, 0x00, 0x01,
+            0xaa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18,
+            0xc8, 0x00, 0x00, 0x00, 0x05,
+            0xb1,
+        ];
+        let boundaries = [0, 3, 9, 28, 33, 34];
+        assert_eq!(decode(&code).unwrap().len(), 5);
+        for length in 0..code.len() {
+            let result = decode(&code[..length]);
+            if boundaries.contains(&length) {
+                assert!(result.is_ok(), "{} bytes", length);
+            } else {
+                assert!(matches!(result.unwrap_err().kind, ParsingErrorKind::UnexpectedEndOfInput { .. }), "{} bytes", length);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_opcodes() {
+        for opcode in [0xcb, 0xfe, 0xff] {
+            let error = decode(&[0x00, opcode, 0x00]).unwrap_err();
+            assert_eq!((error.at_byte, error.kind), (1, ParsingErrorKind::UnknownOpcode { opcode }));
+        }
+        // Decoding stops at the first error
+        let results: Vec<_> = Instructions::new(&[0xcb, 0x00], &Vec::new()).collect();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn constant_pool_operands() {
+        let constant_pool = vec![ConstantPoolEntry::IntegerInfo { value: 7 }];
+        assert_eq!(read_instructions(&[0x12, 0x01], &constant_pool).unwrap(), vec![(0, Instruction::Ldc(Constant::Integer(7)))]);
+        let error = read_instructions(&[0x12, 0x02], &constant_pool).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::InvalidConstantPoolIndex { index: 2 });
+    }
+
+    #[test]
+    fn ldc_forms() {
+        let dynamic = |descriptor: &str| Constant::Dynamic(DynamicRef { bootstrap_method_attr_index: 0, name: "x".to_string(), descriptor: descriptor.to_string() });
+        let instructions = vec![
+            Instruction::Ldc(Constant::Integer(1)),
+            Instruction::LdcW(Constant::Integer(1)),
+            Instruction::Ldc2W(Constant::String("s".to_string())),
+            Instruction::Ldc(Constant::Long(2)),
+            Instruction::LdcW(Constant::Double(3.0)),
+            Instruction::Ldc(dynamic("J")),
+            Instruction::Ldc2W(dynamic("Ljava/lang/Object;")),
+        ];
+        let mut constant_pool = ConstantPoolBuilder::new();
+        let code = write_instructions(&instructions, &mut constant_pool).unwrap();
+        let opcodes: Vec<u8> = read_instructions(&code, constant_pool.constant_pool()).unwrap().iter().map(|(_, instruction)| instruction.opcode()).collect();
+        assert_eq!(opcodes, [0x12, 0x13, 0x12, 0x14, 0x14, 0x14, 0x12]);
+
+        // Constants past index 255 need the wide form
+        let mut constant_pool = ConstantPoolBuilder::new();
+        for value in 0..300 {
+            constant_pool.integer(value).unwrap();
+        }
+        let code = write_instructions(&[Instruction::Ldc(Constant::Integer(299)), Instruction::Ldc2W(Constant::Float(1.0))], &mut constant_pool).unwrap();
+        assert_eq!(code, [0x13, 0x01, 0x2C, 0x13, 0x01, 0x2D]);
+    }
+}
@@ -1,14 +1,13 @@
-use std::fs;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 
 
-pub fn read_bytes_from_file(filename: &String) -> Vec<u8> {
-    let mut f = File::open(filename).expect("Could not read file");
-    let metadata = fs::metadata(filename).expect("Could not read metadata");
-    let size = metadata.len() as usize;
+pub fn read_bytes_from_file<P: AsRef<Path>>(filename: P) -> std::io::Result<Vec<u8>> {
+    let mut f = File::open(filename)?;
+    let size = f.metadata()?.len() as usize;
     let mut buffer = vec![0; size];
-    f.read_exact(&mut buffer).expect("Buffer overflow");
+    f.read_exact(&mut buffer)?;
 
-    buffer
-}
\ No newline at end of file
+    Ok(buffer)
+}
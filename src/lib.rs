@@ -0,0 +1,43 @@
+//! Parser for JVM class files.
+//!
+//! The [`reader`] module turns the bytes of a `.class` file into the structures defined in
+//! [`types`]. For most uses [`parse_class`] and [`parse_class_from_path`] are all that is needed.
+//! The [`writer`] module turns a [`ClassFile`] back into bytes.
+
+use std::path::Path;
+
+pub mod analysis;
+pub mod assembler;
+pub mod constant_pool;
+pub mod descriptor;
+pub mod disassembler;
+pub mod instruction;
+pub mod io;
+pub mod mutf8;
+pub mod reader;
+pub mod signature;
+pub mod smap;
+pub mod types;
+pub mod writer;
+
+pub use crate::assembler::CodeBuilder;
+pub use crate::constant_pool::ConstantPoolBuilder;
+pub use crate::reader::{read_class_file, AttributeLengthCheck, AttributeRegistry, ReaderOptions};
+pub use crate::types::{ClassFile, ConstantPool, Error, ParsingError, WritingError};
+pub use crate::writer::write_class_file;
+
+/// Parses a class file from its raw bytes.
+pub fn parse_class(data: &[u8]) -> Result<ClassFile, ParsingError> {
+    read_class_file(data)
+}
+
+/// Parses a class file from its raw bytes with the custom attribute decoders and length checks in `options`
+pub fn parse_class_with_options(data: &[u8], options: &ReaderOptions) -> Result<ClassFile, ParsingError> {
+    reader::read_class_file_with_options(data, options)
+}
+
+/// Reads the file at `path` and parses it as a class file.
+pub fn parse_class_from_path<P: AsRef<Path>>(path: P) -> Result<ClassFile, Error> {
+    let data = io::read_bytes_from_file(path)?;
+    Ok(read_class_file(&data)?)
+}
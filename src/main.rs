@@ -1,64 +1,248 @@
 use std::env;
 use std::process::exit;
 
-use crate::io::read_bytes_from_file;
-use crate::reader::*;
-use crate::types::{Attribute, Class, ConstantPool, Field, FieldFlag, Method, MethodFlag, ParsingError};
-
-mod reader;
-mod types;
-
-mod io;
+use bytecode_parser::analysis::check_code_limits;
+use bytecode_parser::descriptor::{FieldType, MethodDescriptor};
+use bytecode_parser::disassembler;
+use bytecode_parser::io::read_bytes_from_file;
+use bytecode_parser::parse_class;
+use bytecode_parser::types::{AccessFlag, Attribute, Class, ClassFile, ConstantPool, Module, Field, FieldFlag, InnerClass, InnerClassFlag, Method, MethodFlag};
 
+struct Options {
+    filename: String,
+    disassemble: bool,
+    check_limits: bool,
+}
 
 fn main() {
-    let filename = parse_args();
-    println!("Analyzing File {}", filename);
+    let Options { filename, disassemble, check_limits } = parse_args();
+    let quiet = disassemble || check_limits;
+    if !quiet {
+        println!("Analyzing File {}", filename);
+    }
 
-    let data = read_bytes_from_file(&filename);
-    println!("size: {} bytes", data.len());
+    let data = match read_bytes_from_file(&filename) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read file {}: {}", filename, err);
+            exit(1);
+        }
+    };
+    if !quiet {
+        println!("size: {} bytes", data.len());
+    }
 
-    let mut constant_pool: ConstantPool = Vec::new();
+    let class_file = parse_class(&data);
 
-    let class_file = read_class_file(&data, &mut constant_pool);
+    let class_file = match class_file {
+        Ok(class_file) => class_file,
+        Err(err) => {
+            eprintln!("Error while parsing class file {}", err);
+            exit(1);
+        }
+    };
 
-    if let Err(ParsingError { at_byte, message }) = class_file {
-        eprintln!("Error while parsing class file at byte {}: {}", at_byte, message);
-        exit(1);
+    if disassemble {
+        match disassembler::disassemble(&class_file) {
+            Ok(output) => print!("{}", output),
+            Err(err) => {
+                eprintln!("Error while disassembling class file {}", err);
+                exit(1);
+            }
+        }
+        return;
     }
 
-    let class_file = class_file.unwrap();
+    if check_limits {
+        print_limit_mismatches(&class_file);
+        return;
+    }
 
     println!("magic: 0x{:X}", class_file.magic);
 
     println!("Class Version {}.{}", class_file.major_version, class_file.minor_version);
 
-    print_constant_pool(class_file.constant_pool);
+    print_constant_pool(&class_file.constant_pool);
 
     println!("access flags: {:?}", class_file.access_flags);
 
-    println!("class name: {}", class_file.this_class.name);
+    if let Some(module) = class_file.module() {
+        print_module(&class_file, module);
+        println!("successfully parsed {} bytes", class_file.parsed_bytes);
+        return;
+    }
+
+    println!("class name: {}", class_file.java_name(&class_file.this_class.name));
 
-    println!("super class name: {}", class_file.super_class.name.replace('/', "."));
+    println!("declaration: {}", class_declaration(&class_file));
+
+    match class_file.signature() {
+        Some(Ok(signature)) => println!("generic signature: {}", signature),
+        Some(Err(err)) => eprintln!("Could not parse signature {}", err),
+        None => {}
+    }
 
-    for attr in class_file.attributes {
-        if let Attribute::SourceFile { source_file } = attr {
-            println!("source file: {}", source_file);
+    match &class_file.super_class {
+        Some(super_class) => println!("super class name: {}", class_file.java_name(&super_class.name)),
+        None => println!("super class name: none"),
+    }
+
+    for attr in &class_file.attributes {
+        match attr {
+            Attribute::NestHost { host_class } => println!("nest host: {}", class_file.java_name(&host_class.name)),
+            Attribute::EnclosingMethod { class, method } => match method {
+                Some(method) => println!("enclosing method: {}.{}{}", class_file.java_name(&class.name), method.name, method.descriptor),
+                None => println!("enclosing class: {}", class_file.java_name(&class.name)),
+            },
+            _ => {}
         }
     }
 
-    print_interfaces(&class_file.interfaces);
+    if let Some(source_file) = class_file.source_file() {
+        println!("source file: {}", source_file);
+    }
+
+    match class_file.smap() {
+        Some(Ok(smap)) => {
+            let strata: Vec<&str> = smap.strata.iter().map(|stratum| stratum.name.as_str()).collect();
+            println!("source map: strata {} (default {})", strata.join(", "), smap.default_stratum);
+        }
+        Some(Err(err)) => eprintln!("Could not decode source map {}", err),
+        None => {}
+    }
+
+    print_interfaces(&class_file, &class_file.interfaces);
+
+    print_inner_classes(&class_file, class_file.inner_classes());
 
     print_fields(&class_file.fields);
 
     print_methods(&class_file.methods);
 
+    print_call_sites(&class_file);
+
     println!("successfully parsed {} bytes", class_file.parsed_bytes);
 }
 
-fn parse_args() -> String {
-    let args: Vec<String> = env::args().collect();
-    args.get(1).expect("Expected 1 argument but got 0").to_owned()
+fn parse_args() -> Options {
+    let mut filename: Option<String> = None;
+    let mut disassemble = false;
+    let mut check_limits = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-c" => disassemble = true,
+            "-m" => check_limits = true,
+            _ if filename.is_none() && !arg.starts_with('-') => filename = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument {}", arg);
+                print_usage_and_exit();
+            }
+        }
+    }
+    match filename {
+        Some(filename) => Options { filename, disassemble, check_limits },
+        None => print_usage_and_exit(),
+    }
+}
+
+fn print_usage_and_exit() -> ! {
+    eprintln!("Usage: bytecode-parser [options] <file>");
+    eprintln!("  -c  Disassemble the code of every method");
+    eprintln!("  -m  Check the max_stack and max_locals every method declares");
+    exit(1);
+}
+
+/// The class header as it would be written in java source, e.g. `public record Point(int x, int y)`
+/// or `public abstract sealed class Shape permits Circle, Square`
+fn class_declaration(class_file: &ClassFile) -> String {
+    let flags = &class_file.access_flags;
+    let record_components = class_file.record_components();
+    let is_interface = flags.contains(&AccessFlag::AccInterface);
+    let mut declaration = String::new();
+    if flags.contains(&AccessFlag::AccPublic) {
+        declaration.push_str("public ");
+    }
+    // records are implicitly final, interfaces implicitly abstract
+    if flags.contains(&AccessFlag::AccAbstract) && !is_interface {
+        declaration.push_str("abstract ");
+    }
+    if flags.contains(&AccessFlag::AccFinal) && record_components.is_none() {
+        declaration.push_str("final ");
+    }
+    if !class_file.permitted_subclasses().is_empty() {
+        declaration.push_str("sealed ");
+    }
+    if flags.contains(&AccessFlag::AccAnnotation) {
+        declaration.push_str("@interface ");
+    } else if is_interface {
+        declaration.push_str("interface ");
+    } else if flags.contains(&AccessFlag::AccEnum) {
+        declaration.push_str("enum ");
+    } else if record_components.is_some() {
+        declaration.push_str("record ");
+    } else {
+        declaration.push_str("class ");
+    }
+    declaration.push_str(&class_file.java_name(&class_file.this_class.name));
+
+    if let Some(components) = record_components {
+        let components: Vec<String> = components.iter()
+            .map(|component| match (component.signature(), component.field_type()) {
+                (Some(Ok(signature)), _) => format!("{:#} {}", signature, component.name),
+                (_, Ok(field_type)) => format!("{:#} {}", field_type, component.name),
+                _ => format!("{} {}", component.descriptor, component.name),
+            })
+            .collect();
+        declaration.push('(');
+        declaration.push_str(&components.join(", "));
+        declaration.push(')');
+    }
+
+    let permitted_subclasses: Vec<String> = class_file.permitted_subclasses().iter()
+        .map(|class| class_file.java_name(&class.name))
+        .collect();
+    if !permitted_subclasses.is_empty() {
+        declaration.push_str(" permits ");
+        declaration.push_str(&permitted_subclasses.join(", "));
+    }
+    declaration
+}
+
+/// Prints the methods whose declared limits differ from the computed ones, and exits with an error
+/// if any of them are too small or can't be analyzed
+fn print_limit_mismatches(class_file: &ClassFile) {
+    let mut failed = false;
+    for mismatch in check_code_limits(class_file) {
+        match mismatch {
+            Ok(mismatch) => {
+                println!("{}{}: declares max_stack {} and max_locals {}, needs {} and {}{}",
+                         mismatch.name, mismatch.descriptor,
+                         mismatch.declared.max_stack, mismatch.declared.max_locals,
+                         mismatch.computed.max_stack, mismatch.computed.max_locals,
+                         if mismatch.is_too_small() { "" } else { " (larger than needed)" });
+                failed |= mismatch.is_too_small();
+            }
+            Err(err) => {
+                eprintln!("Could not analyze code {}", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+fn print_module(class_file: &ClassFile, module: &Module) {
+    if let Some(main_class) = class_file.module_main_class() {
+        println!("main class: {}", main_class.name.replace('/', "."));
+    }
+    let packages = class_file.module_packages();
+    println!("packages ({}):", packages.len());
+    for package in packages {
+        println!("  {}", package.replace('/', "."));
+    }
+    print!("{}", disassembler::module_declaration(module));
 }
 
 fn print_constant_pool(constant_pool: &ConstantPool) {
@@ -68,7 +252,7 @@ fn print_constant_pool(constant_pool: &ConstantPool) {
     }
 }
 
-fn print_fields(fields: &Vec<Field>) {
+fn print_fields(fields: &[Field]) {
     println!("fields ({}):", fields.len());
     for field in fields {
         let mut line = String::from("  ");
@@ -86,20 +270,22 @@ fn print_fields(fields: &Vec<Field>) {
             line.push_str("final ")
         }
 
-        line.push_str(field.type_name().as_str());
+        match (field.signature(), field.field_type()) {
+            (Some(Ok(signature)), _) => line.push_str(format!("{:#}", signature).as_str()),
+            (_, Ok(field_type)) => line.push_str(format!("{:#}", field_type).as_str()),
+            _ => line.push_str(field.descriptor.as_str()),
+        }
         line.push(' ');
         line.push_str(field.name.as_str());
 
-        let constant_value_attr = field.attributes.iter().find(|attr| matches!(attr, Attribute::ConstantValue {value: _}));
-        if let Some(Attribute::ConstantValue { value }) = constant_value_attr {
-            let const_value = value.const_value_as_string();
-            if let Some(value) = const_value {
-                line.push_str(" = ");
-                if field.descriptor == "Z" {
-                    line.push_str(if value == "1" { "true" } else { "false" })
-                } else {
-                    line.push_str(value.as_str());
-                }
+        let constant_value_attr = field.attributes.iter().find(|attr| matches!(attr, Attribute::ConstantValue { .. }));
+        if let Some(Attribute::ConstantValue { value, .. }) = constant_value_attr {
+            let value = value.const_value_as_string();
+            line.push_str(" = ");
+            if field.descriptor == "Z" {
+                line.push_str(if value == "1" { "true" } else { "false" })
+            } else {
+                line.push_str(value.as_str());
             }
         }
 
@@ -107,7 +293,7 @@ fn print_fields(fields: &Vec<Field>) {
     }
 }
 
-fn print_methods(methods: &Vec<Method>) {
+fn print_methods(methods: &[Method]) {
     println!("methods ({}):", methods.len());
     for method in methods {
         let mut line = String::from("  ");
@@ -130,13 +316,54 @@ fn print_methods(methods: &Vec<Method>) {
         if method.access_flags.iter().any(|flag| matches!(flag, MethodFlag::AccSynchronized)) {
             line.push_str("synchronized ")
         }
-        line.push_str(method.descriptor.as_str());
+        let descriptor = method.parsed_descriptor();
+        let mut parameter_type_names: Vec<String> = match &descriptor {
+            Ok(descriptor) => descriptor.parameters.iter().map(|parameter| format!("{:#}", parameter)).collect(),
+            Err(_) => Vec::new(),
+        };
+        // The generic signature leaves out synthetic parameters, so it is only used if it matches the descriptor
+        let signature = method.signature().and_then(Result::ok)
+            .filter(|signature| descriptor.is_ok() && signature.parameters.len() == parameter_type_names.len());
+        if let Some(signature) = &signature {
+            if !signature.type_parameters.is_empty() {
+                let type_parameters: Vec<String> = signature.type_parameters.iter().map(|parameter| format!("{:#}", parameter)).collect();
+                line.push('<');
+                line.push_str(type_parameters.join(", ").as_str());
+                line.push_str("> ");
+            }
+            match &signature.return_type {
+                Some(return_type) => line.push_str(format!("{:#}", return_type).as_str()),
+                None => line.push_str("void"),
+            }
+            parameter_type_names = signature.parameters.iter().map(|parameter| format!("{:#}", parameter)).collect();
+        } else {
+            match &descriptor {
+                Ok(MethodDescriptor { return_type: Some(return_type), .. }) => line.push_str(format!("{:#}", return_type).as_str()),
+                Ok(MethodDescriptor { return_type: None, .. }) => line.push_str("void"),
+                Err(_) => line.push_str(method.descriptor.as_str()),
+            }
+        }
         line.push(' ');
         line.push_str(method.name.as_str());
 
+        let parameters: Vec<String> = parameter_type_names.into_iter()
+            .zip(method.parameter_names())
+            .map(|(type_name, name)| match name {
+                Some(name) => format!("{} {}", type_name, name),
+                None => type_name
+            })
+            .collect();
+        line.push('(');
+        line.push_str(parameters.join(", ").as_str());
+        line.push(')');
+
         let exception_attr = method.attributes.iter().find(|attr| matches!(attr, Attribute::Exceptions {exceptions: _}));
-        if let Some(Attribute::Exceptions { exceptions }) = exception_attr {
-            let exceptions: Vec<String> = exceptions.iter().map(|e| e.name.clone()).collect();
+        if let Some(signature) = signature.filter(|signature| !signature.throws.is_empty()) {
+            let exceptions: Vec<String> = signature.throws.iter().map(|e| format!("{:#}", e)).collect();
+            line.push_str(" throws ");
+            line.push_str(exceptions.join(", ").as_str())
+        } else if let Some(Attribute::Exceptions { exceptions }) = exception_attr {
+            let exceptions: Vec<String> = exceptions.iter().map(|e| format!("{:#}", FieldType::Object(e.name.clone()))).collect();
             if !exceptions.is_empty() {
                 line.push_str(" throws ");
                 line.push_str(exceptions.join(", ").as_str())
@@ -146,9 +373,58 @@ fn print_methods(methods: &Vec<Method>) {
     }
 }
 
-fn print_interfaces(interfaces: &Vec<Class>) {
+fn print_call_sites(class_file: &ClassFile) {
+    let call_sites = match class_file.call_sites() {
+        Ok(call_sites) => call_sites,
+        Err(err) => {
+            eprintln!("Could not resolve call sites {}", err);
+            return;
+        }
+    };
+    println!("call sites ({}):", call_sites.len());
+    for call_site in call_sites {
+        println!("  {}:{}", call_site.name, call_site.descriptor);
+        println!("    bootstrap method: {}", disassembler::format_method_handle(&call_site.bootstrap_method));
+        for argument in &call_site.arguments {
+            println!("    argument: {}", disassembler::format_constant(argument));
+        }
+    }
+}
+
+fn print_interfaces(class_file: &ClassFile, interfaces: &[Class]) {
     println!("implemented interfaces ({}):", interfaces.len());
-    interfaces.iter().map(|class| class.name.replace('/', ".")).for_each(|name| {
+    interfaces.iter().map(|class| class_file.java_name(&class.name)).for_each(|name| {
         println!("  {}", name);
     });
 }
+
+fn print_inner_classes(class_file: &ClassFile, inner_classes: &[InnerClass]) {
+    println!("inner classes ({}):", inner_classes.len());
+    for inner_class in inner_classes {
+        let mut line = String::from("  ");
+        for (flag, modifier) in [
+            (InnerClassFlag::AccPublic, "public "),
+            (InnerClassFlag::AccPrivate, "private "),
+            (InnerClassFlag::AccProtected, "protected "),
+            (InnerClassFlag::AccStatic, "static "),
+            (InnerClassFlag::AccFinal, "final "),
+            (InnerClassFlag::AccAbstract, "abstract "),
+        ] {
+            if inner_class.access_flags.contains(&flag) {
+                line.push_str(modifier)
+            }
+        }
+        match (&inner_class.outer_class, &inner_class.inner_name) {
+            (Some(_), Some(_)) => line.push_str(class_file.java_name(&inner_class.inner_class.name).as_str()),
+            (None, Some(inner_name)) => {
+                line.push_str(inner_name);
+                line.push_str(" (local class)");
+            }
+            _ => {
+                line.push_str(inner_class.inner_class.name.replace('/', ".").as_str());
+                line.push_str(" (anonymous class)");
+            }
+        }
+        println!("{line}");
+    }
+}
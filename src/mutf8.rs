@@ -0,0 +1,131 @@
+//! The JVM stores strings in "modified UTF-8": NUL is encoded as the two bytes `C0 80` and
+//! supplementary characters are encoded as a surrogate pair of two three byte sequences instead of a
+//! single four byte sequence.
+
+/// Decodes modified UTF-8.
+///
+/// Returns `None` if the bytes are malformed, contain a surrogate without its partner, or are not
+/// in the canonical form [`encode`] produces. In all of these cases `encode(&decode_lossy(bytes))`
+/// wouldn't give back the original bytes.
+pub fn decode(bytes: &[u8]) -> Option<String> {
+    let string = decode_lossy(bytes);
+    if encode(&string) == bytes {
+        Some(string)
+    } else {
+        None
+    }
+}
+
+/// Decodes modified UTF-8, replacing anything that isn't representable with U+FFFD.
+pub fn decode_lossy(bytes: &[u8]) -> String {
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let continuation = |offset: usize| bytes.get(index + offset).filter(|b| *b & 0xC0 == 0x80).map(|b| (*b & 0x3F) as u16);
+        if byte & 0x80 == 0 {
+            units.push(byte as u16);
+            index += 1;
+        } else if byte & 0xE0 == 0xC0 {
+            match continuation(1) {
+                Some(low) => {
+                    units.push(((byte & 0x1F) as u16) << 6 | low);
+                    index += 2;
+                }
+                None => {
+                    units.push(0xFFFD);
+                    index += 1;
+                }
+            }
+        } else if byte & 0xF0 == 0xE0 {
+            match (continuation(1), continuation(2)) {
+                (Some(middle), Some(low)) => {
+                    units.push(((byte & 0x0F) as u16) << 12 | middle << 6 | low);
+                    index += 3;
+                }
+                _ => {
+                    units.push(0xFFFD);
+                    index += 1;
+                }
+            }
+        } else {
+            units.push(0xFFFD);
+            index += 1;
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Encodes a string as modified UTF-8.
+pub fn encode(string: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(string.len());
+    for unit in string.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_class_file;
+    use crate::types::ConstantPoolEntry;
+    use crate::writer::write_class_file;
+
+    #[test]
+    fn nul() {
+        assert_eq!(decode(&[b'a', 0xC0, 0x80]), Some("a\0".to_string()));
+        assert_eq!(encode("a\0"), [b'a', 0xC0, 0x80]);
+        // A plain NUL byte isn't valid modified UTF-8
+        assert_eq!(decode(&[0]), None);
+    }
+
+    #[test]
+    fn supplementary_characters() {
+        let surrogate_pair = [0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80];
+        assert_eq!(encode("\u{1F600}"), surrogate_pair);
+        assert_eq!(decode(&surrogate_pair), Some("\u{1F600}".to_string()));
+        // The four byte form of standard UTF-8 isn't used
+        assert_eq!(decode("\u{1F600}".as_bytes()), None);
+    }
+
+    #[test]
+    fn malformed() {
+        // A lone surrogate
+        assert_eq!(decode(&[0xED, 0xA0, 0x80]), None);
+        assert_eq!(decode_lossy(&[0xED, 0xA0, 0x80]), "\u{FFFD}");
+        // An overlong form of `A`
+        assert_eq!(decode(&[0xC1, 0x81]), None);
+        // Truncated sequences
+        assert_eq!(decode(&[b'a', 0xE2, 0x82]), None);
+        assert_eq!(decode(&[0xC3]), None);
+        assert_eq!(decode_lossy(&[b'a', 0xE2, 0x82]), "a\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn malformed_strings_are_read_and_written_back() {
+        let raw: Vec<u8> = vec![b'A', 0xED, 0xA0, 0x80, b'.', 0xC1, 0x81];
+        let mut class: Vec<u8> = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 5];
+        class.extend_from_slice(&[1, 0, 1, b'A', 7, 0, 1, 1, 0, 10]);
+        class.extend_from_slice(b"SourceFile");
+        class.extend_from_slice(&[1, 0, raw.len() as u8]);
+        class.extend_from_slice(&raw);
+        class.extend_from_slice(&[0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 3, 0, 0, 0, 2, 0, 4]);
+
+        let class_file = read_class_file(&class).unwrap();
+        assert_eq!(class_file.constant_pool[3], ConstantPoolEntry::Utf8Info { value: decode_lossy(&raw), raw: Some(raw.clone()) });
+        assert_eq!(class_file.source_file(), Some("A\u{FFFD}.A"));
+        assert_eq!(write_class_file(&class_file).unwrap(), class);
+    }
+}
@@ -1,25 +1,94 @@
-use crate::types::{AccessFlag, Annotation, Attribute, Class, ClassFile, ConstantPool, ConstantPoolEntry, ElementValue, ElementValuePair, ExceptionHandler, Field, FieldFlag, LineNumber, Method, MethodFlag, ParsingError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::constant_pool::ConstantPoolReferences;
+use crate::mutf8;
+use crate::types::{AccessFlag, Annotation, Attribute, AttributeValue, BootstrapMethod, Class, ClassFile, Constant, ConstantPool, ConstantPoolEntry, ConstantValue, CustomValue, DynamicRef, ElementValue, ElementValuePair, ExceptionHandler, Exports, ExportsFlag, Field, FieldFlag, InnerClass, InnerClassFlag, LineNumber, LocalVariable, LocalVariableTarget, LocalVariableType, MemberRef, MemberRefKind, Method, MethodFlag, MethodHandle, MethodParameter, MethodParameterFlag, Module, ModuleFlag, Opens, Provides, NameAndType, ParsingError, ParsingErrorKind, RecordComponent, ReferenceKind, Requires, RequiresFlag, StackMapFrame, TargetInfo, TargetType, TypeAnnotation, TypePathEntry, TypePathKind, VerificationType};
+
+/// Settings that change how class files are read
+#[derive(Debug, Default)]
+pub struct ReaderOptions {
+    pub attribute_registry: AttributeRegistry,
+    pub attribute_length_check: AttributeLengthCheck,
+}
+
+/// How the length declared in the header of an attribute is checked against its contents
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AttributeLengthCheck {
+    /// Fail with [`ParsingErrorKind::AttributeLengthMismatch`] if decoding the attribute doesn't take
+    /// up exactly the declared length
+    #[default]
+    Strict,
+    /// Always continue after the declared length. Attributes that can't be decoded within it, or that
+    /// don't take up all of it, are kept as [`Attribute::Unknown`].
+    Lenient,
+}
+
+type AttributeDecoder = dyn Fn(&[u8], &ConstantPool) -> Result<CustomValue, ParsingError> + Send + Sync;
+
+/// Decoders for attributes the parser doesn't know itself, e.g. vendor specific ones or Scala's
+/// `ScalaSig`. Attributes without a decoder are kept as [`Attribute::Unknown`].
+#[derive(Default)]
+pub struct AttributeRegistry {
+    decoders: HashMap<String, Box<AttributeDecoder>>,
+}
+
+impl AttributeRegistry {
+    pub fn new() -> AttributeRegistry {
+        AttributeRegistry::default()
+    }
+
+    /// Decodes attributes called `name` with `decoder`, which gets the contents of the attribute
+    /// without its header. The result is stored in an [`Attribute::Custom`]. Decoders for attributes
+    /// the parser decodes itself are never called.
+    pub fn register<T, F>(&mut self, name: &str, decoder: F)
+    where
+        T: AttributeValue,
+        F: Fn(&[u8], &ConstantPool) -> Result<T, ParsingError> + Send + Sync + 'static,
+    {
+        self.decoders.insert(name.to_string(), Box::new(move |data, constant_pool| decoder(data, constant_pool).map(|value| CustomValue::new(data.to_vec(), value))));
+    }
+}
+
+impl fmt::Debug for AttributeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+pub fn read_class_file(data: &[u8]) -> Result<ClassFile, ParsingError> {
+    read_class_file_with_options(data, &ReaderOptions::default())
+}
 
-pub fn read_class_file<'a>(data: &Vec<u8>, constant_pool: &'a mut ConstantPool) -> Result<ClassFile<'a>, ParsingError> {
+/// Like [`read_class_file`], but with custom attribute decoders and length checks
+pub fn read_class_file_with_options(data: &[u8], options: &ReaderOptions) -> Result<ClassFile, ParsingError> {
     let mut index: usize = 0;
     let magic = read_u4(data, &mut index)?;
+    if magic != 0xCAFEBABE {
+        return Err(ParsingError::new(0, ParsingErrorKind::InvalidMagic { magic }));
+    }
     let minor_version = read_u2(data, &mut index)?;
     let major_version = read_u2(data, &mut index)?;
-    read_constant_pool(data, &mut index, constant_pool)?;
+    let mut constant_pool: ConstantPool = Vec::new();
+    read_constant_pool(data, &mut index, &mut constant_pool)?;
+    let tracked_pool = TrackedPool::new(&constant_pool);
     let access_flags = read_access_flags(data, &mut index)?;
-    let this_class = read_class(data, &mut index, constant_pool)?;
-    let super_class = read_class(data, &mut index, constant_pool)?;
-    let interfaces = read_interfaces(data, &mut index, constant_pool)?;
-    let fields = read_fields(data, &mut index, constant_pool)?;
-    let methods = read_methods(data, &mut index, constant_pool)?;
-    let attributes = read_attributes(data, &mut index, constant_pool)?;
-
+    let this_class = read_class(data, &mut index, &tracked_pool).map_err(|e| e.with_context("this_class"))?;
+    let super_class = read_optional_class(data, &mut index, &tracked_pool).map_err(|e| e.with_context("super_class"))?;
+    let interfaces = read_interfaces(data, &mut index, &tracked_pool)?;
+    let fields = read_fields(data, &mut index, &tracked_pool, options)?;
+    let methods = read_methods(data, &mut index, &tracked_pool, options)?;
+    let attributes = read_attributes(data, &mut index, &tracked_pool, options, 0)?;
+    let constant_pool_references = tracked_pool.into_references();
 
     Ok(ClassFile {
         magic,
         minor_version,
         major_version,
         constant_pool,
+        constant_pool_references,
         access_flags,
         this_class,
         super_class,
@@ -31,18 +100,73 @@ pub fn read_class_file<'a>(data: &Vec<u8>, constant_pool: &'a mut ConstantPool)
     })
 }
 
+/// The constant pool of the class being read. It records the index of every reference that is
+/// resolved through it, along with the part of the class it is in, for
+/// [`ClassFile::constant_pool_references`].
+struct TrackedPool<'a> {
+    constant_pool: &'a ConstantPool,
+    /// The part of the class being read, in the form of the keys of [`ConstantPoolReferences`]
+    path: RefCell<String>,
+    references: RefCell<Vec<(String, u16)>>,
+}
+
+impl<'a> TrackedPool<'a> {
+    fn new(constant_pool: &'a ConstantPool) -> TrackedPool<'a> {
+        TrackedPool { constant_pool, path: RefCell::new(String::new()), references: RefCell::new(Vec::new()) }
+    }
+
+    fn record(&self, index: u16) {
+        self.references.borrow_mut().push((self.path.borrow().clone(), index));
+    }
+
+    /// Runs `read` for a nested part of the class, which is called whatever `key` returns for the
+    /// value it read, e.g. `fields[x:I]`. Its references are renamed once the key is known.
+    fn in_part<T>(&self, read: impl FnOnce() -> Result<T, ParsingError>, key: impl FnOnce(&T) -> String) -> Result<T, ParsingError> {
+        let length = self.path.borrow().len();
+        let first_reference = self.references.borrow().len();
+        let placeholder = if length > 0 { "/?" } else { "?" };
+        self.path.borrow_mut().push_str(placeholder);
+        let result = read();
+        self.path.borrow_mut().truncate(length);
+
+        let value = result?;
+        let key = key(&value);
+        let key = if length > 0 { format!("/{}", key) } else { key };
+        for (path, _) in &mut self.references.borrow_mut()[first_reference..] {
+            path.replace_range(length..length + placeholder.len(), &key);
+        }
+        Ok(value)
+    }
+
+    fn into_references(self) -> ConstantPoolReferences {
+        let mut references = ConstantPoolReferences::default();
+        for (path, index) in self.references.into_inner() {
+            references.indices.entry(path).or_default().push(index);
+        }
+        references
+    }
+}
+
+impl Deref for TrackedPool<'_> {
+    type Target = ConstantPool;
+
+    fn deref(&self) -> &ConstantPool {
+        self.constant_pool
+    }
+}
 
-fn read_constant_pool(buffer: &Vec<u8>, index: &mut usize, constant_pool: &mut ConstantPool) -> Result<(), ParsingError> {
+fn read_constant_pool(buffer: &[u8], index: &mut usize, constant_pool: &mut ConstantPool) -> Result<(), ParsingError> {
     let constant_pool_count = read_u2(buffer, index)?;
 
 
     let mut should_put_empty = false;
-    for _i in 0..constant_pool_count - 1 {
+    for _i in 0..constant_pool_count.saturating_sub(1) {
         if should_put_empty {
             constant_pool.push(ConstantPoolEntry::Empty);
             should_put_empty = false;
         } else {
-            let entry = read_constant_pool_entry(buffer, index)?;
+            let entry = read_constant_pool_entry(buffer, index)
+                .map_err(|e| e.with_context(format!("constant_pool[{}]", constant_pool.len() + 1)))?;
             if matches!(entry, ConstantPoolEntry::DoubleInfo{value: _}) || matches!(entry, ConstantPoolEntry::LongInfo{value: _}) {
                 should_put_empty = true;
             }
@@ -53,54 +177,163 @@ fn read_constant_pool(buffer: &Vec<u8>, index: &mut usize, constant_pool: &mut C
     Ok(())
 }
 
-fn read_interfaces(buffer: &Vec<u8>, index: &mut usize, constant_pool: &ConstantPool) -> Result<Vec<Class>, ParsingError> {
+fn read_interfaces(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<Class>, ParsingError> {
     let interfaces_count = read_u2(buffer, index)? as usize;
     let mut interfaces: Vec<Class> = Vec::with_capacity(interfaces_count);
-    for _ in 0..interfaces_count {
-        let class_index = read_u2(buffer, index)? as usize;
-        if let ConstantPoolEntry::Class { name_index } = constant_pool.get(class_index - 1).expect("Expected Class but went out of bounds") {
-            if let ConstantPoolEntry::Utf8Info { value } = constant_pool.get(*name_index as usize - 1).expect("Expected Utf8 but went out of bounds") {
-                interfaces.push(Class { name: value.to_owned() })
-            } else {
-                return Err(ParsingError::new(*index, "Expected Class Name"));
-            }
-        } else {
-            return Err(ParsingError::new(*index, "Expected Class Info"));
-        }
+    for i in 0..interfaces_count {
+        let interface = constant_pool.in_part(|| read_class(buffer, index, constant_pool), |interface| format!("interfaces[{}]", interface.name))
+            .map_err(|e| e.with_context(format!("interfaces[{}]", i)))?;
+        interfaces.push(interface);
     }
     Ok(interfaces)
 }
 
-fn read_fields<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Result<Vec<Field<'a>>, ParsingError> {
+fn read_fields(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions) -> Result<Vec<Field>, ParsingError> {
     let fields_count = read_u2(buffer, index)? as usize;
     let mut fields: Vec<Field> = Vec::with_capacity(fields_count);
-    for _ in 0..fields_count {
-        let flag_mask = read_u2(buffer, index)?;
-        let access_flags = parse_field_flags(flag_mask);
+    for i in 0..fields_count {
+        let field = constant_pool.in_part(|| read_field(buffer, index, constant_pool, options), |field| format!("fields[{}:{}]", field.name, field.descriptor))
+            .map_err(|e| e.with_context(format!("fields[{}]", i)))?;
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+fn read_field(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions) -> Result<Field, ParsingError> {
+    let flag_mask = read_u2(buffer, index)?;
+    let access_flags = parse_field_flags(flag_mask);
+
+    let name = read_utf8(buffer, index, constant_pool)?;
+    let descriptor = read_utf8(buffer, index, constant_pool)?;
+
+    let attributes = read_attributes(buffer, index, constant_pool, options, 0)?;
+
+    Ok(Field {
+        access_flags,
+        name,
+        descriptor,
+        attributes,
+    })
+}
+
+pub(crate) fn read_constant_pool_entry_at(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<&ConstantPoolEntry, ParsingError> {
+    (index as usize).checked_sub(1)
+        .and_then(|i| constant_pool.get(i))
+        .filter(|entry| !matches!(entry, ConstantPoolEntry::Empty))
+        .ok_or_else(|| ParsingError::new(at_byte, ParsingErrorKind::InvalidConstantPoolIndex { index }))
+}
+
+pub(crate) fn read_utf8_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<String, ParsingError> {
+    if let ConstantPoolEntry::Utf8Info { value, .. } = read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        Ok(value.to_owned())
+    } else {
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Utf8" }))
+    }
+}
 
-        let name_index = read_u2(buffer, index)?;
-        let name = read_utf8_from_constant_pool(constant_pool, name_index).expect("Expected Utf8");
+/// Reads a constant pool index and resolves the Utf8 entry it points to
+fn read_utf8(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<String, ParsingError> {
+    let at_byte = *index;
+    let utf8_index = read_u2(buffer, index)?;
+    constant_pool.record(utf8_index);
+    read_utf8_from_constant_pool(constant_pool, utf8_index, at_byte)
+}
 
-        let descriptor_index = read_u2(buffer, index)?;
-        let descriptor = read_utf8_from_constant_pool(constant_pool, descriptor_index).expect("Expected Utf8");
+/// Like [`read_utf8`], but an index of 0 means there is no string
+fn read_optional_utf8(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Option<String>, ParsingError> {
+    let at_byte = *index;
+    let utf8_index = read_u2(buffer, index)?;
+    if utf8_index == 0 {
+        Ok(None)
+    } else {
+        constant_pool.record(utf8_index);
+        Ok(Some(read_utf8_from_constant_pool(constant_pool, utf8_index, at_byte)?))
+    }
+}
 
-        let attributes = read_attributes(buffer, index, constant_pool)?;
+/// Resolves a loadable constant. Utf8 entries are accepted as strings because `s` element values
+/// reference them directly instead of going through a String entry.
+pub(crate) fn read_constant_value(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<ConstantValue, ParsingError> {
+    match read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        ConstantPoolEntry::IntegerInfo { value } => Ok(ConstantValue::Integer(*value as i32)),
+        ConstantPoolEntry::FloatInfo { value } => Ok(ConstantValue::Float(*value)),
+        ConstantPoolEntry::LongInfo { value } => Ok(ConstantValue::Long(*value as i64)),
+        ConstantPoolEntry::DoubleInfo { value } => Ok(ConstantValue::Double(*value)),
+        ConstantPoolEntry::StringInfo { string_index } => Ok(ConstantValue::String(read_utf8_from_constant_pool(constant_pool, *string_index, at_byte)?)),
+        ConstantPoolEntry::Utf8Info { value, .. } => Ok(ConstantValue::String(value.to_owned())),
+        _ => Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Constant Value" }))
+    }
+}
 
-        fields.push(Field {
-            access_flags,
-            name,
-            descriptor,
-            attributes,
+pub(crate) fn read_name_and_type_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<NameAndType, ParsingError> {
+    if let ConstantPoolEntry::NameAndTypeInfo { name_index, descriptor_index } = read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        Ok(NameAndType {
+            name: read_utf8_from_constant_pool(constant_pool, *name_index, at_byte)?,
+            descriptor: read_utf8_from_constant_pool(constant_pool, *descriptor_index, at_byte)?,
         })
+    } else {
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "NameAndType" }))
     }
-    Ok(fields)
 }
 
-fn read_utf8_from_constant_pool(constant_pool: &ConstantPool, index: u16) -> Option<String> {
-    if let ConstantPoolEntry::Utf8Info { value } = constant_pool.get(index as usize - 1).expect("Expected Utf8 but went out of bounds") {
-        Some(value.to_owned())
+pub(crate) fn read_member_ref_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<MemberRef, ParsingError> {
+    let (kind, class_index, name_and_type_index) = match read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        ConstantPoolEntry::Fieldref { class_index, name_and_type_index } => (MemberRefKind::Field, class_index, name_and_type_index),
+        ConstantPoolEntry::Methodref { class_index, name_and_type_index } => (MemberRefKind::Method, class_index, name_and_type_index),
+        ConstantPoolEntry::InterfaceMethodref { class_index, name_and_type_index } => (MemberRefKind::InterfaceMethod, class_index, name_and_type_index),
+        _ => return Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Member Reference" }))
+    };
+    let class = read_class_from_constant_pool(constant_pool, *class_index, at_byte)?;
+    let NameAndType { name, descriptor } = read_name_and_type_from_constant_pool(constant_pool, *name_and_type_index, at_byte)?;
+    Ok(MemberRef { kind, class, name, descriptor })
+}
+
+pub(crate) fn read_method_handle_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<MethodHandle, ParsingError> {
+    if let ConstantPoolEntry::MethodHandle { reference_kind, reference_index } = read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        let reference_kind = match reference_kind {
+            1 => ReferenceKind::GetField,
+            2 => ReferenceKind::GetStatic,
+            3 => ReferenceKind::PutField,
+            4 => ReferenceKind::PutStatic,
+            5 => ReferenceKind::InvokeVirtual,
+            6 => ReferenceKind::InvokeStatic,
+            7 => ReferenceKind::InvokeSpecial,
+            8 => ReferenceKind::NewInvokeSpecial,
+            9 => ReferenceKind::InvokeInterface,
+            _ => return Err(ParsingError::new(at_byte, ParsingErrorKind::UnknownReferenceKind { kind: *reference_kind }))
+        };
+        let reference = read_member_ref_from_constant_pool(constant_pool, *reference_index, at_byte)?;
+        Ok(MethodHandle { reference_kind, reference })
     } else {
-        None
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "MethodHandle" }))
+    }
+}
+
+/// Resolves either a Dynamic or an InvokeDynamic entry
+pub(crate) fn read_dynamic_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<DynamicRef, ParsingError> {
+    match read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        ConstantPoolEntry::Dynamic { bootstrap_method_attr_index, name_and_type_index } |
+        ConstantPoolEntry::InvokeDynamicInfo { bootstrap_method_attr_index, name_and_type_index } => {
+            let NameAndType { name, descriptor } = read_name_and_type_from_constant_pool(constant_pool, *name_and_type_index, at_byte)?;
+            Ok(DynamicRef { bootstrap_method_attr_index: *bootstrap_method_attr_index, name, descriptor })
+        }
+        _ => Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Dynamic" }))
+    }
+}
+
+/// Resolves any loadable constant, as referenced by `ldc` instructions and bootstrap method arguments
+pub(crate) fn read_constant_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<Constant, ParsingError> {
+    match read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        ConstantPoolEntry::IntegerInfo { value } => Ok(Constant::Integer(*value as i32)),
+        ConstantPoolEntry::FloatInfo { value } => Ok(Constant::Float(*value)),
+        ConstantPoolEntry::LongInfo { value } => Ok(Constant::Long(*value as i64)),
+        ConstantPoolEntry::DoubleInfo { value } => Ok(Constant::Double(*value)),
+        ConstantPoolEntry::StringInfo { string_index } => Ok(Constant::String(read_utf8_from_constant_pool(constant_pool, *string_index, at_byte)?)),
+        ConstantPoolEntry::Class { .. } => Ok(Constant::Class(read_class_from_constant_pool(constant_pool, index, at_byte)?)),
+        ConstantPoolEntry::MethodTypeInfo { descriptor_index } => Ok(Constant::MethodType(read_utf8_from_constant_pool(constant_pool, *descriptor_index, at_byte)?)),
+        ConstantPoolEntry::MethodHandle { .. } => Ok(Constant::MethodHandle(read_method_handle_from_constant_pool(constant_pool, index, at_byte)?)),
+        ConstantPoolEntry::Dynamic { .. } => Ok(Constant::Dynamic(read_dynamic_from_constant_pool(constant_pool, index, at_byte)?)),
+        _ => Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Loadable Constant" }))
     }
 }
 
@@ -133,34 +366,40 @@ fn parse_field_flags(mask: u16) -> Vec<FieldFlag> {
     if mask & 0x4000 != 0 {
         flags.push(FieldFlag::AccEnum)
     }
+    if mask & !0x50DF != 0 {
+        flags.push(FieldFlag::Reserved(mask & !0x50DF))
+    }
     flags
 }
 
-fn read_methods<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Result<Vec<Method<'a>>, ParsingError> {
+fn read_methods(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions) -> Result<Vec<Method>, ParsingError> {
     let methods_count = read_u2(buffer, index)? as usize;
     let mut methods: Vec<Method> = Vec::with_capacity(methods_count);
 
-    for _ in 0..methods_count {
-        let flag_mask = read_u2(buffer, index)?;
-        let access_flags = parse_method_flags(flag_mask);
+    for i in 0..methods_count {
+        let method = constant_pool.in_part(|| read_method(buffer, index, constant_pool, options), |method| format!("methods[{}:{}]", method.name, method.descriptor))
+            .map_err(|e| e.with_context(format!("methods[{}]", i)))?;
+        methods.push(method);
+    }
 
-        let name_index = read_u2(buffer, index)?;
-        let name = read_utf8_from_constant_pool(constant_pool, name_index).expect("Expected Utf8");
+    Ok(methods)
+}
 
-        let descriptor_index = read_u2(buffer, index)?;
-        let descriptor = read_utf8_from_constant_pool(constant_pool, descriptor_index).expect("Expected Utf8");
+fn read_method(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions) -> Result<Method, ParsingError> {
+    let flag_mask = read_u2(buffer, index)?;
+    let access_flags = parse_method_flags(flag_mask);
 
-        let attributes = read_attributes(buffer, index, constant_pool)?;
+    let name = read_utf8(buffer, index, constant_pool)?;
+    let descriptor = read_utf8(buffer, index, constant_pool)?;
 
-        methods.push(Method {
-            name,
-            descriptor,
-            attributes,
-            access_flags,
-        })
-    }
+    let attributes = read_attributes(buffer, index, constant_pool, options, 0)?;
 
-    Ok(methods)
+    Ok(Method {
+        name,
+        descriptor,
+        attributes,
+        access_flags,
+    })
 }
 
 fn parse_method_flags(mask: u16) -> Vec<MethodFlag> {
@@ -201,126 +440,463 @@ fn parse_method_flags(mask: u16) -> Vec<MethodFlag> {
     if mask & 0x1000 != 0 {
         flags.push(MethodFlag::AccSynthetic)
     }
+    if mask & !0x1DFF != 0 {
+        flags.push(MethodFlag::Reserved(mask & !0x1DFF))
+    }
     flags
 }
 
-fn read_attributes<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Result<Vec<Attribute<'a>>, ParsingError> {
+/// Attributes nest through `Code` and `Record`. Valid classes only go one level deep, the depth is
+/// limited so that malicious input can't overflow the stack.
+const MAX_ATTRIBUTE_DEPTH: usize = 16;
+
+fn read_attributes(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions, depth: usize) -> Result<Vec<Attribute>, ParsingError> {
+    if depth > MAX_ATTRIBUTE_DEPTH {
+        return Err(ParsingError::new(*index, ParsingErrorKind::NestingTooDeep));
+    }
     let attributes_count = read_u2(buffer, index)? as usize;
     let mut attributes: Vec<Attribute> = Vec::with_capacity(attributes_count);
-    for _ in 0..attributes_count {
-        let name_index = read_u2(buffer, index)?;
-        let name = read_utf8_from_constant_pool(constant_pool, name_index).expect("Expected Utf8");
-        let size = read_u4(buffer, index)? as usize;
-
-        let attribute = match name.as_str() {
-            "ConstantValue" => {
-                let constant_value_index = read_u2(buffer, index)? as usize;
-                Attribute::ConstantValue { value: constant_pool.get(constant_value_index - 1).expect("Expected Constant Value") }
+    for i in 0..attributes_count {
+        let attribute = constant_pool.in_part(|| {
+            let name = read_utf8(buffer, index, constant_pool).map_err(|e| e.with_context(format!("attributes[{}]", i)))?;
+            let size = read_u4(buffer, index).map_err(|e| e.with_context(format!("attributes[{}]", name)))?;
+            read_checked_attribute(buffer, index, constant_pool, options, &name, size, depth)
+                .map_err(|e| e.with_context(format!("attributes[{}]", name)))
+        }, |attribute| format!("attributes[{}]", attribute.name()))?;
+        attributes.push(attribute);
+    }
+    Ok(attributes)
+}
+
+/// Reads the body of an attribute and compares the bytes it took up with the `size` from its header,
+/// as configured by [`ReaderOptions::attribute_length_check`]
+fn read_checked_attribute(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions, name: &str, size: u32, depth: usize) -> Result<Attribute, ParsingError> {
+    let start = *index;
+    match options.attribute_length_check {
+        AttributeLengthCheck::Strict => {
+            let attribute = read_attribute(buffer, index, constant_pool, options, name, size as usize, depth)?;
+            let actual = *index - start;
+            if actual != size as usize {
+                return Err(ParsingError::new(start, ParsingErrorKind::AttributeLengthMismatch { declared: size, actual }));
+            }
+            Ok(attribute)
+        }
+        AttributeLengthCheck::Lenient => {
+            let data = read_bytes(buffer, index, size as usize)?;
+            // The body can't be read past its declared end, whatever it contains
+            let mut body_index = start;
+            let references = constant_pool.references.borrow().len();
+            match read_attribute(&buffer[..*index], &mut body_index, constant_pool, options, name, size as usize, depth) {
+                Ok(attribute) if body_index == *index => Ok(attribute),
+                // Bytes the attribute doesn't account for would be lost when writing it back
+                _ => {
+                    // An unknown attribute is written back as is, without references
+                    constant_pool.references.borrow_mut().truncate(references);
+                    Ok(Attribute::Unknown { name: name.to_string(), data })
+                }
             }
+        }
+    }
+}
 
-            "Synthetic" => Attribute::Synthetic,
+/// Reads the body of the attribute `name`. `depth` is the number of attributes it is nested in.
+fn read_attribute(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions, name: &str, size: usize, depth: usize) -> Result<Attribute, ParsingError> {
+    let attribute = match name {
+        "ConstantValue" => {
+            let at_byte = *index;
+            let constant_value_index = read_u2(buffer, index)?;
+            let value = read_constant_value(constant_pool, constant_value_index, at_byte)?;
+            Attribute::ConstantValue { index: constant_value_index, value }
+        }
 
-            "Signature" => {
-                let signature_index = read_u2(buffer, index)?;
-                let signature = read_utf8_from_constant_pool(constant_pool, signature_index).expect("Expected Utf8");
-                Attribute::Signature { signature }
-            }
+        "Synthetic" => Attribute::Synthetic,
 
-            "Deprecated" => Attribute::Deprecated,
+        "Signature" => {
+            Attribute::Signature { signature: read_utf8(buffer, index, constant_pool)? }
+        }
 
-            "RuntimeVisibleAnnotations" => {
-                Attribute::RuntimeVisibleAnnotations { annotations: read_annotations(buffer, index, constant_pool) }
-            }
+        "Deprecated" => Attribute::Deprecated,
 
-            "RuntimeInvisibleAnnotations" => {
-                Attribute::RuntimeInvisibleAnnotations { annotations: read_annotations(buffer, index, constant_pool) }
-            }
+        "RuntimeVisibleAnnotations" => {
+            Attribute::RuntimeVisibleAnnotations { annotations: read_annotations(buffer, index, constant_pool)? }
+        }
 
-            "Code" => {
-                let max_stack = read_u2(buffer, index)?;
-                let max_locals = read_u2(buffer, index)?;
+        "RuntimeInvisibleAnnotations" => {
+            Attribute::RuntimeInvisibleAnnotations { annotations: read_annotations(buffer, index, constant_pool)? }
+        }
 
-                let code_length = read_u4(buffer, index)?;
-                let mut code: Vec<u8> = Vec::new();
-                for _ in 0..code_length {
-                    code.push(read_u1(buffer, index)?)
-                }
-                let exception_table = read_exception_table(buffer, index, constant_pool);
-                let attributes = read_attributes(buffer, index, constant_pool)?;
-
-                Attribute::Code {
-                    max_stack,
-                    max_locals,
-                    code,
-                    exception_table,
-                    attributes,
-                }
-            }
+        "RuntimeVisibleTypeAnnotations" => {
+            Attribute::RuntimeVisibleTypeAnnotations { annotations: read_type_annotations(buffer, index, constant_pool)? }
+        }
 
-            "Exceptions" => {
-                Attribute::Exceptions { exceptions: read_exceptions(buffer, index, constant_pool) }
-            }
+        "RuntimeInvisibleTypeAnnotations" => {
+            Attribute::RuntimeInvisibleTypeAnnotations { annotations: read_type_annotations(buffer, index, constant_pool)? }
+        }
 
-            "RuntimeVisibleParameterAnnotations" => {
-                Attribute::RuntimeVisibleParameterAnnotations { annotations: read_parameter_annotations(buffer, index, constant_pool) }
+        "Code" => {
+            let max_stack = read_u2(buffer, index)?;
+            let max_locals = read_u2(buffer, index)?;
+
+            let code_length = read_u4(buffer, index)? as usize;
+            let code = read_bytes(buffer, index, code_length)?;
+            let exception_table = read_exception_table(buffer, index, constant_pool)?;
+            let attributes = read_attributes(buffer, index, constant_pool, options, depth + 1)?;
+
+            Attribute::Code {
+                max_stack,
+                max_locals,
+                code,
+                exception_table,
+                attributes,
             }
+        }
 
-            "RuntimeInvisibleParameterAnnotations" => {
-                Attribute::RuntimeInvisibleParameterAnnotations { annotations: read_parameter_annotations(buffer, index, constant_pool) }
-            }
+        "Exceptions" => {
+            Attribute::Exceptions { exceptions: read_exceptions(buffer, index, constant_pool)? }
+        }
 
-            "AnnotationDefault" => {
-                Attribute::AnnotationDefault { default_value: read_element_value(buffer, index, constant_pool) }
-            }
+        "RuntimeVisibleParameterAnnotations" => {
+            Attribute::RuntimeVisibleParameterAnnotations { annotations: read_parameter_annotations(buffer, index, constant_pool)? }
+        }
 
-            "LineNumberTable" => {
-                Attribute::LineNumberTable { line_number_table: read_line_number_table(buffer, index) }
-            }
+        "RuntimeInvisibleParameterAnnotations" => {
+            Attribute::RuntimeInvisibleParameterAnnotations { annotations: read_parameter_annotations(buffer, index, constant_pool)? }
+        }
+
+        "AnnotationDefault" => {
+            Attribute::AnnotationDefault { default_value: read_element_value(buffer, index, constant_pool, 0)? }
+        }
+
+        "LineNumberTable" => {
+            Attribute::LineNumberTable { line_number_table: read_line_number_table(buffer, index)? }
+        }
+
+        "LocalVariableTable" => {
+            Attribute::LocalVariableTable { local_variable_table: read_local_variable_table(buffer, index, constant_pool)? }
+        }
+
+        "LocalVariableTypeTable" => {
+            Attribute::LocalVariableTypeTable { local_variable_type_table: read_local_variable_type_table(buffer, index, constant_pool)? }
+        }
+
+        "SourceFile" => {
+            Attribute::SourceFile { source_file: read_utf8(buffer, index, constant_pool)? }
+        }
+
+        "NestMembers" => {
+            Attribute::NestMembers { classes: read_classes(buffer, index, constant_pool)? }
+        }
+
+        "InnerClasses" => {
+            Attribute::InnerClasses { classes: read_inner_classes(buffer, index, constant_pool)? }
+        }
+
+        "EnclosingMethod" => {
+            let class = read_class(buffer, index, constant_pool)?;
+            let at_byte = *index;
+            let method_index = read_u2(buffer, index)?;
+            let method = if method_index == 0 {
+                None
+            } else {
+                constant_pool.record(method_index);
+                Some(read_name_and_type_from_constant_pool(constant_pool, method_index, at_byte)?)
+            };
+            Attribute::EnclosingMethod { class, method }
+        }
+
+        "NestHost" => {
+            Attribute::NestHost { host_class: read_class(buffer, index, constant_pool)? }
+        }
+
+        "StackMapTable" => {
+            Attribute::StackMapTable { entries: read_stack_map_table(buffer, index, constant_pool)? }
+        }
+
+        "BootstrapMethods" => {
+            Attribute::BootstrapMethods { bootstrap_methods: read_bootstrap_methods(buffer, index, constant_pool)? }
+        }
+
+        "Record" => {
+            Attribute::Record { components: read_record_components(buffer, index, constant_pool, options, depth + 1)? }
+        }
+
+        "PermittedSubclasses" => {
+            Attribute::PermittedSubclasses { classes: read_classes(buffer, index, constant_pool)? }
+        }
+
+        "MethodParameters" => {
+            Attribute::MethodParameters { parameters: read_method_parameters(buffer, index, constant_pool)? }
+        }
 
-            "SourceFile" => {
-                let source_file_index = read_u2(buffer, index)?;
-                Attribute::SourceFile { source_file: read_utf8_from_constant_pool(constant_pool, source_file_index).expect("Expected Utf8") }
+        "Module" => {
+            Attribute::Module { module: read_module(buffer, index, constant_pool)? }
+        }
+
+        "ModulePackages" => {
+            let package_count = read_u2(buffer, index)? as usize;
+            let mut packages: Vec<String> = Vec::with_capacity(package_count);
+            for _ in 0..package_count {
+                packages.push(read_package_name(buffer, index, constant_pool)?);
             }
+            Attribute::ModulePackages { packages }
+        }
 
-            "NestMembers" => {
-                let classes_num = read_u2(buffer, index)? as usize;
-                let mut classes: Vec<Class> = Vec::with_capacity(classes_num);
-                for _ in 0..classes_num {
-                    let class = read_class(buffer, index, constant_pool)?;
-                    classes.push(class);
+        "SourceDebugExtension" => {
+            Attribute::SourceDebugExtension { debug_extension: read_bytes(buffer, index, size)? }
+        }
+
+        "ModuleMainClass" => {
+            Attribute::ModuleMainClass { main_class: read_class(buffer, index, constant_pool)? }
+        }
+
+        _ => {
+            let at_byte = *index;
+            let data = read_bytes(buffer, index, size)?;
+            match options.attribute_registry.decoders.get(name) {
+                Some(decoder) => {
+                    let value = decoder(&data, constant_pool).map_err(|mut e| {
+                        e.at_byte += at_byte;
+                        e
+                    })?;
+                    Attribute::Custom { name: name.to_string(), value }
                 }
-                Attribute::NestMembers { classes }
+                None => Attribute::Unknown { name: name.to_string(), data }
             }
+        }
+    };
+    Ok(attribute)
+}
 
-            _ => {
-                println!("Ignoring attribute {}", name);
-                *index += size;
-                Attribute::Unimplemented
-            }
-        };
+/// Reads a u2 count followed by that many Class indices, as used by `NestMembers` and `PermittedSubclasses`
+fn read_classes(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<Class>, ParsingError> {
+    let number_of_classes = read_u2(buffer, index)? as usize;
+    let mut classes: Vec<Class> = Vec::with_capacity(number_of_classes);
+    for _ in 0..number_of_classes {
+        let class = read_class(buffer, index, constant_pool)?;
+        classes.push(class);
+    }
+    Ok(classes)
+}
 
-        attributes.push(attribute);
+fn read_record_components(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions, depth: usize) -> Result<Vec<RecordComponent>, ParsingError> {
+    let components_count = read_u2(buffer, index)? as usize;
+    let mut components: Vec<RecordComponent> = Vec::with_capacity(components_count);
+    for i in 0..components_count {
+        let component = constant_pool.in_part(|| read_record_component(buffer, index, constant_pool, options, depth), |component| format!("components[{}]", component.name))
+            .map_err(|e| e.with_context(format!("components[{}]", i)))?;
+        components.push(component);
     }
-    Ok(attributes)
+    Ok(components)
+}
+
+fn read_record_component(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, options: &ReaderOptions, depth: usize) -> Result<RecordComponent, ParsingError> {
+    let name = read_utf8(buffer, index, constant_pool)?;
+    let descriptor = read_utf8(buffer, index, constant_pool)?;
+    let attributes = read_attributes(buffer, index, constant_pool, options, depth)?;
+    Ok(RecordComponent { name, descriptor, attributes })
+}
+
+fn read_method_parameters(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<MethodParameter>, ParsingError> {
+    let parameters_count = read_u1(buffer, index)? as usize;
+    let mut parameters: Vec<MethodParameter> = Vec::with_capacity(parameters_count);
+    for i in 0..parameters_count {
+        let parameter = read_method_parameter(buffer, index, constant_pool)
+            .map_err(|e| e.with_context(format!("parameters[{}]", i)))?;
+        parameters.push(parameter);
+    }
+    Ok(parameters)
+}
+
+fn read_method_parameter(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<MethodParameter, ParsingError> {
+    let name = read_optional_utf8(buffer, index, constant_pool)?;
+    let access_flags = parse_method_parameter_flags(read_u2(buffer, index)?);
+    Ok(MethodParameter { name, access_flags })
+}
+
+fn parse_method_parameter_flags(mask: u16) -> Vec<MethodParameterFlag> {
+    let mut flags: Vec<MethodParameterFlag> = Vec::new();
+    if mask & 0x0010 != 0 {
+        flags.push(MethodParameterFlag::AccFinal)
+    }
+    if mask & 0x1000 != 0 {
+        flags.push(MethodParameterFlag::AccSynthetic)
+    }
+    if mask & 0x8000 != 0 {
+        flags.push(MethodParameterFlag::AccMandated)
+    }
+    if mask & !0x9010 != 0 {
+        flags.push(MethodParameterFlag::Reserved(mask & !0x9010))
+    }
+    flags
+}
+
+fn read_module(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Module, ParsingError> {
+    let name = read_module_name(buffer, index, constant_pool)?;
+    let flags = parse_module_flags(read_u2(buffer, index)?);
+    let version = read_optional_utf8(buffer, index, constant_pool)?;
+
+    let requires_count = read_u2(buffer, index)? as usize;
+    let mut requires: Vec<Requires> = Vec::with_capacity(requires_count);
+    for i in 0..requires_count {
+        requires.push(read_requires(buffer, index, constant_pool).map_err(|e| e.with_context(format!("requires[{}]", i)))?);
+    }
+
+    let exports_count = read_u2(buffer, index)? as usize;
+    let mut exports: Vec<Exports> = Vec::with_capacity(exports_count);
+    for i in 0..exports_count {
+        let (package, flags, to) = read_exports(buffer, index, constant_pool).map_err(|e| e.with_context(format!("exports[{}]", i)))?;
+        exports.push(Exports { package, flags, to });
+    }
+
+    let opens_count = read_u2(buffer, index)? as usize;
+    let mut opens: Vec<Opens> = Vec::with_capacity(opens_count);
+    for i in 0..opens_count {
+        let (package, flags, to) = read_exports(buffer, index, constant_pool).map_err(|e| e.with_context(format!("opens[{}]", i)))?;
+        opens.push(Opens { package, flags, to });
+    }
+
+    let uses = read_classes(buffer, index, constant_pool).map_err(|e| e.with_context("uses"))?;
+
+    let provides_count = read_u2(buffer, index)? as usize;
+    let mut provides: Vec<Provides> = Vec::with_capacity(provides_count);
+    for i in 0..provides_count {
+        let service = read_class(buffer, index, constant_pool).map_err(|e| e.with_context(format!("provides[{}]", i)))?;
+        let with = read_classes(buffer, index, constant_pool).map_err(|e| e.with_context(format!("provides[{}]", i)))?;
+        provides.push(Provides { service, with });
+    }
+
+    Ok(Module { name, flags, version, requires, exports, opens, uses, provides })
+}
+
+fn read_requires(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Requires, ParsingError> {
+    let module = read_module_name(buffer, index, constant_pool)?;
+    let flags = parse_requires_flags(read_u2(buffer, index)?);
+    let version = read_optional_utf8(buffer, index, constant_pool)?;
+    Ok(Requires { module, flags, version })
 }
 
-fn read_exception_table(buffer: &Vec<u8>, index: &mut usize, constant_pool: &ConstantPool) -> Vec<ExceptionHandler> {
-    let exception_table_length = read_u2(buffer, index).expect("Expected Exception Table Length");
+/// Reads an `exports` or `opens` entry, which share the same layout
+fn read_exports(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<(String, Vec<ExportsFlag>, Vec<String>), ParsingError> {
+    let package = read_package_name(buffer, index, constant_pool)?;
+    let flags = parse_exports_flags(read_u2(buffer, index)?);
+    let to_count = read_u2(buffer, index)? as usize;
+    let mut to: Vec<String> = Vec::with_capacity(to_count);
+    for _ in 0..to_count {
+        to.push(read_module_name(buffer, index, constant_pool)?);
+    }
+    Ok((package, flags, to))
+}
+
+/// Reads a constant pool index and resolves the name of the Module entry it points to
+fn read_module_name(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<String, ParsingError> {
+    let at_byte = *index;
+    let module_index = read_u2(buffer, index)?;
+    constant_pool.record(module_index);
+    if let ConstantPoolEntry::Module { name_index } = read_constant_pool_entry_at(constant_pool, module_index, at_byte)? {
+        read_utf8_from_constant_pool(constant_pool, *name_index, at_byte)
+    } else {
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index: module_index, expected: "Module" }))
+    }
+}
+
+/// Reads a constant pool index and resolves the name of the Package entry it points to
+fn read_package_name(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<String, ParsingError> {
+    let at_byte = *index;
+    let package_index = read_u2(buffer, index)?;
+    constant_pool.record(package_index);
+    if let ConstantPoolEntry::Package { name_index } = read_constant_pool_entry_at(constant_pool, package_index, at_byte)? {
+        read_utf8_from_constant_pool(constant_pool, *name_index, at_byte)
+    } else {
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index: package_index, expected: "Package" }))
+    }
+}
+
+fn parse_module_flags(mask: u16) -> Vec<ModuleFlag> {
+    let mut flags: Vec<ModuleFlag> = Vec::new();
+    if mask & 0x0020 != 0 {
+        flags.push(ModuleFlag::AccOpen)
+    }
+    if mask & 0x1000 != 0 {
+        flags.push(ModuleFlag::AccSynthetic)
+    }
+    if mask & 0x8000 != 0 {
+        flags.push(ModuleFlag::AccMandated)
+    }
+    if mask & !0x9020 != 0 {
+        flags.push(ModuleFlag::Reserved(mask & !0x9020))
+    }
+    flags
+}
+
+fn parse_requires_flags(mask: u16) -> Vec<RequiresFlag> {
+    let mut flags: Vec<RequiresFlag> = Vec::new();
+    if mask & 0x0020 != 0 {
+        flags.push(RequiresFlag::AccTransitive)
+    }
+    if mask & 0x0040 != 0 {
+        flags.push(RequiresFlag::AccStaticPhase)
+    }
+    if mask & 0x1000 != 0 {
+        flags.push(RequiresFlag::AccSynthetic)
+    }
+    if mask & 0x8000 != 0 {
+        flags.push(RequiresFlag::AccMandated)
+    }
+    if mask & !0x9060 != 0 {
+        flags.push(RequiresFlag::Reserved(mask & !0x9060))
+    }
+    flags
+}
+
+fn parse_exports_flags(mask: u16) -> Vec<ExportsFlag> {
+    let mut flags: Vec<ExportsFlag> = Vec::new();
+    if mask & 0x1000 != 0 {
+        flags.push(ExportsFlag::AccSynthetic)
+    }
+    if mask & 0x8000 != 0 {
+        flags.push(ExportsFlag::AccMandated)
+    }
+    if mask & !0x9000 != 0 {
+        flags.push(ExportsFlag::Reserved(mask & !0x9000))
+    }
+    flags
+}
+
+fn read_bootstrap_methods(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<BootstrapMethod>, ParsingError> {
+    let num_bootstrap_methods = read_u2(buffer, index)? as usize;
+    let mut bootstrap_methods: Vec<BootstrapMethod> = Vec::with_capacity(num_bootstrap_methods);
+    for i in 0..num_bootstrap_methods {
+        let bootstrap_method = read_bootstrap_method(buffer, index, constant_pool)
+            .map_err(|e| e.with_context(format!("bootstrap_methods[{}]", i)))?;
+        bootstrap_methods.push(bootstrap_method);
+    }
+    Ok(bootstrap_methods)
+}
+
+fn read_bootstrap_method(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<BootstrapMethod, ParsingError> {
+    let at_byte = *index;
+    let method_ref = read_u2(buffer, index)?;
+    constant_pool.record(method_ref);
+    let method = read_method_handle_from_constant_pool(constant_pool, method_ref, at_byte)?;
+    let num_arguments = read_u2(buffer, index)? as usize;
+    let mut arguments: Vec<Constant> = Vec::with_capacity(num_arguments);
+    for _ in 0..num_arguments {
+        let at_byte = *index;
+        let argument = read_u2(buffer, index)?;
+        constant_pool.record(argument);
+        arguments.push(read_constant_from_constant_pool(constant_pool, argument, at_byte)?);
+    }
+    Ok(BootstrapMethod { method, arguments })
+}
+
+fn read_exception_table(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<ExceptionHandler>, ParsingError> {
+    let exception_table_length = read_u2(buffer, index)?;
     let mut exception_table: Vec<ExceptionHandler> = Vec::new();
-    for _ in 0..exception_table_length {
-        let start_pc = read_u2(buffer, index).expect("Expected Start PC");
-        let end_pc = read_u2(buffer, index).expect("Expected End PC");
-        let handler_pc = read_u2(buffer, index).expect("Expected Handler PC");
-        let catch_type_index = read_u2(buffer, index).expect("Expected Catch Type") as usize;
-        let catch_type: Option<Class>;
-        if catch_type_index == 0 {
-            catch_type = None;
-        } else if let ConstantPoolEntry::Class { name_index } = constant_pool.get(catch_type_index - 1).expect("Expected Constant Pool Entry") {
-            let class_name = read_utf8_from_constant_pool(constant_pool, *name_index).expect("Expected Utf8");
-            catch_type = Some(Class { name: class_name })
-        } else {
-            panic!("Catch Type must be a Class")
-        };
+    for i in 0..exception_table_length {
+        let start_pc = read_u2(buffer, index)?;
+        let end_pc = read_u2(buffer, index)?;
+        let handler_pc = read_u2(buffer, index)?;
+        let catch_type = read_optional_class(buffer, index, constant_pool).map_err(|e| e.with_context(format!("exception_table[{}]", i)))?;
 
         exception_table.push(ExceptionHandler {
             start_pc,
@@ -329,258 +905,557 @@ fn read_exception_table(buffer: &Vec<u8>, index: &mut usize, constant_pool: &Con
             catch_type,
         })
     }
-    exception_table
+    Ok(exception_table)
 }
 
-fn read_exceptions(buffer: &Vec<u8>, index: &mut usize, constant_pool: &ConstantPool) -> Vec<Class> {
-    let exceptions_number = read_u2(buffer, index).expect("Expected Exception Number") as usize;
+fn read_exceptions(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<Class>, ParsingError> {
+    let exceptions_number = read_u2(buffer, index)? as usize;
     let mut exceptions: Vec<Class> = Vec::with_capacity(exceptions_number);
 
-    for _ in 0..exceptions_number {
-        let exception_index = read_u2(buffer, index).expect("Expected Exception Index") as usize;
-        if let ConstantPoolEntry::Class { name_index } = constant_pool.get(exception_index - 1).expect("Expected Constant Pool Entry") {
-            let class_name = read_utf8_from_constant_pool(constant_pool, *name_index).expect("Expected Class");
-            exceptions.push(Class { name: class_name })
-        } else {
-            panic!("Exception must be a Class")
-        }
+    for i in 0..exceptions_number {
+        exceptions.push(read_class(buffer, index, constant_pool).map_err(|e| e.with_context(format!("exceptions[{}]", i)))?);
     }
 
-    exceptions
+    Ok(exceptions)
 }
 
-fn read_line_number_table(buffer: &Vec<u8>, index: &mut usize) -> Vec<LineNumber> {
-    let line_number_count = read_u2(buffer, index).expect("Expected Line Number Count") as usize;
+fn read_line_number_table(buffer: &[u8], index: &mut usize) -> Result<Vec<LineNumber>, ParsingError> {
+    let line_number_count = read_u2(buffer, index)? as usize;
     let mut line_numbers: Vec<LineNumber> = Vec::with_capacity(line_number_count);
 
     for _ in 0..line_number_count {
-        let start_pc = read_u2(buffer, index).expect("Expected Start PC");
-        let line_number = read_u2(buffer, index).expect("Expected Line Number");
+        let start_pc = read_u2(buffer, index)?;
+        let line_number = read_u2(buffer, index)?;
         line_numbers.push(LineNumber { start_pc, line_number })
     }
 
-    line_numbers
+    Ok(line_numbers)
 }
 
-fn read_parameter_annotations<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Vec<Vec<Annotation<'a>>> {
-    let num_parameters = read_u1(buffer, index).expect("Expected Parameter Number") as usize;
+fn read_inner_classes(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<InnerClass>, ParsingError> {
+    let number_of_classes = read_u2(buffer, index)? as usize;
+    let mut classes: Vec<InnerClass> = Vec::with_capacity(number_of_classes);
+
+    for i in 0..number_of_classes {
+        classes.push(read_inner_class(buffer, index, constant_pool).map_err(|e| e.with_context(format!("classes[{}]", i)))?);
+    }
+
+    Ok(classes)
+}
+
+fn read_inner_class(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<InnerClass, ParsingError> {
+    let inner_class = read_class(buffer, index, constant_pool)?;
+    let outer_class = read_optional_class(buffer, index, constant_pool)?;
+    let inner_name = read_optional_utf8(buffer, index, constant_pool)?;
+    let access_flags = parse_inner_class_flags(read_u2(buffer, index)?);
+    Ok(InnerClass { inner_class, outer_class, inner_name, access_flags })
+}
+
+fn parse_inner_class_flags(mask: u16) -> Vec<InnerClassFlag> {
+    let mut flags: Vec<InnerClassFlag> = Vec::new();
+    if mask & 0x0001 != 0 {
+        flags.push(InnerClassFlag::AccPublic)
+    }
+    if mask & 0x0002 != 0 {
+        flags.push(InnerClassFlag::AccPrivate)
+    }
+    if mask & 0x0004 != 0 {
+        flags.push(InnerClassFlag::AccProtected)
+    }
+    if mask & 0x0008 != 0 {
+        flags.push(InnerClassFlag::AccStatic)
+    }
+    if mask & 0x0010 != 0 {
+        flags.push(InnerClassFlag::AccFinal)
+    }
+    if mask & 0x0200 != 0 {
+        flags.push(InnerClassFlag::AccInterface)
+    }
+    if mask & 0x0400 != 0 {
+        flags.push(InnerClassFlag::AccAbstract)
+    }
+    if mask & 0x1000 != 0 {
+        flags.push(InnerClassFlag::AccSynthetic)
+    }
+    if mask & 0x2000 != 0 {
+        flags.push(InnerClassFlag::AccAnnotation)
+    }
+    if mask & 0x4000 != 0 {
+        flags.push(InnerClassFlag::AccEnum)
+    }
+    if mask & !0x761F != 0 {
+        flags.push(InnerClassFlag::Reserved(mask & !0x761F))
+    }
+    flags
+}
+
+fn read_local_variable_table(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<LocalVariable>, ParsingError> {
+    let local_variable_count = read_u2(buffer, index)? as usize;
+    let mut local_variables: Vec<LocalVariable> = Vec::with_capacity(local_variable_count);
+
+    for i in 0..local_variable_count {
+        let local_variable = read_local_variable(buffer, index, constant_pool).map_err(|e| e.with_context(format!("local_variable_table[{}]", i)))?;
+        local_variables.push(local_variable);
+    }
+
+    Ok(local_variables)
+}
+
+fn read_local_variable(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<LocalVariable, ParsingError> {
+    let start_pc = read_u2(buffer, index)?;
+    let length = read_u2(buffer, index)?;
+    let name = read_utf8(buffer, index, constant_pool)?;
+    let descriptor = read_utf8(buffer, index, constant_pool)?;
+    let variable_index = read_u2(buffer, index)?;
+    Ok(LocalVariable { start_pc, length, name, descriptor, index: variable_index })
+}
+
+fn read_local_variable_type_table(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<LocalVariableType>, ParsingError> {
+    let local_variable_type_count = read_u2(buffer, index)? as usize;
+    let mut local_variable_types: Vec<LocalVariableType> = Vec::with_capacity(local_variable_type_count);
+
+    for i in 0..local_variable_type_count {
+        let local_variable_type = read_local_variable_type(buffer, index, constant_pool).map_err(|e| e.with_context(format!("local_variable_type_table[{}]", i)))?;
+        local_variable_types.push(local_variable_type);
+    }
+
+    Ok(local_variable_types)
+}
+
+fn read_local_variable_type(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<LocalVariableType, ParsingError> {
+    let start_pc = read_u2(buffer, index)?;
+    let length = read_u2(buffer, index)?;
+    let name = read_utf8(buffer, index, constant_pool)?;
+    let signature = read_utf8(buffer, index, constant_pool)?;
+    let variable_index = read_u2(buffer, index)?;
+    Ok(LocalVariableType { start_pc, length, name, signature, index: variable_index })
+}
+
+fn read_stack_map_table(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<StackMapFrame>, ParsingError> {
+    let number_of_entries = read_u2(buffer, index)? as usize;
+    let mut entries: Vec<StackMapFrame> = Vec::with_capacity(number_of_entries);
+
+    let mut previous_pc: Option<u16> = None;
+    for i in 0..number_of_entries {
+        let frame = read_stack_map_frame(buffer, index, constant_pool, previous_pc).map_err(|e| e.with_context(format!("entries[{}]", i)))?;
+        previous_pc = Some(frame.pc());
+        entries.push(frame);
+    }
+
+    Ok(entries)
+}
+
+fn read_stack_map_frame(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, previous_pc: Option<u16>) -> Result<StackMapFrame, ParsingError> {
+    let frame_byte = *index;
+    let frame_type = read_u1(buffer, index)?;
+
+    // The first frame applies at its offset delta, every following one at offset delta + 1 after its predecessor
+    let pc_from_delta = |offset_delta: u16| match previous_pc {
+        None => Some(offset_delta),
+        Some(previous_pc) => previous_pc.checked_add(offset_delta).and_then(|pc| pc.checked_add(1)),
+    }.ok_or_else(|| ParsingError::new(frame_byte, ParsingErrorKind::InvalidFrameOffset { offset_delta }));
+
+    let frame = match frame_type {
+        0..=63 => StackMapFrame::Same { pc: pc_from_delta(frame_type as u16)? },
+        64..=127 => {
+            let pc = pc_from_delta(frame_type as u16 - 64)?;
+            StackMapFrame::SameLocals1StackItem { pc, stack: read_verification_type(buffer, index, constant_pool)? }
+        }
+        247 => {
+            let pc = pc_from_delta(read_u2(buffer, index)?)?;
+            StackMapFrame::SameLocals1StackItemExtended { pc, stack: read_verification_type(buffer, index, constant_pool)? }
+        }
+        248..=250 => StackMapFrame::Chop { pc: pc_from_delta(read_u2(buffer, index)?)?, chopped: 251 - frame_type },
+        251 => StackMapFrame::SameExtended { pc: pc_from_delta(read_u2(buffer, index)?)? },
+        252..=254 => {
+            let pc = pc_from_delta(read_u2(buffer, index)?)?;
+            let locals = read_verification_types(buffer, index, constant_pool, (frame_type - 251) as usize)?;
+            StackMapFrame::Append { pc, locals }
+        }
+        255 => {
+            let pc = pc_from_delta(read_u2(buffer, index)?)?;
+            let number_of_locals = read_u2(buffer, index)? as usize;
+            let locals = read_verification_types(buffer, index, constant_pool, number_of_locals)?;
+            let number_of_stack_items = read_u2(buffer, index)? as usize;
+            let stack = read_verification_types(buffer, index, constant_pool, number_of_stack_items)?;
+            StackMapFrame::Full { pc, locals, stack }
+        }
+        _ => return Err(ParsingError::new(frame_byte, ParsingErrorKind::UnknownStackMapFrameType { frame_type }))
+    };
+    Ok(frame)
+}
+
+fn read_verification_types(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, count: usize) -> Result<Vec<VerificationType>, ParsingError> {
+    let mut types: Vec<VerificationType> = Vec::with_capacity(count);
+    for _ in 0..count {
+        types.push(read_verification_type(buffer, index, constant_pool)?);
+    }
+    Ok(types)
+}
+
+fn read_verification_type(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<VerificationType, ParsingError> {
+    let tag_byte = *index;
+    let tag = read_u1(buffer, index)?;
+    match tag {
+        0 => Ok(VerificationType::Top),
+        1 => Ok(VerificationType::Integer),
+        2 => Ok(VerificationType::Float),
+        3 => Ok(VerificationType::Double),
+        4 => Ok(VerificationType::Long),
+        5 => Ok(VerificationType::Null),
+        6 => Ok(VerificationType::UninitializedThis),
+        7 => Ok(VerificationType::Object(read_class(buffer, index, constant_pool)?)),
+        8 => Ok(VerificationType::Uninitialized { offset: read_u2(buffer, index)? }),
+        _ => Err(ParsingError::new(tag_byte, ParsingErrorKind::UnknownVerificationType { tag }))
+    }
+}
+
+fn read_parameter_annotations(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<Vec<Annotation>>, ParsingError> {
+    let num_parameters = read_u1(buffer, index)? as usize;
     let mut parameter_annotations: Vec<Vec<Annotation>> = Vec::with_capacity(num_parameters);
 
-    for _ in 0..num_parameters {
-        parameter_annotations.push(read_annotations(buffer, index, constant_pool));
+    for i in 0..num_parameters {
+        parameter_annotations.push(read_annotations(buffer, index, constant_pool).map_err(|e| e.with_context(format!("parameters[{}]", i)))?);
     }
 
-    parameter_annotations
+    Ok(parameter_annotations)
 }
 
-fn read_annotations<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Vec<Annotation<'a>> {
-    let annotations_count = read_u2(buffer, index).expect("Expected Annotation Count") as usize;
+fn read_annotations(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<Annotation>, ParsingError> {
+    let annotations_count = read_u2(buffer, index)? as usize;
     let mut annotations: Vec<Annotation> = Vec::with_capacity(annotations_count);
 
-    for _ in 0..annotations_count {
-        annotations.push(read_annotation(buffer, index, constant_pool));
+    for i in 0..annotations_count {
+        annotations.push(read_annotation(buffer, index, constant_pool, 0).map_err(|e| e.with_context(format!("annotations[{}]", i)))?);
+    }
+
+    Ok(annotations)
+}
+
+fn read_type_annotations(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Vec<TypeAnnotation>, ParsingError> {
+    let annotations_count = read_u2(buffer, index)? as usize;
+    let mut annotations: Vec<TypeAnnotation> = Vec::with_capacity(annotations_count);
+
+    for i in 0..annotations_count {
+        annotations.push(read_type_annotation(buffer, index, constant_pool).map_err(|e| e.with_context(format!("annotations[{}]", i)))?);
     }
 
-    annotations
+    Ok(annotations)
+}
+
+fn read_type_annotation(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<TypeAnnotation, ParsingError> {
+    let (target_type, target_info) = read_type_annotation_target(buffer, index)?;
+    let type_path = read_type_path(buffer, index)?;
+    let annotation = read_annotation(buffer, index, constant_pool, 0)?;
+    Ok(TypeAnnotation { target_type, target_info, type_path, annotation })
+}
+
+fn read_type_annotation_target(buffer: &[u8], index: &mut usize) -> Result<(TargetType, TargetInfo), ParsingError> {
+    let at_byte = *index;
+    let target_type = read_u1(buffer, index)?;
+    let target = match target_type {
+        0x00 => (TargetType::ClassTypeParameter, TargetInfo::TypeParameter { type_parameter_index: read_u1(buffer, index)? }),
+        0x01 => (TargetType::MethodTypeParameter, TargetInfo::TypeParameter { type_parameter_index: read_u1(buffer, index)? }),
+        0x10 => (TargetType::ClassExtends, TargetInfo::Supertype { supertype_index: read_u2(buffer, index)? }),
+        0x11 | 0x12 => {
+            let target_type = if target_type == 0x11 { TargetType::ClassTypeParameterBound } else { TargetType::MethodTypeParameterBound };
+            let type_parameter_index = read_u1(buffer, index)?;
+            let bound_index = read_u1(buffer, index)?;
+            (target_type, TargetInfo::TypeParameterBound { type_parameter_index, bound_index })
+        }
+        0x13 => (TargetType::Field, TargetInfo::Empty),
+        0x14 => (TargetType::MethodReturn, TargetInfo::Empty),
+        0x15 => (TargetType::MethodReceiver, TargetInfo::Empty),
+        0x16 => (TargetType::MethodFormalParameter, TargetInfo::FormalParameter { formal_parameter_index: read_u1(buffer, index)? }),
+        0x17 => (TargetType::Throws, TargetInfo::Throws { throws_type_index: read_u2(buffer, index)? }),
+        0x40 | 0x41 => {
+            let target_type = if target_type == 0x40 { TargetType::LocalVariable } else { TargetType::ResourceVariable };
+            let table_length = read_u2(buffer, index)? as usize;
+            let mut table: Vec<LocalVariableTarget> = Vec::with_capacity(table_length);
+            for _ in 0..table_length {
+                let start_pc = read_u2(buffer, index)?;
+                let length = read_u2(buffer, index)?;
+                let variable_index = read_u2(buffer, index)?;
+                table.push(LocalVariableTarget { start_pc, length, index: variable_index });
+            }
+            (target_type, TargetInfo::LocalVariable { table })
+        }
+        0x42 => (TargetType::ExceptionParameter, TargetInfo::Catch { exception_table_index: read_u2(buffer, index)? }),
+        0x43..=0x46 => {
+            let target_type = match target_type {
+                0x43 => TargetType::Instanceof,
+                0x44 => TargetType::New,
+                0x45 => TargetType::ConstructorReference,
+                _ => TargetType::MethodReference,
+            };
+            (target_type, TargetInfo::Offset { offset: read_u2(buffer, index)? })
+        }
+        0x47..=0x4B => {
+            let target_type = match target_type {
+                0x47 => TargetType::Cast,
+                0x48 => TargetType::ConstructorInvocationTypeArgument,
+                0x49 => TargetType::MethodInvocationTypeArgument,
+                0x4A => TargetType::ConstructorReferenceTypeArgument,
+                _ => TargetType::MethodReferenceTypeArgument,
+            };
+            let offset = read_u2(buffer, index)?;
+            let type_argument_index = read_u1(buffer, index)?;
+            (target_type, TargetInfo::TypeArgument { offset, type_argument_index })
+        }
+        _ => return Err(ParsingError::new(at_byte, ParsingErrorKind::UnknownTargetType { target_type }))
+    };
+    Ok(target)
+}
+
+fn read_type_path(buffer: &[u8], index: &mut usize) -> Result<Vec<TypePathEntry>, ParsingError> {
+    let path_length = read_u1(buffer, index)? as usize;
+    let mut type_path: Vec<TypePathEntry> = Vec::with_capacity(path_length);
+    for i in 0..path_length {
+        let at_byte = *index;
+        let kind = match read_u1(buffer, index)? {
+            0 => TypePathKind::Array,
+            1 => TypePathKind::Nested,
+            2 => TypePathKind::Wildcard,
+            3 => TypePathKind::TypeArgument,
+            kind => return Err(ParsingError::new(at_byte, ParsingErrorKind::UnknownTypePathKind { kind }).with_context(format!("type_path[{}]", i)))
+        };
+        let type_argument_index = read_u1(buffer, index)?;
+        type_path.push(TypePathEntry { kind, type_argument_index });
+    }
+    Ok(type_path)
 }
 
-fn read_annotation<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Annotation<'a> {
-    let type_index = read_u2(buffer, index).expect("Expected Type Index");
-    let type_name = read_utf8_from_constant_pool(constant_pool, type_index).expect("Expected Utf8");
+/// Annotations can be nested arbitrarily deep through element values. The depth is limited so that
+/// malicious input can't overflow the stack.
+const MAX_ELEMENT_VALUE_DEPTH: usize = 256;
 
-    let element_value_pairs = read_element_value_pairs(buffer, index, constant_pool);
+fn read_annotation(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, depth: usize) -> Result<Annotation, ParsingError> {
+    let type_name = read_utf8(buffer, index, constant_pool)?;
 
-    Annotation {
+    let element_value_pairs = read_element_value_pairs(buffer, index, constant_pool, depth)?;
+
+    Ok(Annotation {
         type_name,
         element_value_pairs,
-    }
+    })
 }
 
-fn read_element_value_pairs<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> Vec<ElementValuePair<'a>> {
-    let pair_count = read_u2(buffer, index).expect("Expected Pair Count") as usize;
+fn read_element_value_pairs(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, depth: usize) -> Result<Vec<ElementValuePair>, ParsingError> {
+    let pair_count = read_u2(buffer, index)? as usize;
     let mut pairs: Vec<ElementValuePair> = Vec::with_capacity(pair_count);
 
-    for _ in 0..pair_count {
-        let element_name_index = read_u2(buffer, index).expect("Expected Element Name Index");
-        let element_name = read_utf8_from_constant_pool(constant_pool, element_name_index).expect("Expected Utf8");
-        let element_value = read_element_value(buffer, index, constant_pool);
+    for i in 0..pair_count {
+        let element_name = read_utf8(buffer, index, constant_pool).map_err(|e| e.with_context(format!("element_value_pairs[{}]", i)))?;
+        let element_value = read_element_value(buffer, index, constant_pool, depth + 1).map_err(|e| e.with_context(element_name.as_str()))?;
         pairs.push(ElementValuePair(element_name, element_value));
     }
 
-    pairs
+    Ok(pairs)
 }
 
-fn read_element_value<'a>(buffer: &Vec<u8>, index: &mut usize, constant_pool: &'a ConstantPool) -> ElementValue<'a> {
-    let tag = read_u1(buffer, index).expect("Expected Tag") as char;
+fn read_element_value(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool, depth: usize) -> Result<ElementValue, ParsingError> {
+    if depth > MAX_ELEMENT_VALUE_DEPTH {
+        return Err(ParsingError::new(*index, ParsingErrorKind::NestingTooDeep));
+    }
+    let tag_byte = *index;
+    let tag = read_u1(buffer, index)? as char;
 
     match tag {
         'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' | 's' => {
-            let const_value_index = read_u2(buffer, index).expect("Expected Const Value Index") as usize;
-            ElementValue::ConstValue { value: constant_pool.get(const_value_index - 1).expect("Expected Constant Pool Entry") }
+            let at_byte = *index;
+            let const_value_index = read_u2(buffer, index)?;
+            let value = read_constant_value(constant_pool, const_value_index, at_byte)?;
+            Ok(ElementValue::ConstValue { tag, index: const_value_index, value })
         }
 
         'e' => {
-            let type_name_index = read_u2(buffer, index).expect("Expected Type Name Index");
-            let type_name = read_utf8_from_constant_pool(constant_pool, type_name_index).expect("Expected Utf8");
-
-            let const_name_index = read_u2(buffer, index).expect("Expected Const Name Index");
-            let const_name = read_utf8_from_constant_pool(constant_pool, const_name_index).expect("Expected Utf8");
-            ElementValue::EnumConstValue { type_name, const_name }
+            let type_name = read_utf8(buffer, index, constant_pool)?;
+            let const_name = read_utf8(buffer, index, constant_pool)?;
+            Ok(ElementValue::EnumConstValue { type_name, const_name })
         }
 
         'c' => {
-            let class_info_index = read_u2(buffer, index).expect("Expected Class Info Index");
-            let class_info = read_utf8_from_constant_pool(constant_pool, class_info_index).expect("Expected Utf8");
-            ElementValue::ClassInfo { descriptor: class_info }
+            let class_info = read_utf8(buffer, index, constant_pool)?;
+            Ok(ElementValue::ClassInfo { descriptor: class_info })
         }
 
-        '@' => ElementValue::AnnotationValue { annotation: read_annotation(buffer, index, constant_pool) },
+        '@' => Ok(ElementValue::AnnotationValue { annotation: read_annotation(buffer, index, constant_pool, depth + 1)? }),
 
         '[' => {
-            let num_values = read_u2(buffer, index).expect("Expected Array Value Count") as usize;
+            let num_values = read_u2(buffer, index)? as usize;
             let mut elements: Vec<ElementValue> = Vec::with_capacity(num_values);
 
-            for _ in 0..num_values {
-                elements.push(read_element_value(buffer, index, constant_pool));
+            for i in 0..num_values {
+                elements.push(read_element_value(buffer, index, constant_pool, depth + 1).map_err(|e| e.with_context(format!("elements[{}]", i)))?);
             }
-            ElementValue::ArrayValue { elements }
+            Ok(ElementValue::ArrayValue { elements })
         }
-        _ => panic!("Invalid Element Value Tag: {}", tag)
+        _ => Err(ParsingError::new(tag_byte, ParsingErrorKind::UnknownElementValueTag { tag: tag as u8 }))
     }
 }
 
-fn read_u1(buffer: &Vec<u8>, index: &mut usize) -> Result<u8, ParsingError> {
-    if *index > (buffer.len() - 1) {
-        Err(ParsingError::new(*index, "Expected u1"))
+pub(crate) fn read_u1(buffer: &[u8], index: &mut usize) -> Result<u8, ParsingError> {
+    if *index >= buffer.len() {
+        Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: 1 }))
     } else {
-        let value = *buffer.get(*index).unwrap();
+        let value = buffer[*index];
         *index += 1;
         Ok(value)
     }
 }
 
-fn read_u2(buffer: &Vec<u8>, index: &mut usize) -> Result<u16, ParsingError> {
-    if *index > (buffer.len() - 2) {
-        Err(ParsingError::new(*index, "Expected u2"))
+pub(crate) fn read_u2(buffer: &[u8], index: &mut usize) -> Result<u16, ParsingError> {
+    if buffer.len() < 2 || *index > (buffer.len() - 2) {
+        Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: 2 }))
     } else {
-        let value = (*buffer.get(*index).unwrap() as u16) << 8 | (*buffer.get(*index + 1).unwrap() as u16);
+        let value = (buffer[*index] as u16) << 8 | (buffer[*index + 1] as u16);
         *index += 2;
         Ok(value)
     }
 }
 
-fn read_u4(buffer: &Vec<u8>, index: &mut usize) -> Result<u32, ParsingError> {
-    if *index > (buffer.len() - 4) {
-        Err(ParsingError::new(*index, "Expected u4"))
+pub(crate) fn read_u4(buffer: &[u8], index: &mut usize) -> Result<u32, ParsingError> {
+    if buffer.len() < 4 || *index > (buffer.len() - 4) {
+        Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: 4 }))
     } else {
-        let value = (*buffer.get(*index).unwrap() as u32) << 24 |
-            (*buffer.get(*index + 1).unwrap() as u32) << 16 |
-            (*buffer.get(*index + 2).unwrap() as u32) << 8 |
-            (*buffer.get(*index + 3).unwrap() as u32);
+        let value = (buffer[*index] as u32) << 24 |
+            (buffer[*index + 1] as u32) << 16 |
+            (buffer[*index + 2] as u32) << 8 |
+            (buffer[*index + 3] as u32);
         *index += 4;
         Ok(value)
     }
 }
 
-fn read_u8(buffer: &Vec<u8>, index: &mut usize) -> Option<u64> {
-    let high: u64 = read_u4(buffer, index).expect("Expected Integer") as u64;
-    let low: u64 = read_u4(buffer, index).expect("Expected Integer") as u64;
-    Some((high << 32) | low)
+fn read_u8(buffer: &[u8], index: &mut usize) -> Result<u64, ParsingError> {
+    let high: u64 = read_u4(buffer, index)? as u64;
+    let low: u64 = read_u4(buffer, index)? as u64;
+    Ok((high << 32) | low)
 }
 
-fn read_f4(buffer: &Vec<u8>, index: &mut usize) -> Option<f32> {
-    let int = read_u4(buffer, index).expect("Expected Integer");
-    Some(f32::from_bits(int))
+fn read_f4(buffer: &[u8], index: &mut usize) -> Result<f32, ParsingError> {
+    let int = read_u4(buffer, index)?;
+    Ok(f32::from_bits(int))
 }
 
-fn read_f8(buffer: &Vec<u8>, index: &mut usize) -> Option<f64> {
-    let int = read_u8(buffer, index).expect("Expected Long");
-    Some(f64::from_bits(int))
+fn read_f8(buffer: &[u8], index: &mut usize) -> Result<f64, ParsingError> {
+    let int = read_u8(buffer, index)?;
+    Ok(f64::from_bits(int))
 }
 
-fn read_length_and_utf8(buffer: &Vec<u8>, index: &mut usize) -> Option<String> {
-    if *index > buffer.len() - 1 {
-        return None;
+fn read_bytes(buffer: &[u8], index: &mut usize, length: usize) -> Result<Vec<u8>, ParsingError> {
+    if *index > buffer.len() || length > buffer.len() - *index {
+        Err(ParsingError::new(*index, ParsingErrorKind::UnexpectedEndOfInput { needed: length }))
+    } else {
+        let bytes = buffer[*index..(*index + length)].to_vec();
+        *index += length;
+        Ok(bytes)
     }
-    let length = read_u2(buffer, index).expect("Expected Length") as usize;
-    if *index + length > buffer.len() {
-        return None;
+}
+
+fn read_length_and_utf8(buffer: &[u8], index: &mut usize) -> Result<ConstantPoolEntry, ParsingError> {
+    let length = read_u2(buffer, index)? as usize;
+    let bytes = read_bytes(buffer, index, length)?;
+    match mutf8::decode(&bytes) {
+        Some(value) => Ok(ConstantPoolEntry::Utf8Info { value, raw: None }),
+        None => Ok(ConstantPoolEntry::Utf8Info { value: mutf8::decode_lossy(&bytes), raw: Some(bytes) }),
     }
-    let string = String::from_utf8(buffer[*index..(*index + length)].to_vec()).unwrap();
-    *index += length;
-    Some(string)
 }
 
 
-fn read_constant_pool_entry(buffer: &Vec<u8>, index: &mut usize) -> Result<ConstantPoolEntry, ParsingError> {
-    let tag = read_u1(buffer, index).expect("Expected Constant Pool Tag");
+fn read_constant_pool_entry(buffer: &[u8], index: &mut usize) -> Result<ConstantPoolEntry, ParsingError> {
+    let tag_byte = *index;
+    let tag = read_u1(buffer, index)?;
     match tag {
         7 => Ok(ConstantPoolEntry::Class { name_index: read_u2(buffer, index)? }),
 
         9 => Ok(ConstantPoolEntry::Fieldref {
-            class_index: read_u2(buffer, index).expect("Expected Class Index"),
-            name_and_type_index: read_u2(buffer, index).expect("Expected Name And Type Index"),
+            class_index: read_u2(buffer, index)?,
+            name_and_type_index: read_u2(buffer, index)?,
         }),
 
         10 => Ok(ConstantPoolEntry::Methodref {
-            class_index: read_u2(buffer, index).expect("Expected Class Index"),
-            name_and_type_index: read_u2(buffer, index).expect("Expected Name And Type Index"),
+            class_index: read_u2(buffer, index)?,
+            name_and_type_index: read_u2(buffer, index)?,
         }),
 
         11 => Ok(ConstantPoolEntry::InterfaceMethodref {
-            class_index: read_u2(buffer, index).expect("Expected Class Index"),
-            name_and_type_index: read_u2(buffer, index).expect("Expected Name And Type Index"),
+            class_index: read_u2(buffer, index)?,
+            name_and_type_index: read_u2(buffer, index)?,
         }),
 
-        8 => Ok(ConstantPoolEntry::StringInfo { string_index: read_u2(buffer, index).expect("Expected String Index") }),
+        8 => Ok(ConstantPoolEntry::StringInfo { string_index: read_u2(buffer, index)? }),
 
-        3 => Ok(ConstantPoolEntry::IntegerInfo { value: read_u4(buffer, index).expect("Expected Integer") }),
+        3 => Ok(ConstantPoolEntry::IntegerInfo { value: read_u4(buffer, index)? }),
 
-        4 => Ok(ConstantPoolEntry::FloatInfo { value: read_f4(buffer, index).expect("Expected Float") }),
+        4 => Ok(ConstantPoolEntry::FloatInfo { value: read_f4(buffer, index)? }),
 
-        5 => Ok(ConstantPoolEntry::LongInfo { value: read_u8(buffer, index).expect("Expected Long") }),
+        5 => Ok(ConstantPoolEntry::LongInfo { value: read_u8(buffer, index)? }),
 
-        6 => Ok(ConstantPoolEntry::DoubleInfo { value: read_f8(buffer, index).expect("Expected Double") }),
+        6 => Ok(ConstantPoolEntry::DoubleInfo { value: read_f8(buffer, index)? }),
 
         12 => Ok(ConstantPoolEntry::NameAndTypeInfo {
-            name_index: read_u2(buffer, index).expect("Expected Name Index"),
-            descriptor_index: read_u2(buffer, index).expect("Expected Descriptor Index"),
+            name_index: read_u2(buffer, index)?,
+            descriptor_index: read_u2(buffer, index)?,
         }),
 
-        1 => Ok(ConstantPoolEntry::Utf8Info { value: read_length_and_utf8(buffer, index).expect("Expected String") }),
+        1 => read_length_and_utf8(buffer, index),
 
         15 => Ok(ConstantPoolEntry::MethodHandle {
-            reference_kind: read_u1(buffer, index).expect("Expected Reference Kind"),
-            reference_index: read_u2(buffer, index).expect("Expected Reference Index"),
+            reference_kind: read_u1(buffer, index)?,
+            reference_index: read_u2(buffer, index)?,
         }),
 
-        16 => Ok(ConstantPoolEntry::MethodTypeInfo { descriptor_index: read_u2(buffer, index).expect("Expected Descriptor Index") }),
+        16 => Ok(ConstantPoolEntry::MethodTypeInfo { descriptor_index: read_u2(buffer, index)? }),
+
+        17 => Ok(ConstantPoolEntry::Dynamic {
+            bootstrap_method_attr_index: read_u2(buffer, index)?,
+            name_and_type_index: read_u2(buffer, index)?,
+        }),
 
         18 => Ok(ConstantPoolEntry::InvokeDynamicInfo {
-            bootstrap_method_attr_index: read_u2(buffer, index).expect("Expected Bootstrap Method Attr Index"),
-            name_and_type_index: read_u2(buffer, index).expect("Expected Name And Type Index"),
+            bootstrap_method_attr_index: read_u2(buffer, index)?,
+            name_and_type_index: read_u2(buffer, index)?,
         }),
 
-        _ => Err(ParsingError::new(*index, format!("Invalid Constant Pool Tag {}", tag).as_str()))
+        19 => Ok(ConstantPoolEntry::Module { name_index: read_u2(buffer, index)? }),
+
+        20 => Ok(ConstantPoolEntry::Package { name_index: read_u2(buffer, index)? }),
+
+        _ => Err(ParsingError::new(tag_byte, ParsingErrorKind::UnknownConstantPoolTag { tag }))
     }
 }
 
-fn read_class(buffer: &Vec<u8>, index: &mut usize, constant_pool: &ConstantPool) -> Result<Class, ParsingError> {
-    let this_class_index = read_u2(buffer, index)? as usize;
-    if let ConstantPoolEntry::Class { name_index } = constant_pool.get(this_class_index - 1).unwrap() {
-        if let ConstantPoolEntry::Utf8Info { value } = constant_pool.get(*name_index as usize - 1).unwrap() {
-            Ok(Class {
-                name: value.to_owned()
-            })
-        } else {
-            Err(ParsingError::new(*index, "Expected Utf8 Constant Pool Entry"))
-        }
+fn read_class(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Class, ParsingError> {
+    let at_byte = *index;
+    let class_index = read_u2(buffer, index)?;
+    constant_pool.record(class_index);
+    read_class_from_constant_pool(constant_pool, class_index, at_byte)
+}
+
+/// Like [`read_class`], but an index of 0 is allowed and resolves to `None`
+fn read_optional_class(buffer: &[u8], index: &mut usize, constant_pool: &TrackedPool) -> Result<Option<Class>, ParsingError> {
+    let at_byte = *index;
+    let class_index = read_u2(buffer, index)?;
+    if class_index == 0 {
+        Ok(None)
+    } else {
+        constant_pool.record(class_index);
+        read_class_from_constant_pool(constant_pool, class_index, at_byte).map(Some)
+    }
+}
+
+pub(crate) fn read_class_from_constant_pool(constant_pool: &ConstantPool, index: u16, at_byte: usize) -> Result<Class, ParsingError> {
+    if let ConstantPoolEntry::Class { name_index } = read_constant_pool_entry_at(constant_pool, index, at_byte)? {
+        Ok(Class {
+            name: read_utf8_from_constant_pool(constant_pool, *name_index, at_byte)?
+        })
     } else {
-        Err(ParsingError::new(*index, "Expected Class Constant Pool Entry"))
+        Err(ParsingError::new(at_byte, ParsingErrorKind::WrongConstantPoolTag { index, expected: "Class" }))
     }
 }
 
-fn read_access_flags(buffer: &Vec<u8>, index: &mut usize) -> Result<Vec<AccessFlag>, ParsingError> {
+fn read_access_flags(buffer: &[u8], index: &mut usize) -> Result<Vec<AccessFlag>, ParsingError> {
     let access_flags_mask = read_u2(buffer, index)?;
     Ok(parse_access_flags(access_flags_mask))
 }
@@ -611,5 +1486,300 @@ fn parse_access_flags(mask: u16) -> Vec<AccessFlag> {
     if mask & 0x4000 != 0 {
         flags.push(AccessFlag::AccEnum);
     }
+    if mask & 0x8000 != 0 {
+        flags.push(AccessFlag::AccModule);
+    }
+    if mask & !0xF631 != 0 {
+        flags.push(AccessFlag::Reserved(mask & !0xF631));
+    }
     flags
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A class whose only attribute is a `Code` attribute, with `depth` more nested inside of it. The
+    /// attributes of the innermost one are at depth `depth + 1`.
+    fn class_with_nested_code(depth: usize) -> Vec<u8> {
+        let mut attribute: Vec<u8> = Vec::new();
+        for i in 0..=depth {
+            let mut body: Vec<u8> = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            body.extend_from_slice(&(if i == 0 { 0u16 } else { 1u16 }).to_be_bytes());
+            body.extend_from_slice(&attribute);
+            attribute = vec![0, 3];
+            attribute.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            attribute.extend_from_slice(&body);
+        }
+        let mut class: Vec<u8> = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 4];
+        class.extend_from_slice(&[1, 0, 1, b'A', 7, 0, 1, 1, 0, 4, b'C', b'o', b'd', b'e']);
+        class.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        class.extend_from_slice(&attribute);
+        class
+    }
+
+    #[test]
+    fn nested_attributes() {
+        let class_file = read_class_file(&class_with_nested_code(MAX_ATTRIBUTE_DEPTH - 1)).unwrap();
+        assert!(matches!(class_file.attributes[0], Attribute::Code { .. }));
+
+        let error = read_class_file(&class_with_nested_code(MAX_ATTRIBUTE_DEPTH)).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::NestingTooDeep);
+        let error = read_class_file(&class_with_nested_code(1000)).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::NestingTooDeep);
+    }
+
+    /// A class `A` whose pool holds the Utf8 and Class entries of `A` at 1 and 2 and Utf8 entries for
+    /// `strings` from index 3 on. `members` are the fields, methods and attributes.
+    fn class_with(strings: &[&str], members: &[u8]) -> Vec<u8> {
+        let mut class: Vec<u8> = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52];
+        class.extend_from_slice(&(strings.len() as u16 + 3).to_be_bytes());
+        class.extend_from_slice(&[1, 0, 1, b'A', 7, 0, 1]);
+        for string in strings {
+            class.push(1);
+            class.extend_from_slice(&(string.len() as u16).to_be_bytes());
+            class.extend_from_slice(string.as_bytes());
+        }
+        class.extend_from_slice(&[0, 0x21, 0, 2, 0, 0, 0, 0]);
+        class.extend_from_slice(members);
+        class
+    }
+
+    #[test]
+    fn stack_map_frames() {
+        let constant_pool = read_class_file(&class_with(&[], &[0, 0, 0, 0, 0, 0])).unwrap().constant_pool;
+        // One frame of every type. After the first one, each applies at offset delta + 1 past the last
+        let table: Vec<u8> = vec![
+            0, 7,
+            3,
+            66, 1,
+            247, 1, 0, 7, 0, 2,
+            249, 0, 0,
+            251, 0, 1,
+            253, 0, 2, 4, 8, 0, 5,
+            255, 0, 3, 0, 1, 6, 0, 2, 5, 0,
+        ];
+        let entries = read_stack_map_table(&table, &mut 0, &TrackedPool::new(&constant_pool)).unwrap();
+        assert_eq!(entries, [
+            StackMapFrame::Same { pc: 3 },
+            StackMapFrame::SameLocals1StackItem { pc: 6, stack: VerificationType::Integer },
+            StackMapFrame::SameLocals1StackItemExtended { pc: 263, stack: VerificationType::Object(Class { name: "A".to_string() }) },
+            StackMapFrame::Chop { pc: 264, chopped: 2 },
+            StackMapFrame::SameExtended { pc: 266 },
+            StackMapFrame::Append { pc: 269, locals: vec![VerificationType::Long, VerificationType::Uninitialized { offset: 5 }] },
+            StackMapFrame::Full { pc: 273, locals: vec![VerificationType::UninitializedThis], stack: vec![VerificationType::Null, VerificationType::Top] },
+        ]);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        crate::writer::write_stack_map_table(&mut buffer, &entries, &mut crate::constant_pool::ConstantPoolBuilder::from_constant_pool(constant_pool)).unwrap();
+        assert_eq!(buffer, table);
+    }
+
+    #[test]
+    fn type_annotation_targets() {
+        let constant_pool = read_class_file(&class_with(&["LT;"], &[0, 0, 0, 0, 0, 0])).unwrap().constant_pool;
+        let targets: Vec<(Vec<u8>, TargetType, TargetInfo)> = vec![
+            (vec![0x00, 1], TargetType::ClassTypeParameter, TargetInfo::TypeParameter { type_parameter_index: 1 }),
+            (vec![0x01, 2], TargetType::MethodTypeParameter, TargetInfo::TypeParameter { type_parameter_index: 2 }),
+            (vec![0x10, 0xFF, 0xFF], TargetType::ClassExtends, TargetInfo::Supertype { supertype_index: 65535 }),
+            (vec![0x11, 1, 2], TargetType::ClassTypeParameterBound, TargetInfo::TypeParameterBound { type_parameter_index: 1, bound_index: 2 }),
+            (vec![0x12, 3, 4], TargetType::MethodTypeParameterBound, TargetInfo::TypeParameterBound { type_parameter_index: 3, bound_index: 4 }),
+            (vec![0x13], TargetType::Field, TargetInfo::Empty),
+            (vec![0x14], TargetType::MethodReturn, TargetInfo::Empty),
+            (vec![0x15], TargetType::MethodReceiver, TargetInfo::Empty),
+            (vec![0x16, 5], TargetType::MethodFormalParameter, TargetInfo::FormalParameter { formal_parameter_index: 5 }),
+            (vec![0x17, 0, 6], TargetType::Throws, TargetInfo::Throws { throws_type_index: 6 }),
+            (vec![0x40, 0, 2, 0, 1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6], TargetType::LocalVariable, TargetInfo::LocalVariable { table: vec![
+                LocalVariableTarget { start_pc: 1, length: 2, index: 3 },
+                LocalVariableTarget { start_pc: 4, length: 5, index: 6 },
+            ] }),
+            (vec![0x41, 0, 1, 0, 7, 0, 8, 0, 9], TargetType::ResourceVariable, TargetInfo::LocalVariable { table: vec![
+                LocalVariableTarget { start_pc: 7, length: 8, index: 9 },
+            ] }),
+            (vec![0x42, 0, 10], TargetType::ExceptionParameter, TargetInfo::Catch { exception_table_index: 10 }),
+            (vec![0x43, 0, 11], TargetType::Instanceof, TargetInfo::Offset { offset: 11 }),
+            (vec![0x44, 0, 12], TargetType::New, TargetInfo::Offset { offset: 12 }),
+            (vec![0x45, 0, 13], TargetType::ConstructorReference, TargetInfo::Offset { offset: 13 }),
+            (vec![0x46, 0, 14], TargetType::MethodReference, TargetInfo::Offset { offset: 14 }),
+            (vec![0x47, 0, 15, 0], TargetType::Cast, TargetInfo::TypeArgument { offset: 15, type_argument_index: 0 }),
+            (vec![0x48, 0, 16, 1], TargetType::ConstructorInvocationTypeArgument, TargetInfo::TypeArgument { offset: 16, type_argument_index: 1 }),
+            (vec![0x49, 0, 17, 2], TargetType::MethodInvocationTypeArgument, TargetInfo::TypeArgument { offset: 17, type_argument_index: 2 }),
+            (vec![0x4A, 0, 18, 3], TargetType::ConstructorReferenceTypeArgument, TargetInfo::TypeArgument { offset: 18, type_argument_index: 3 }),
+            (vec![0x4B, 0, 19, 4], TargetType::MethodReferenceTypeArgument, TargetInfo::TypeArgument { offset: 19, type_argument_index: 4 }),
+        ];
+        // The element type of the array in `Outer.Inner<? extends T[]>`
+        let type_path = [4, 1, 0, 3, 0, 2, 0, 0, 0];
+        let annotation = Annotation { type_name: "LT;".to_string(), element_value_pairs: Vec::new() };
+
+        for (target, target_type, target_info) in targets {
+            let mut bytes = target;
+            bytes.extend_from_slice(&type_path);
+            bytes.extend_from_slice(&[0, 3, 0, 0]);
+            let type_annotation = read_type_annotation(&bytes, &mut 0, &TrackedPool::new(&constant_pool)).unwrap();
+            assert_eq!(type_annotation, TypeAnnotation {
+                target_type,
+                target_info,
+                type_path: vec![
+                    TypePathEntry { kind: TypePathKind::Nested, type_argument_index: 0 },
+                    TypePathEntry { kind: TypePathKind::TypeArgument, type_argument_index: 0 },
+                    TypePathEntry { kind: TypePathKind::Wildcard, type_argument_index: 0 },
+                    TypePathEntry { kind: TypePathKind::Array, type_argument_index: 0 },
+                ],
+                annotation: annotation.clone(),
+            });
+
+            let mut buffer: Vec<u8> = Vec::new();
+            let mut builder = crate::constant_pool::ConstantPoolBuilder::from_constant_pool(constant_pool.clone());
+            crate::writer::write_type_annotation(&mut buffer, &type_annotation, &mut builder).unwrap();
+            assert_eq!(buffer, bytes);
+        }
+
+        let error = read_type_annotation(&[0x18, 0, 0, 3, 0, 0], &mut 0, &TrackedPool::new(&constant_pool)).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::UnknownTargetType { target_type: 0x18 });
+        let error = read_type_annotation(&[0x13, 2, 0, 0, 4, 0, 0, 3, 0, 0], &mut 0, &TrackedPool::new(&constant_pool)).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::UnknownTypePathKind { kind: 4 });
+        assert_eq!((error.at_byte, error.context), (4, vec!["type_path[1]".to_string()]));
+    }
+
+    fn lenient() -> ReaderOptions {
+        ReaderOptions { attribute_length_check: AttributeLengthCheck::Lenient, ..ReaderOptions::default() }
+    }
+
+    #[test]
+    fn strict_attribute_length_mismatch() {
+        // A SourceFile attribute that declares 4 bytes, but only needs 2
+        let class = class_with(&["SourceFile", "A.java"], &[0, 0, 0, 0, 0, 1, 0, 3, 0, 0, 0, 4, 0, 4, 0, 0]);
+        let error = read_class_file(&class).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::AttributeLengthMismatch { declared: 4, actual: 2 });
+        assert_eq!(error.at_byte, class.len() - 4);
+        assert_eq!(error.context, ["attributes[SourceFile]"]);
+    }
+
+    #[test]
+    fn lenient_attribute_length_resyncs_after_an_overlong_body() {
+        let class = class_with(&["x", "I", "Signature", "TT;", "m", "()V", "SourceFile", "A.java"], &[
+            // Two fields, the first with a Signature attribute that declares 2 bytes more than it needs
+            0, 2,
+            0, 0, 0, 3, 0, 4, 0, 1, 0, 5, 0, 0, 0, 4, 0, 6, 0xAB, 0xCD,
+            0, 0, 0, 3, 0, 4, 0, 0,
+            // A method and the SourceFile attribute of the class
+            0, 1, 0, 9, 0, 7, 0, 8, 0, 0,
+            0, 1, 0, 9, 0, 0, 0, 2, 0, 10,
+        ]);
+        assert!(read_class_file(&class).is_err());
+
+        let class_file = read_class_file_with_options(&class, &lenient()).unwrap();
+        assert_eq!(class_file.fields[0].attributes, [Attribute::Unknown { name: "Signature".to_string(), data: vec![0, 6, 0xAB, 0xCD] }]);
+        assert_eq!(class_file.fields[1].name, "x");
+        assert_eq!(class_file.fields[1].attributes, []);
+        assert_eq!((class_file.methods[0].name.as_str(), class_file.methods[0].descriptor.as_str()), ("m", "()V"));
+        assert_eq!(class_file.source_file(), Some("A.java"));
+        assert_eq!(class_file.parsed_bytes, class.len());
+        assert_eq!(crate::writer::write_class_file(&class_file).unwrap(), class);
+    }
+
+    #[test]
+    fn lenient_attribute_length_keeps_undecodable_bodies() {
+        // An Exceptions attribute that lists two classes, but only has room for one of them
+        let class = class_with(&["m", "()V", "Exceptions"], &[0, 0, 0, 1, 0, 1, 0, 3, 0, 4, 0, 1, 0, 5, 0, 0, 0, 4, 0, 2, 0, 2, 0, 0]);
+        let error = read_class_file(&class).unwrap_err();
+        assert_eq!(error.context, ["methods[0]", "attributes[Exceptions]", "exceptions[1]"]);
+
+        let class_file = read_class_file_with_options(&class, &lenient()).unwrap();
+        assert_eq!(class_file.methods[0].attributes, [Attribute::Unknown { name: "Exceptions".to_string(), data: vec![0, 2, 0, 2] }]);
+        // The class the body referred to before it failed isn't written back as a reference
+        assert_eq!(class_file.constant_pool_references.indices["methods[m:()V]/attributes[Exceptions]"], [5]);
+        assert_eq!(crate::writer::write_class_file(&class_file).unwrap(), class);
+    }
+
+    /// The decoded form of the `Marker` attribute in the registry tests: a u2 constant pool index of
+    /// a Utf8 entry
+    #[derive(Debug, PartialEq)]
+    struct Marker(String);
+
+    fn marker_registry() -> ReaderOptions {
+        let mut options = ReaderOptions::default();
+        options.attribute_registry.register("Marker", |data: &[u8], constant_pool: &ConstantPool| {
+            let mut index = 0;
+            let at_byte = index;
+            let utf8_index = read_u2(data, &mut index)?;
+            if index != data.len() {
+                return Err(ParsingError::new(index, ParsingErrorKind::AttributeLengthMismatch { declared: data.len() as u32, actual: index }));
+            }
+            Ok(Marker(read_utf8_from_constant_pool(constant_pool, utf8_index, at_byte)?))
+        });
+        options
+    }
+
+    /// A class with a `Marker` and an `Other` attribute, both with `marker` as their contents
+    fn class_with_markers(marker: &[u8]) -> Vec<u8> {
+        let mut members: Vec<u8> = vec![0, 0, 0, 0, 0, 2, 0, 3];
+        members.extend_from_slice(&(marker.len() as u32).to_be_bytes());
+        members.extend_from_slice(marker);
+        members.extend_from_slice(&[0, 5]);
+        members.extend_from_slice(&(marker.len() as u32).to_be_bytes());
+        members.extend_from_slice(marker);
+        class_with(&["Marker", "marked", "Other"], &members)
+    }
+
+    #[test]
+    fn registered_attributes_are_decoded() {
+        let class = class_with_markers(&[0, 4]);
+        let class_file = read_class_file_with_options(&class, &marker_registry()).unwrap();
+        let value = match &class_file.attributes[0] {
+            Attribute::Custom { name, value } if name == "Marker" => value,
+            attribute => panic!("{:?}", attribute),
+        };
+        assert_eq!(value.downcast_ref::<Marker>(), Some(&Marker("marked".to_string())));
+        assert_eq!(value.downcast_ref::<String>(), None);
+        assert_eq!(value.data, [0, 4]);
+        // Only the registered name is decoded
+        assert_eq!(class_file.attributes[1], Attribute::Unknown { name: "Other".to_string(), data: vec![0, 4] });
+
+        let class_file = read_class_file(&class).unwrap();
+        assert_eq!(class_file.attributes[0], Attribute::Unknown { name: "Marker".to_string(), data: vec![0, 4] });
+    }
+
+    #[test]
+    fn decoder_errors_are_located_in_the_class_file() {
+        // The contents of the Marker attribute are followed by the Other attribute
+        let marker_start = |class: &[u8], marker: &[u8]| class.len() - 6 - 2 * marker.len();
+
+        let class = class_with_markers(&[0, 4, 0]);
+        let error = read_class_file_with_options(&class, &marker_registry()).unwrap_err();
+        assert_eq!(error, ParsingError {
+            at_byte: marker_start(&class, &[0, 4, 0]) + 2,
+            kind: ParsingErrorKind::AttributeLengthMismatch { declared: 3, actual: 2 },
+            context: vec!["attributes[Marker]".to_string()],
+        });
+
+        let class = class_with_markers(&[0, 9]);
+        let error = read_class_file_with_options(&class, &marker_registry()).unwrap_err();
+        assert_eq!(error, ParsingError {
+            at_byte: marker_start(&class, &[0, 9]),
+            kind: ParsingErrorKind::InvalidConstantPoolIndex { index: 9 },
+            context: vec!["attributes[Marker]".to_string()],
+        });
+    }
+
+    #[test]
+    fn custom_attributes_are_written_back_from_their_data() {
+        let class = class_with_markers(&[0, 4]);
+        let mut class_file = read_class_file_with_options(&class, &marker_registry()).unwrap();
+        assert_eq!(crate::writer::write_class_file(&class_file).unwrap(), class);
+
+        // The raw data is written, and decides whether two values are equal
+        let original = class_file.clone();
+        match &mut class_file.attributes[0] {
+            Attribute::Custom { value, .. } => value.data = vec![0, 3],
+            attribute => panic!("{:?}", attribute),
+        }
+        assert_ne!(class_file, original);
+        let written = read_class_file_with_options(&crate::writer::write_class_file(&class_file).unwrap(), &marker_registry()).unwrap();
+        match &written.attributes[0] {
+            Attribute::Custom { value, .. } => assert_eq!(value.downcast_ref::<Marker>(), Some(&Marker("Marker".to_string()))),
+            attribute => panic!("{:?}", attribute),
+        }
+        assert_eq!(written, class_file);
+    }
+}
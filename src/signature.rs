@@ -0,0 +1,607 @@
+//! Parser for the generic signatures stored in `Signature` attributes, following the class, method
+//! and field signature grammars of JVMS §4.7.9.1.
+//!
+//! All types implement [`Display`](fmt::Display) and render as they would be written in java source,
+//! with fully qualified class names, e.g. `java.util.Map<K, java.util.List<? extends V>>`. The alternate
+//! form (`{:#}`) leaves out the packages: `Map<K, List<? extends V>>`.
+
+use std::fmt;
+
+use crate::types::{ParsingError, ParsingErrorKind};
+
+/// The signature of a class: its type parameters and generic super types
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub super_class: ClassType,
+    pub interfaces: Vec<ClassType>,
+}
+
+/// The signature of a method. `return_type` is `None` for `void` methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<TypeSignature>,
+    pub return_type: Option<TypeSignature>,
+    /// Class types or type variables
+    pub throws: Vec<ReferenceType>,
+}
+
+/// The signature of a field, record component or local variable
+pub type FieldSignature = ReferenceType;
+
+/// A type parameter like `T extends Number & Comparable<T>`. `class_bound` is `None` if the
+/// parameter is only bounded by interfaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<ReferenceType>,
+    pub interface_bounds: Vec<ReferenceType>,
+}
+
+/// Any type, as used by parameters and return types
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSignature {
+    Base(BaseType),
+    Reference(ReferenceType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaseType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReferenceType {
+    Class(ClassType),
+    TypeVariable(String),
+    Array(Box<TypeSignature>),
+}
+
+/// A possibly parameterized class type. Member classes of a parameterized class have one entry in
+/// `classes` per nesting level, e.g. `Outer<T>.Inner<U>`, otherwise there is a single entry whose
+/// name may contain `$`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassType {
+    /// The package in internal form, e.g. `java/util`, empty for the unnamed package
+    pub package: String,
+    pub classes: Vec<SimpleClassType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleClassType {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeArgument {
+    /// `?`
+    Wildcard,
+    Exact(ReferenceType),
+    /// `? extends T`
+    Extends(ReferenceType),
+    /// `? super T`
+    Super(ReferenceType),
+}
+
+impl BaseType {
+    /// The primitive type a descriptor character like `I` stands for
+    pub fn from_tag(tag: char) -> Option<BaseType> {
+        match tag {
+            'B' => Some(BaseType::Byte),
+            'C' => Some(BaseType::Char),
+            'D' => Some(BaseType::Double),
+            'F' => Some(BaseType::Float),
+            'I' => Some(BaseType::Int),
+            'J' => Some(BaseType::Long),
+            'S' => Some(BaseType::Short),
+            'Z' => Some(BaseType::Boolean),
+            _ => None
+        }
+    }
+}
+
+impl ClassType {
+    /// The binary name of the class, e.g. `java/util/Map$Entry`, without any type arguments
+    pub fn binary_name(&self) -> String {
+        let mut name = self.package.clone();
+        if !name.is_empty() {
+            name.push('/');
+        }
+        let classes: Vec<&str> = self.classes.iter().map(|class| class.name.as_str()).collect();
+        name.push_str(&classes.join("$"));
+        name
+    }
+
+    fn is_object(&self) -> bool {
+        self.package == "java/lang" && matches!(self.classes.as_slice(), [SimpleClassType { name, type_arguments }] if name == "Object" && type_arguments.is_empty())
+    }
+}
+
+pub fn parse_class_signature(signature: &str) -> Result<ClassSignature, ParsingError> {
+    let mut parser = Parser { signature, index: 0, depth: 0 };
+    let type_parameters = parser.type_parameters()?;
+    let super_class = parser.class_type()?;
+    let mut interfaces: Vec<ClassType> = Vec::new();
+    while !parser.at_end() {
+        interfaces.push(parser.class_type()?);
+    }
+    Ok(ClassSignature { type_parameters, super_class, interfaces })
+}
+
+pub fn parse_method_signature(signature: &str) -> Result<MethodSignature, ParsingError> {
+    let mut parser = Parser { signature, index: 0, depth: 0 };
+    let type_parameters = parser.type_parameters()?;
+    parser.expect('(')?;
+    let mut parameters: Vec<TypeSignature> = Vec::new();
+    while parser.peek() != Some(')') {
+        parameters.push(parser.type_signature()?);
+    }
+    parser.expect(')')?;
+    let return_type = if parser.peek() == Some('V') {
+        parser.index += 1;
+        None
+    } else {
+        Some(parser.type_signature()?)
+    };
+    let mut throws: Vec<ReferenceType> = Vec::new();
+    while !parser.at_end() {
+        parser.expect('^')?;
+        let exception = match parser.peek() {
+            Some('L') => ReferenceType::Class(parser.class_type()?),
+            Some('T') => parser.reference_type()?,
+            _ => return Err(parser.error()),
+        };
+        throws.push(exception);
+    }
+    Ok(MethodSignature { type_parameters, parameters, return_type, throws })
+}
+
+pub fn parse_field_signature(signature: &str) -> Result<FieldSignature, ParsingError> {
+    let mut parser = Parser { signature, index: 0, depth: 0 };
+    let field_signature = parser.reference_type()?;
+    if !parser.at_end() {
+        return Err(parser.error());
+    }
+    Ok(field_signature)
+}
+
+/// Type arguments can be nested arbitrarily deep. The depth is limited so that malicious input
+/// can't overflow the stack.
+const MAX_SIGNATURE_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    signature: &'a str,
+    /// Byte offset of the next character
+    index: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.signature[self.index..].chars().next()
+    }
+
+    fn at_end(&self) -> bool {
+        self.index == self.signature.len()
+    }
+
+    fn error(&self) -> ParsingError {
+        ParsingError::new(self.index, ParsingErrorKind::InvalidSignature { signature: self.signature.to_string() })
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParsingError> {
+        if self.peek() == Some(expected) {
+            self.index += expected.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// Reads an unqualified name, which ends at any of `. ; [ / < > :`
+    fn identifier(&mut self) -> Result<String, ParsingError> {
+        let rest = &self.signature[self.index..];
+        let length = rest.find(['.', ';', '[', '/', '<', '>', ':']).unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.error());
+        }
+        self.index += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, ParsingError> {
+        let mut type_parameters: Vec<TypeParameter> = Vec::new();
+        if self.peek() != Some('<') {
+            return Ok(type_parameters);
+        }
+        self.index += 1;
+        while self.peek() != Some('>') {
+            let name = self.identifier()?;
+            self.expect(':')?;
+            let class_bound = match self.peek() {
+                Some(':') => None,
+                _ => Some(self.reference_type()?),
+            };
+            let mut interface_bounds: Vec<ReferenceType> = Vec::new();
+            while self.peek() == Some(':') {
+                self.index += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+            type_parameters.push(TypeParameter { name, class_bound, interface_bounds });
+        }
+        self.index += 1;
+        if type_parameters.is_empty() {
+            return Err(self.error());
+        }
+        Ok(type_parameters)
+    }
+
+    fn type_signature(&mut self) -> Result<TypeSignature, ParsingError> {
+        match self.peek().and_then(BaseType::from_tag) {
+            Some(base_type) => {
+                self.index += 1;
+                Ok(TypeSignature::Base(base_type))
+            }
+            None => Ok(TypeSignature::Reference(self.reference_type()?)),
+        }
+    }
+
+    fn reference_type(&mut self) -> Result<ReferenceType, ParsingError> {
+        if self.depth >= MAX_SIGNATURE_DEPTH {
+            return Err(ParsingError::new(self.index, ParsingErrorKind::NestingTooDeep));
+        }
+        self.depth += 1;
+        let reference_type = match self.peek() {
+            Some('L') => ReferenceType::Class(self.class_type()?),
+            Some('T') => {
+                self.index += 1;
+                let name = self.identifier()?;
+                self.expect(';')?;
+                ReferenceType::TypeVariable(name)
+            }
+            Some('[') => {
+                self.index += 1;
+                ReferenceType::Array(Box::new(self.type_signature()?))
+            }
+            _ => return Err(self.error()),
+        };
+        self.depth -= 1;
+        Ok(reference_type)
+    }
+
+    fn class_type(&mut self) -> Result<ClassType, ParsingError> {
+        self.expect('L')?;
+        let mut package = String::new();
+        let mut name = self.identifier()?;
+        while self.peek() == Some('/') {
+            self.index += 1;
+            if !package.is_empty() {
+                package.push('/');
+            }
+            package.push_str(&name);
+            name = self.identifier()?;
+        }
+        let mut classes = vec![SimpleClassType { name, type_arguments: self.type_arguments()? }];
+        while self.peek() == Some('.') {
+            self.index += 1;
+            let name = self.identifier()?;
+            classes.push(SimpleClassType { name, type_arguments: self.type_arguments()? });
+        }
+        self.expect(';')?;
+        Ok(ClassType { package, classes })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>, ParsingError> {
+        let mut type_arguments: Vec<TypeArgument> = Vec::new();
+        if self.peek() != Some('<') {
+            return Ok(type_arguments);
+        }
+        self.index += 1;
+        while self.peek() != Some('>') {
+            let type_argument = match self.peek() {
+                Some('*') => {
+                    self.index += 1;
+                    TypeArgument::Wildcard
+                }
+                Some('+') => {
+                    self.index += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                Some('-') => {
+                    self.index += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            };
+            type_arguments.push(type_argument);
+        }
+        self.index += 1;
+        if type_arguments.is_empty() {
+            return Err(self.error());
+        }
+        Ok(type_arguments)
+    }
+}
+
+/// Writes `items` separated by `separator`, passing on the alternate flag
+fn write_separated<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T], separator: &str) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(separator)?;
+        }
+        if f.alternate() {
+            write!(f, "{:#}", item)?;
+        } else {
+            write!(f, "{}", item)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes type parameters like `<K, V>`, followed by a space if there are any
+fn write_type_parameters(f: &mut fmt::Formatter<'_>, type_parameters: &[TypeParameter]) -> fmt::Result {
+    if !type_parameters.is_empty() {
+        f.write_str("<")?;
+        write_separated(f, type_parameters, ", ")?;
+        f.write_str("> ")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ClassSignature {
+    /// Renders the part of a class declaration after its name, e.g. `<T> extends Base<T> implements Comparable<T>`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if !self.type_parameters.is_empty() {
+            let type_parameters: Vec<String> = self.type_parameters.iter()
+                .map(|type_parameter| if f.alternate() { format!("{:#}", type_parameter) } else { type_parameter.to_string() })
+                .collect();
+            parts.push(format!("<{}>", type_parameters.join(", ")));
+        }
+        if !self.super_class.is_object() {
+            parts.push(if f.alternate() { format!("extends {:#}", self.super_class) } else { format!("extends {}", self.super_class) });
+        }
+        if !self.interfaces.is_empty() {
+            let interfaces: Vec<String> = self.interfaces.iter()
+                .map(|interface| if f.alternate() { format!("{:#}", interface) } else { interface.to_string() })
+                .collect();
+            parts.push(format!("implements {}", interfaces.join(", ")));
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl MethodSignature {
+    /// Renders the signature as a method declaration, e.g. `<T> void sort(java.util.List<T>)`
+    pub fn with_name<'a>(&'a self, name: &'a str) -> MethodDeclaration<'a> {
+        MethodDeclaration { signature: self, name }
+    }
+}
+
+/// A method signature rendered together with the name of the method
+pub struct MethodDeclaration<'a> {
+    signature: &'a MethodSignature,
+    name: &'a str,
+}
+
+impl fmt::Display for MethodDeclaration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signature = self.signature;
+        write_type_parameters(f, &signature.type_parameters)?;
+        match &signature.return_type {
+            Some(return_type) if f.alternate() => write!(f, "{:#}", return_type)?,
+            Some(return_type) => write!(f, "{}", return_type)?,
+            None => f.write_str("void")?,
+        }
+        write!(f, " {}(", self.name)?;
+        write_separated(f, &signature.parameters, ", ")?;
+        f.write_str(")")?;
+        if !signature.throws.is_empty() {
+            f.write_str(" throws ")?;
+            write_separated(f, &signature.throws, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MethodSignature {
+    /// Renders the signature as a method declaration without a name, e.g. `<T> void (java.util.List<T>)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.with_name(""), f)
+    }
+}
+
+impl fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        let bounds: Vec<&ReferenceType> = self.class_bound.iter().chain(self.interface_bounds.iter()).collect();
+        // `T` is stored as `T extends Object`
+        if let [ReferenceType::Class(class)] = bounds.as_slice() {
+            if class.is_object() {
+                return Ok(());
+            }
+        }
+        if !bounds.is_empty() {
+            f.write_str(" extends ")?;
+            write_separated(f, &bounds, " & ")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSignature::Base(base_type) => fmt::Display::fmt(base_type, f),
+            TypeSignature::Reference(reference_type) => fmt::Display::fmt(reference_type, f),
+        }
+    }
+}
+
+impl fmt::Display for BaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BaseType::Byte => "byte",
+            BaseType::Char => "char",
+            BaseType::Double => "double",
+            BaseType::Float => "float",
+            BaseType::Int => "int",
+            BaseType::Long => "long",
+            BaseType::Short => "short",
+            BaseType::Boolean => "boolean",
+        })
+    }
+}
+
+impl fmt::Display for ReferenceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferenceType::Class(class) => fmt::Display::fmt(class, f),
+            ReferenceType::TypeVariable(name) => f.write_str(name),
+            ReferenceType::Array(component) => {
+                fmt::Display::fmt(component.as_ref(), f)?;
+                f.write_str("[]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ClassType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.package.is_empty() && !f.alternate() {
+            write!(f, "{}.", self.package.replace('/', "."))?;
+        }
+        write_separated(f, &self.classes, ".")
+    }
+}
+
+impl fmt::Display for SimpleClassType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.type_arguments.is_empty() {
+            f.write_str("<")?;
+            write_separated(f, &self.type_arguments, ", ")?;
+            f.write_str(">")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, bound) = match self {
+            TypeArgument::Wildcard => return f.write_str("?"),
+            TypeArgument::Exact(bound) => ("", bound),
+            TypeArgument::Extends(bound) => ("? extends ", bound),
+            TypeArgument::Super(bound) => ("? super ", bound),
+        };
+        f.write_str(prefix)?;
+        fmt::Display::fmt(bound, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(package: &str, name: &str) -> ReferenceType {
+        ReferenceType::Class(ClassType { package: package.to_string(), classes: vec![SimpleClassType { name: name.to_string(), type_arguments: Vec::new() }] })
+    }
+
+    #[test]
+    fn class_signature_with_bounds() {
+        let signature = parse_class_signature("<T:Ljava/lang/Number;:Ljava/lang/Comparable<TT;>;U::Ljava/lang/Runnable;>Ljava/util/AbstractList<TT;>;Ljava/io/Serializable;").unwrap();
+        assert_eq!(signature.type_parameters.len(), 2);
+        assert_eq!(signature.type_parameters[0].class_bound, Some(class("java/lang", "Number")));
+        assert_eq!(signature.type_parameters[1], TypeParameter {
+            name: "U".to_string(),
+            class_bound: None,
+            interface_bounds: vec![class("java/lang", "Runnable")],
+        });
+        assert_eq!(signature.super_class.binary_name(), "java/util/AbstractList");
+        assert_eq!(signature.interfaces.len(), 1);
+
+        assert_eq!(signature.to_string(), "<T extends java.lang.Number & java.lang.Comparable<T>, U extends java.lang.Runnable> extends java.util.AbstractList<T> implements java.io.Serializable");
+        assert_eq!(format!("{:#}", signature), "<T extends Number & Comparable<T>, U extends Runnable> extends AbstractList<T> implements Serializable");
+
+        // An unbounded parameter and the Object super class are left out
+        let signature = parse_class_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;").unwrap();
+        assert_eq!(signature.to_string(), "<T>");
+    }
+
+    #[test]
+    fn wildcards() {
+        let signature = parse_field_signature("Lp/Foo<*+Ljava/lang/Number;-TK;[I>;").unwrap();
+        let class_type = match &signature {
+            ReferenceType::Class(class_type) => class_type,
+            _ => panic!("{:?}", signature),
+        };
+        assert_eq!(class_type.classes[0].type_arguments, vec![
+            TypeArgument::Wildcard,
+            TypeArgument::Extends(class("java/lang", "Number")),
+            TypeArgument::Super(ReferenceType::TypeVariable("K".to_string())),
+            TypeArgument::Exact(ReferenceType::Array(Box::new(TypeSignature::Base(BaseType::Int)))),
+        ]);
+        assert_eq!(signature.to_string(), "p.Foo<?, ? extends java.lang.Number, ? super K, int[]>");
+        assert_eq!(format!("{:#}", signature), "Foo<?, ? extends Number, ? super K, int[]>");
+    }
+
+    #[test]
+    fn inner_classes() {
+        let signature = parse_field_signature("Lp/Outer<TT;>.Inner<TU;>.Deep;").unwrap();
+        let class_type = match &signature {
+            ReferenceType::Class(class_type) => class_type,
+            _ => panic!("{:?}", signature),
+        };
+        assert_eq!(class_type.classes.len(), 3);
+        assert_eq!(class_type.binary_name(), "p/Outer$Inner$Deep");
+        assert_eq!(signature.to_string(), "p.Outer<T>.Inner<U>.Deep");
+        assert_eq!(format!("{:#}", signature), "Outer<T>.Inner<U>.Deep");
+    }
+
+    #[test]
+    fn method_signature_with_throws() {
+        let signature = parse_method_signature("<X:Ljava/lang/Exception;>(I[JLjava/util/List<TX;>;)V^TX;^Ljava/io/IOException;").unwrap();
+        assert_eq!(signature.return_type, None);
+        assert_eq!(signature.parameters.len(), 3);
+        assert_eq!(signature.throws, vec![ReferenceType::TypeVariable("X".to_string()), class("java/io", "IOException")]);
+        assert_eq!(signature.with_name("run").to_string(), "<X extends java.lang.Exception> void run(int, long[], java.util.List<X>) throws X, java.io.IOException");
+        assert_eq!(format!("{:#}", signature.with_name("run")), "<X extends Exception> void run(int, long[], List<X>) throws X, IOException");
+
+        let signature = parse_method_signature("()[TT;").unwrap();
+        assert_eq!(signature.with_name("get").to_string(), "T[] get()");
+    }
+
+    #[test]
+    fn malformed_signatures() {
+        for signature in ["", "Ljava/lang/Object", "TT", "Lp/A<>;", "[", "I", "Ljava/lang/Object;;"] {
+            let error = parse_field_signature(signature).unwrap_err();
+            assert!(matches!(error.kind, ParsingErrorKind::InvalidSignature { .. }), "{:?}", signature);
+        }
+        for signature in ["(V)V", "()", "()V^I", "()V^", "<>()V", "<T>()V"] {
+            assert!(parse_method_signature(signature).is_err(), "{:?}", signature);
+        }
+        assert!(parse_class_signature("<T:>Ljava/lang/Object;").is_err());
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let arrays = "[".repeat(MAX_SIGNATURE_DEPTH);
+        assert!(parse_field_signature(&format!("{}I", arrays)).is_ok());
+        let error = parse_field_signature(&format!("[{}I", arrays)).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::NestingTooDeep);
+
+        let type_arguments = format!("{}Lp/A;{};", "Lp/A<".repeat(1000), ">;".repeat(1000));
+        let error = parse_field_signature(&type_arguments).unwrap_err();
+        assert_eq!(error.kind, ParsingErrorKind::NestingTooDeep);
+    }
+}
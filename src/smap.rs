@@ -0,0 +1,257 @@
+//! Decoding of source maps (SMAP) as defined by JSR-45. Compilers of languages other than java, e.g.
+//! for Kotlin inline functions or JSPs, store them in the `SourceDebugExtension` attribute to map the
+//! lines of the generated class back to the files they came from.
+
+use crate::types::{ParsingError, ParsingErrorKind};
+
+/// A resolved SMAP. Embedded SMAPs (`*O` to `*C` sections) are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smap {
+    /// The name of the generated source file, e.g. `Foo.java`
+    pub output_file: String,
+    /// The stratum used when none is asked for explicitly
+    pub default_stratum: String,
+    pub strata: Vec<Stratum>,
+}
+
+/// The mapping from the output lines to the lines of one source language
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stratum {
+    pub name: String,
+    pub files: Vec<SmapFile>,
+    pub lines: Vec<SmapLine>,
+}
+
+/// An entry of a file section
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmapFile {
+    pub id: u32,
+    pub name: String,
+    /// The path of the file relative to the source root, if it was recorded
+    pub path: Option<String>,
+}
+
+/// An entry of a line section. Input line `input_start_line + i` maps to the `output_line_increment`
+/// output lines starting at `output_start_line + i * output_line_increment`, for each `i` below
+/// `repeat_count`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmapLine {
+    pub input_start_line: u32,
+    pub file_id: u32,
+    pub repeat_count: u32,
+    pub output_start_line: u32,
+    pub output_line_increment: u32,
+}
+
+/// A line in a source file, as found by [`Smap::map_line`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub path: Option<String>,
+    pub line: u32,
+}
+
+impl Smap {
+    pub fn stratum(&self, name: &str) -> Option<&Stratum> {
+        self.strata.iter().find(|stratum| stratum.name == name)
+    }
+
+    /// Maps a line of the output file, e.g. from a `LineNumberTable`, to its source using the default stratum
+    pub fn map_line(&self, output_line: u32) -> Option<SourceLocation> {
+        self.stratum(&self.default_stratum)?.map_line(output_line)
+    }
+}
+
+impl Stratum {
+    /// Maps a line of the output file to its source. Returns `None` if no line section covers it.
+    pub fn map_line(&self, output_line: u32) -> Option<SourceLocation> {
+        self.lines.iter().find_map(|line| {
+            let offset = output_line.checked_sub(line.output_start_line)?;
+            let repetition = offset.checked_div(line.output_line_increment)?;
+            if repetition >= line.repeat_count {
+                return None;
+            }
+            let file = self.files.iter().find(|file| file.id == line.file_id)?;
+            Some(SourceLocation {
+                file: file.name.clone(),
+                path: file.path.clone(),
+                line: line.input_start_line.checked_add(repetition)?,
+            })
+        })
+    }
+}
+
+enum Section {
+    Files,
+    Lines,
+    Ignored,
+}
+
+/// Parses the contents of a `SourceDebugExtension` attribute. Errors point to the byte offset of
+/// the offending line.
+pub fn parse_smap(smap: &str) -> Result<Smap, ParsingError> {
+    let mut lines = Lines { rest: smap, offset: 0, number: 0 };
+
+    let (at_byte, header) = lines.next_line()?;
+    if header != "SMAP" {
+        return Err(lines.error(at_byte));
+    }
+    let (_, output_file) = lines.next_line()?;
+    let (_, default_stratum) = lines.next_line()?;
+
+    let mut strata: Vec<Stratum> = Vec::new();
+    let mut section = Section::Ignored;
+    let mut file_id: u32 = 0;
+    loop {
+        let (at_byte, line) = lines.next_line()?;
+        if let Some(marker) = line.strip_prefix('*') {
+            match marker.split_whitespace().next() {
+                Some("S") => {
+                    let name = marker.trim_start().strip_prefix('S').map_or("", str::trim);
+                    if name.is_empty() {
+                        return Err(lines.error(at_byte));
+                    }
+                    strata.push(Stratum { name: name.to_string(), files: Vec::new(), lines: Vec::new() });
+                    section = Section::Ignored;
+                    file_id = 0;
+                }
+                Some("F") => section = Section::Files,
+                Some("L") => section = Section::Lines,
+                Some("E") => break,
+                Some("O") => lines.skip_embedded()?,
+                // vendor sections and unknown sections are skipped
+                _ => section = Section::Ignored,
+            }
+            continue;
+        }
+
+        match section {
+            Section::Files => {
+                let stratum = strata.last_mut().ok_or_else(|| lines.error(at_byte))?;
+                let (entry, has_path) = match line.strip_prefix('+') {
+                    Some(entry) => (entry, true),
+                    None => (line, false),
+                };
+                let mut parts = entry.trim_start().splitn(2, ' ');
+                let id = parts.next().and_then(|id| id.parse::<u32>().ok()).ok_or_else(|| lines.error(at_byte))?;
+                let name = parts.next().map(str::trim).filter(|name| !name.is_empty()).ok_or_else(|| lines.error(at_byte))?;
+                let path = if has_path { Some(lines.next_line()?.1.to_string()) } else { None };
+                stratum.files.push(SmapFile { id, name: name.to_string(), path });
+            }
+            Section::Lines => {
+                let stratum = strata.last_mut().ok_or_else(|| lines.error(at_byte))?;
+                let line_info = parse_line_info(line, file_id).ok_or_else(|| lines.error(at_byte))?;
+                file_id = line_info.file_id;
+                stratum.lines.push(line_info);
+            }
+            Section::Ignored => {}
+        }
+    }
+
+    Ok(Smap { output_file: output_file.to_string(), default_stratum: default_stratum.to_string(), strata })
+}
+
+/// Parses `InputStartLine[#LineFileID][,RepeatCount]:OutputStartLine[,OutputLineIncrement]`. The file
+/// id defaults to the one of the previous line.
+fn parse_line_info(line: &str, previous_file_id: u32) -> Option<SmapLine> {
+    let (input, output) = line.split_once(':')?;
+    let (input, repeat_count) = match input.split_once(',') {
+        Some((input, repeat_count)) => (input, repeat_count.trim().parse().ok()?),
+        None => (input, 1),
+    };
+    let (input_start_line, file_id) = match input.split_once('#') {
+        Some((input_start_line, file_id)) => (input_start_line.trim().parse().ok()?, file_id.trim().parse().ok()?),
+        None => (input.trim().parse().ok()?, previous_file_id),
+    };
+    let (output_start_line, output_line_increment) = match output.split_once(',') {
+        Some((output_start_line, increment)) => (output_start_line.trim().parse().ok()?, increment.trim().parse().ok()?),
+        None => (output.trim().parse().ok()?, 1),
+    };
+    Some(SmapLine { input_start_line, file_id, repeat_count, output_start_line, output_line_increment })
+}
+
+/// Splits the SMAP into lines, keeping track of where each line starts
+struct Lines<'a> {
+    rest: &'a str,
+    offset: usize,
+    number: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// Returns the next line and the byte offset it starts at. Fails at the end of the input, since
+    /// a SMAP is always terminated by a `*E` line.
+    fn next_line(&mut self) -> Result<(usize, &'a str), ParsingError> {
+        if self.rest.is_empty() {
+            return Err(self.error(self.offset));
+        }
+        let at_byte = self.offset;
+        let (line, rest) = match self.rest.find('\n') {
+            Some(end) => (&self.rest[..end], &self.rest[end + 1..]),
+            None => (self.rest, ""),
+        };
+        self.offset += self.rest.len() - rest.len();
+        self.rest = rest;
+        self.number += 1;
+        Ok((at_byte, line.strip_suffix('\r').unwrap_or(line)))
+    }
+
+    /// Skips an embedded SMAP up to and including the `*C` line that closes it
+    fn skip_embedded(&mut self) -> Result<(), ParsingError> {
+        let mut depth = 1;
+        while depth > 0 {
+            let (_, line) = self.next_line()?;
+            if line.starts_with("*O") {
+                depth += 1;
+            } else if line.starts_with("*C") {
+                depth -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// An error for the line most recently returned
+    fn error(&self, at_byte: usize) -> ParsingError {
+        ParsingError::new(at_byte, ParsingErrorKind::InvalidSmap { line: self.number })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOTLIN_SMAP: &str = "SMAP\nMain.kt\nKotlin\n*S Kotlin\n*F\n+ 1 Main.kt\nMainKt\n+ 2 Inline.kt\nInlineKt\n*L\n1#1,10:1\n5#2,2:11,2\n*E\n";
+
+    #[test]
+    fn parses_strata_files_and_lines() {
+        let smap = parse_smap(KOTLIN_SMAP).unwrap();
+        assert_eq!((smap.output_file.as_str(), smap.default_stratum.as_str()), ("Main.kt", "Kotlin"));
+        assert_eq!(smap.strata.len(), 1);
+        let stratum = &smap.strata[0];
+        assert_eq!(stratum.name, "Kotlin");
+        assert_eq!(stratum.files[1], SmapFile { id: 2, name: "Inline.kt".to_string(), path: Some("InlineKt".to_string()) });
+        assert_eq!(stratum.lines.len(), 2);
+
+        let location = smap.map_line(13).unwrap();
+        assert_eq!((location.file.as_str(), location.line), ("Inline.kt", 6));
+        assert_eq!(smap.map_line(15), None);
+    }
+
+    #[test]
+    fn stratum_names_after_whitespace() {
+        let smap = parse_smap("SMAP\nA.kt\nKotlin\n*  S   Kotlin  \n*E\n").unwrap();
+        assert_eq!(smap.strata[0].name, "Kotlin");
+
+        // Multi-byte whitespace before the section marker
+        let smap = parse_smap("SMAP\nA.kt\nx\n*\u{3000}S x\n*E\n").unwrap();
+        assert_eq!(smap.strata[0].name, "x");
+
+        let error = parse_smap("SMAP\nA.kt\nx\n*S \n*E\n").unwrap_err();
+        assert_eq!((error.at_byte, error.kind), (12, ParsingErrorKind::InvalidSmap { line: 4 }));
+    }
+
+    #[test]
+    fn missing_end_marker() {
+        let error = parse_smap("SMAP\nA.kt\nKotlin\n*S Kotlin\n").unwrap_err();
+        assert!(matches!(error.kind, ParsingErrorKind::InvalidSmap { .. }));
+    }
+}
@@ -1,34 +1,480 @@
-#[derive(Debug)]
-pub struct ClassFile<'a> {
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::constant_pool::ConstantPoolReferences;
+use crate::descriptor::{parse_field_descriptor, parse_method_descriptor, FieldType, MethodDescriptor};
+use crate::mutf8;
+use crate::reader::read_dynamic_from_constant_pool;
+use crate::signature::{parse_class_signature, parse_field_signature, parse_method_signature, ClassSignature, FieldSignature, MethodSignature};
+use crate::smap::{parse_smap, Smap, SourceLocation};
+
+/// A parsed class, or one to be written. Classes that aren't parsed can be started with
+/// [`ClassFile::new`].
+#[derive(Debug, Clone)]
+pub struct ClassFile {
     pub magic: u32,
     pub minor_version: u16,
     pub major_version: u16,
-    pub constant_pool: &'a Vec<ConstantPoolEntry>,
+    pub constant_pool: ConstantPool,
+    /// The entries a parsed class referred to. The writer refers to the same entries again as long as
+    /// they hold the same values, which keeps duplicate entries apart. Empty for classes that weren't
+    /// parsed.
+    pub(crate) constant_pool_references: ConstantPoolReferences,
     pub access_flags: Vec<AccessFlag>,
     pub this_class: Class,
-    pub super_class: Class,
+    /// `None` for `java/lang/Object` and `module-info`, which have no super class
+    pub super_class: Option<Class>,
     pub interfaces: Vec<Class>,
-    pub fields: Vec<Field<'a>>,
-    pub methods: Vec<Method<'a>>,
-    pub attributes: Vec<Attribute<'a>>,
+    pub fields: Vec<Field>,
+    pub methods: Vec<Method>,
+    pub attributes: Vec<Attribute>,
     pub parsed_bytes: usize
 }
 
-#[derive(Debug, Clone)]
+// ClassFile owns all of its data, so it can be stored and shared between threads freely
+const _: fn() = || {
+    fn assert_owned<T: Send + Sync + 'static>() {}
+    assert_owned::<ClassFile>();
+};
+
+// Two classes are equal if they hold the same values, whichever of several equal constant pool
+// entries they refer to
+impl PartialEq for ClassFile {
+    fn eq(&self, other: &ClassFile) -> bool {
+        let ClassFile {
+            magic, minor_version, major_version, constant_pool, constant_pool_references: _, access_flags,
+            this_class, super_class, interfaces, fields, methods, attributes, parsed_bytes,
+        } = self;
+        *magic == other.magic && *minor_version == other.minor_version && *major_version == other.major_version
+            && *constant_pool == other.constant_pool && *access_flags == other.access_flags
+            && *this_class == other.this_class && *super_class == other.super_class
+            && *interfaces == other.interfaces && *fields == other.fields && *methods == other.methods
+            && *attributes == other.attributes && *parsed_bytes == other.parsed_bytes
+    }
+}
+
+impl ClassFile {
+    /// A class without flags, members or attributes, with an empty constant pool that the writer
+    /// fills in
+    pub fn new(major_version: u16, this_class: Class, super_class: Option<Class>) -> ClassFile {
+        ClassFile {
+            magic: 0xCAFEBABE,
+            minor_version: 0,
+            major_version,
+            constant_pool: Vec::new(),
+            constant_pool_references: ConstantPoolReferences::default(),
+            access_flags: Vec::new(),
+            this_class,
+            super_class,
+            interfaces: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            attributes: Vec::new(),
+            parsed_bytes: 0,
+        }
+    }
+
+    pub fn source_file(&self) -> Option<&str> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::SourceFile { source_file } => Some(source_file.as_str()),
+            _ => None
+        })
+    }
+
+    pub fn inner_classes(&self) -> &[InnerClass] {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::InnerClasses { classes } => Some(classes.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    /// Turns a binary class name like `a/b/Outer$Inner` into the name used in java source, like
+    /// `a.b.Outer.Inner`. Member classes are resolved using the `InnerClasses` attribute, because `$` is
+    /// also allowed as part of a regular class name.
+    pub fn java_name(&self, binary_name: &str) -> String {
+        let inner_classes = self.inner_classes();
+        let mut simple_names: Vec<&str> = Vec::new();
+        let mut name = binary_name;
+        // The limit guards against cycles in malformed attributes
+        while simple_names.len() < inner_classes.len() {
+            let member = inner_classes.iter().find_map(|inner_class| match inner_class {
+                InnerClass { inner_class, outer_class: Some(outer_class), inner_name: Some(inner_name), .. } if inner_class.name == name => {
+                    Some((outer_class.name.as_str(), inner_name.as_str()))
+                }
+                _ => None
+            });
+            match member {
+                Some((outer_name, inner_name)) => {
+                    simple_names.push(inner_name);
+                    name = outer_name;
+                }
+                None => break
+            }
+        }
+        let mut java_name = name.replace('/', ".");
+        for simple_name in simple_names.iter().rev() {
+            java_name.push('.');
+            java_name.push_str(simple_name);
+        }
+        java_name
+    }
+
+    /// The generic signature of the class, `None` if it has no `Signature` attribute
+    pub fn signature(&self) -> Option<Result<ClassSignature, ParsingError>> {
+        find_signature(&self.attributes).map(parse_class_signature)
+    }
+
+    pub fn source_debug_extension(&self) -> Option<&[u8]> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::SourceDebugExtension { debug_extension } => Some(debug_extension.as_slice()),
+            _ => None
+        })
+    }
+
+    /// Decodes the SMAP stored in the `SourceDebugExtension` attribute, `None` if the class has none
+    pub fn smap(&self) -> Option<Result<Smap, ParsingError>> {
+        self.source_debug_extension().map(|debug_extension| parse_smap(&mutf8::decode_lossy(debug_extension)))
+    }
+
+    /// The source file and line the instruction at `pc` of `method` was compiled from. Lines are
+    /// mapped through the SMAP if the class has one, so e.g. code inlined from another Kotlin file
+    /// is attributed to that file.
+    pub fn source_location(&self, method: &Method, pc: u16) -> Option<SourceLocation> {
+        let line = method.line_number(pc)? as u32;
+        if let Some(Ok(smap)) = self.smap() {
+            if let Some(location) = smap.map_line(line) {
+                return Some(location);
+            }
+        }
+        Some(SourceLocation { file: self.source_file()?.to_string(), path: None, line })
+    }
+
+    /// The components of a record class, `None` if the class isn't a record
+    pub fn record_components(&self) -> Option<&[RecordComponent]> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Record { components } => Some(components.as_slice()),
+            _ => None
+        })
+    }
+
+    /// The classes allowed to extend or implement a sealed class, empty if the class isn't sealed
+    pub fn permitted_subclasses(&self) -> &[Class] {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::PermittedSubclasses { classes } => Some(classes.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    pub fn module(&self) -> Option<&Module> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Module { module } => Some(module),
+            _ => None
+        })
+    }
+
+    /// The packages of the module in internal form, e.g. `java/util`
+    pub fn module_packages(&self) -> &[String] {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::ModulePackages { packages } => Some(packages.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    pub fn module_main_class(&self) -> Option<&Class> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::ModuleMainClass { main_class } => Some(main_class),
+            _ => None
+        })
+    }
+
+    pub fn bootstrap_methods(&self) -> &[BootstrapMethod] {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::BootstrapMethods { bootstrap_methods } => Some(bootstrap_methods.as_slice()),
+            _ => None
+        }).unwrap_or(&[])
+    }
+
+    /// Looks up the bootstrap method of a Dynamic or InvokeDynamic entry, e.g. the operand of an
+    /// `invokedynamic` instruction. Returns `None` if the class has no such bootstrap method.
+    pub fn call_site(&self, dynamic: &DynamicRef) -> Option<CallSite> {
+        let bootstrap_method = self.bootstrap_methods().get(dynamic.bootstrap_method_attr_index as usize)?;
+        Some(CallSite {
+            bootstrap_method: bootstrap_method.method.clone(),
+            arguments: bootstrap_method.arguments.clone(),
+            name: dynamic.name.clone(),
+            descriptor: dynamic.descriptor.clone(),
+        })
+    }
+
+    /// Resolves every InvokeDynamic entry of the constant pool, in constant pool order
+    pub fn call_sites(&self) -> Result<Vec<CallSite>, ParsingError> {
+        let mut call_sites: Vec<CallSite> = Vec::new();
+        for (i, entry) in self.constant_pool.iter().enumerate() {
+            if let ConstantPoolEntry::InvokeDynamicInfo { bootstrap_method_attr_index, .. } = entry {
+                let index = (i + 1) as u16;
+                let dynamic = read_dynamic_from_constant_pool(&self.constant_pool, index, 0)?;
+                let call_site = self.call_site(&dynamic).ok_or_else(|| {
+                    ParsingError::new(0, ParsingErrorKind::InvalidBootstrapMethodIndex { index: *bootstrap_method_attr_index })
+                })?;
+                call_sites.push(call_site);
+            }
+        }
+        Ok(call_sites)
+    }
+
+    pub fn find_field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn find_method(&self, name: &str, descriptor: &str) -> Option<&Method> {
+        self.methods.iter().find(|method| method.name == name && method.descriptor == descriptor)
+    }
+
+    pub fn find_methods<'s>(&'s self, name: &'s str) -> impl Iterator<Item=&'s Method> {
+        self.methods.iter().filter(move |method| method.name == name)
+    }
+}
+
+fn find_signature(attributes: &[Attribute]) -> Option<&str> {
+    attributes.iter().find_map(|attr| match attr {
+        Attribute::Signature { signature } => Some(signature.as_str()),
+        _ => None
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParsingError {
     pub at_byte: usize,
-    pub message: String,
+    pub kind: ParsingErrorKind,
+    /// Where in the class file structure the error occurred, outermost element first,
+    /// e.g. `["methods[3]", "attributes[Code]", "exception_table[1]"]`
+    pub context: Vec<String>,
 }
 
 impl ParsingError {
-    pub fn new(at_byte: usize, msg: &str) -> ParsingError {
-        ParsingError { at_byte, message: msg.to_string() }
+    pub fn new(at_byte: usize, kind: ParsingErrorKind) -> ParsingError {
+        ParsingError { at_byte, kind, context: Vec::new() }
+    }
+
+    /// Marks the error as having occurred inside of `segment`. Called while the error propagates
+    /// outwards, so each segment is prepended to the path.
+    pub fn with_context<S: Into<String>>(mut self, segment: S) -> ParsingError {
+        self.context.insert(0, segment.into());
+        self
+    }
+
+    /// The context rendered as a path like `methods[3].attributes[Code].exception_table[1]`
+    pub fn context_path(&self) -> String {
+        self.context.join(".")
     }
 }
 
-pub type ConstantPool = Vec<ConstantPoolEntry>;
+impl fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}", self.at_byte)?;
+        if !self.context.is_empty() {
+            write!(f, " in {}", self.context_path())?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for ParsingError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsingErrorKind {
+    /// The input ended although `needed` more bytes were expected
+    UnexpectedEndOfInput { needed: usize },
+    InvalidMagic { magic: u32 },
+    /// The index is 0, past the end of the constant pool or points to the unusable slot after a Long or Double
+    InvalidConstantPoolIndex { index: u16 },
+    /// The entry at `index` isn't of the kind the structure referencing it requires
+    WrongConstantPoolTag { index: u16, expected: &'static str },
+    UnknownConstantPoolTag { tag: u8 },
+    InvalidUtf8,
+    UnknownElementValueTag { tag: u8 },
+    /// The length declared in an attribute header doesn't match the length of its contents
+    AttributeLengthMismatch { declared: u32, actual: usize },
+    /// Attributes, element values or type arguments are nested deeper than the parser supports
+    NestingTooDeep,
+    UnknownOpcode { opcode: u8 },
+    /// A branch or switch offset points before the start of the code or past the largest possible pc
+    InvalidBranchTarget { offset: i32 },
+    UnknownArrayType { atype: u8 },
+    /// A tableswitch whose low bound is larger than its high bound, or a lookupswitch with a negative number of pairs
+    InvalidSwitch,
+    UnknownReferenceKind { kind: u8 },
+    /// The code array is longer than the 65535 bytes a pc can address
+    CodeTooLong { length: usize },
+    UnknownStackMapFrameType { frame_type: u8 },
+    UnknownVerificationType { tag: u8 },
+    /// The offset delta of a stack map frame moves its pc past the largest possible pc
+    InvalidFrameOffset { offset_delta: u16 },
+    /// A Dynamic or InvokeDynamic entry refers to a bootstrap method the `BootstrapMethods` attribute doesn't have
+    InvalidBootstrapMethodIndex { index: u16 },
+    UnknownTargetType { target_type: u8 },
+    UnknownTypePathKind { kind: u8 },
+    /// The SMAP of a `SourceDebugExtension` is malformed in the given line, counting from 1
+    InvalidSmap { line: usize },
+    /// A generic signature doesn't follow the signature grammar. `at_byte` is the offset into the signature.
+    InvalidSignature { signature: String },
+    /// A field or method descriptor is malformed. `at_byte` is the offset into the descriptor.
+    InvalidDescriptor { descriptor: String },
+    /// An instruction pops more values than the operand stack holds
+    StackUnderflow,
+    /// The operand stack grows beyond the 65535 entries `max_stack` can declare
+    StackOverflow,
+    /// Two control flow paths reach an instruction with different operand stack heights
+    InconsistentStackHeight { expected: u16, actual: u16 },
+    /// A branch or exception handler leads to a pc that isn't the start of an instruction
+    InvalidControlFlow { target: usize },
+    /// The last instruction of the code is followed by another one, e.g. it isn't a return or `goto`
+    FallsOffEnd,
+}
+
+impl fmt::Display for ParsingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsingErrorKind::UnexpectedEndOfInput { needed } => write!(f, "Unexpected end of input, expected {} more bytes", needed),
+            ParsingErrorKind::InvalidMagic { magic } => write!(f, "Invalid magic 0x{:X}", magic),
+            ParsingErrorKind::InvalidConstantPoolIndex { index } => write!(f, "Invalid Constant Pool Index {}", index),
+            ParsingErrorKind::WrongConstantPoolTag { index, expected } => write!(f, "Expected {} Constant Pool Entry at index {}", expected, index),
+            ParsingErrorKind::UnknownConstantPoolTag { tag } => write!(f, "Invalid Constant Pool Tag {}", tag),
+            ParsingErrorKind::InvalidUtf8 => write!(f, "Invalid Utf8"),
+            ParsingErrorKind::UnknownElementValueTag { tag } => write!(f, "Invalid Element Value Tag {:?}", *tag as char),
+            ParsingErrorKind::AttributeLengthMismatch { declared, actual } => write!(f, "Attribute declares a length of {} bytes but has {}", declared, actual),
+            ParsingErrorKind::NestingTooDeep => write!(f, "Attributes, Element Values or Signature nested too deeply"),
+            ParsingErrorKind::UnknownOpcode { opcode } => write!(f, "Invalid Opcode 0x{:02X}", opcode),
+            ParsingErrorKind::InvalidBranchTarget { offset } => write!(f, "Invalid Branch Offset {}", offset),
+            ParsingErrorKind::UnknownArrayType { atype } => write!(f, "Invalid Array Type {}", atype),
+            ParsingErrorKind::InvalidSwitch => write!(f, "Invalid Switch"),
+            ParsingErrorKind::UnknownReferenceKind { kind } => write!(f, "Invalid Method Handle Reference Kind {}", kind),
+            ParsingErrorKind::CodeTooLong { length } => write!(f, "Code is {} bytes long, but may be at most 65535", length),
+            ParsingErrorKind::UnknownStackMapFrameType { frame_type } => write!(f, "Invalid Stack Map Frame Type {}", frame_type),
+            ParsingErrorKind::UnknownVerificationType { tag } => write!(f, "Invalid Verification Type Tag {}", tag),
+            ParsingErrorKind::InvalidFrameOffset { offset_delta } => write!(f, "Invalid Stack Map Frame Offset Delta {}", offset_delta),
+            ParsingErrorKind::InvalidBootstrapMethodIndex { index } => write!(f, "Invalid Bootstrap Method Index {}", index),
+            ParsingErrorKind::UnknownTargetType { target_type } => write!(f, "Invalid Type Annotation Target Type 0x{:02X}", target_type),
+            ParsingErrorKind::UnknownTypePathKind { kind } => write!(f, "Invalid Type Path Kind {}", kind),
+            ParsingErrorKind::InvalidSmap { line } => write!(f, "Invalid SMAP in line {}", line),
+            ParsingErrorKind::InvalidSignature { signature } => write!(f, "Invalid Signature {:?}", signature),
+            ParsingErrorKind::InvalidDescriptor { descriptor } => write!(f, "Invalid Descriptor {:?}", descriptor),
+            ParsingErrorKind::StackUnderflow => write!(f, "Operand Stack Underflow"),
+            ParsingErrorKind::StackOverflow => write!(f, "Operand Stack exceeds 65535 entries"),
+            ParsingErrorKind::InconsistentStackHeight { expected, actual } => write!(f, "Inconsistent Stack Height {} != {}", expected, actual),
+            ParsingErrorKind::InvalidControlFlow { target } => write!(f, "Control flows to pc {}, which is not the start of an instruction", target),
+            ParsingErrorKind::FallsOffEnd => write!(f, "Control falls off the end of the code"),
+        }
+    }
+}
+
+/// Error returned when a [`ClassFile`] can't be turned back into bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct WritingError {
+    pub kind: WritingErrorKind,
+    /// Where in the class file structure the error occurred, like [`ParsingError::context`]
+    pub context: Vec<String>,
+}
+
+impl WritingError {
+    pub fn new(kind: WritingErrorKind) -> WritingError {
+        WritingError { kind, context: Vec::new() }
+    }
+
+    /// Marks the error as having occurred inside of `segment`, see [`ParsingError::with_context`]
+    pub fn with_context<S: Into<String>>(mut self, segment: S) -> WritingError {
+        self.context.insert(0, segment.into());
+        self
+    }
+
+    pub fn context_path(&self) -> String {
+        self.context.join(".")
+    }
+}
 
+impl fmt::Display for WritingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.context.is_empty() {
+            write!(f, "in {}: ", self.context_path())?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for WritingError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WritingErrorKind {
+    /// A table, string or attribute has more elements than its length field can count
+    TooLarge { length: usize, max: usize },
+    /// A stack map frame doesn't come after the previous one, or its type can't express its contents
+    InvalidStackMapFrame { pc: u16 },
+    /// Adding an entry would take the constant pool past the 65534 slots it can have
+    ConstantPoolOverflow,
+    /// The target of a branch is too far away from its pc for the branch's 16 bit offset
+    BranchOutOfRange { pc: u16, target: u16 },
+    /// The operands of an instruction don't fit together, e.g. a branch instruction was expected,
+    /// or the targets of a tableswitch don't match its bounds
+    InvalidInstruction { mnemonic: &'static str },
+    /// A label was used in code but never placed, or placed more than once
+    InvalidLabel { label: usize },
+}
+
+impl fmt::Display for WritingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WritingErrorKind::TooLarge { length, max } => write!(f, "Length {} exceeds the maximum of {}", length, max),
+            WritingErrorKind::InvalidStackMapFrame { pc } => write!(f, "Invalid Stack Map Frame at pc {}", pc),
+            WritingErrorKind::ConstantPoolOverflow => write!(f, "Constant Pool has too many entries"),
+            WritingErrorKind::BranchOutOfRange { pc, target } => write!(f, "Branch at pc {} can't reach pc {}", pc, target),
+            WritingErrorKind::InvalidInstruction { mnemonic } => write!(f, "Invalid Operands for {}", mnemonic),
+            WritingErrorKind::InvalidLabel { label } => write!(f, "Label {} is not placed exactly once", label),
+        }
+    }
+}
+
+/// Error returned when a class file can't be loaded, either because it couldn't be read or because it
+/// isn't a valid class file.
 #[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Parsing(ParsingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "could not read class file: {}", err),
+            Error::Parsing(err) => write!(f, "error while parsing class file {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Parsing(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<ParsingError> for Error {
+    fn from(err: ParsingError) -> Self {
+        Error::Parsing(err)
+    }
+}
+
+pub type ConstantPool = Vec<ConstantPoolEntry>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConstantPoolEntry {
     Class { name_index: u16 },
     Fieldref { class_index: u16, name_and_type_index: u16 },
@@ -40,10 +486,15 @@ pub enum ConstantPoolEntry {
     LongInfo { value: u64 },
     DoubleInfo { value: f64 },
     NameAndTypeInfo { name_index: u16, descriptor_index: u16 },
-    Utf8Info { value: String },
+    /// `raw` holds the original modified UTF-8 bytes if they can't be reproduced by encoding `value`,
+    /// which then contains replacement characters for the parts that couldn't be decoded
+    Utf8Info { value: String, raw: Option<Vec<u8>> },
     MethodHandle { reference_kind: u8, reference_index: u16 },
     MethodTypeInfo { descriptor_index: u16 },
+    Dynamic { bootstrap_method_attr_index: u16, name_and_type_index: u16 },
     InvokeDynamicInfo { bootstrap_method_attr_index: u16, name_and_type_index: u16 },
+    Module { name_index: u16 },
+    Package { name_index: u16 },
     Empty, // Used to represent the empty space after a Double or a Long
 }
 
@@ -59,12 +510,115 @@ impl ConstantPoolEntry {
     }
 }
 
-#[derive(Debug)]
+/// A constant pool value resolved from a `ConstantValue` attribute or a constant element value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValue {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+}
+
+impl ConstantValue {
+    pub fn const_value_as_string(&self) -> String {
+        match self {
+            ConstantValue::Integer(value) => value.to_string(),
+            ConstantValue::Float(value) => value.to_string(),
+            ConstantValue::Long(value) => value.to_string(),
+            ConstantValue::Double(value) => value.to_string(),
+            ConstantValue::String(value) => format!("{:?}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Class {
     pub name: String,
 }
 
-#[derive(Debug)]
+/// A loadable constant, as pushed by `ldc` or passed to a bootstrap method
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    Class(Class),
+    MethodType(String),
+    MethodHandle(MethodHandle),
+    Dynamic(DynamicRef),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameAndType {
+    pub name: String,
+    pub descriptor: String,
+}
+
+/// A resolved Fieldref, Methodref or InterfaceMethodref
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberRef {
+    pub kind: MemberRefKind,
+    pub class: Class,
+    pub name: String,
+    pub descriptor: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemberRefKind {
+    Field,
+    Method,
+    InterfaceMethod,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodHandle {
+    pub reference_kind: ReferenceKind,
+    pub reference: MemberRef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+/// A resolved Dynamic or InvokeDynamic entry. The bootstrap method is only known by its index into
+/// the `BootstrapMethods` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicRef {
+    pub bootstrap_method_attr_index: u16,
+    pub name: String,
+    pub descriptor: String,
+}
+
+/// An entry of the `BootstrapMethods` attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapMethod {
+    pub method: MethodHandle,
+    pub arguments: Vec<Constant>,
+}
+
+/// A Dynamic or InvokeDynamic entry together with the bootstrap method that links it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub bootstrap_method: MethodHandle,
+    pub arguments: Vec<Constant>,
+    pub name: String,
+    pub descriptor: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum AccessFlag {
     AccPublic,
     AccFinal,
@@ -74,51 +628,38 @@ pub enum AccessFlag {
     AccSynthetic,
     AccAnnotation,
     AccEnum,
+    AccModule,
+    /// Bits the class file format doesn't define for this structure. They are kept so that the
+    /// flags are written back unchanged.
+    Reserved(u16),
 }
 
-#[derive(Debug)]
-pub struct Field<'a> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
     pub access_flags: Vec<FieldFlag>,
     pub name: String,
     pub descriptor: String,
-    pub attributes: Vec<Attribute<'a>>,
+    pub attributes: Vec<Attribute>,
 }
 
-impl Field<'_> {
+impl Field {
+    pub fn field_type(&self) -> Result<FieldType, ParsingError> {
+        parse_field_descriptor(&self.descriptor)
+    }
+
+    /// The java name of the type, e.g. `java.lang.String[]`, or the raw descriptor if it is malformed
     pub fn type_name(&self) -> String {
-        Field::type_name_from_string(&self.descriptor)
-    }
-
-    fn type_name_from_string(string: &String) -> String {
-        match string.as_str() {
-            "B" => String::from("byte"),
-            "C" => String::from("char"),
-            "D" => String::from("double"),
-            "F" => String::from("float"),
-            "I" => String::from("int"),
-            "J" => String::from("long"),
-            "Z" => String::from("boolean"),
-            "S" => String::from("short"),
-            _ => {
-                if string.starts_with('L') {
-                    string.clone()[1..string.len() - 1].replace('/', ".")
-                } else if string.starts_with('[') {
-                    let mut copy = string.clone();
-                    while copy.starts_with('[') {
-                        copy.remove(0);
-                        copy = Field::type_name_from_string(&copy);
-                        copy.push_str("[]");
-                    }
-                    copy
-                } else {
-                    String::new()
-                }
-            }
-        }
+        self.field_type().map_or_else(|_| self.descriptor.clone(), |field_type| field_type.to_string())
+    }
+
+    /// The generic type of the field, `None` if it has no `Signature` attribute
+    pub fn signature(&self) -> Option<Result<FieldSignature, ParsingError>> {
+        find_signature(&self.attributes).map(parse_field_signature)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum FieldFlag {
     AccPublic,
     AccPrivate,
@@ -129,17 +670,105 @@ pub enum FieldFlag {
     AccTransient,
     AccSynthetic,
     AccEnum,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
 }
 
-#[derive(Debug)]
-pub struct Method<'a> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
     pub access_flags: Vec<MethodFlag>,
     pub name: String,
     pub descriptor: String,
-    pub attributes: Vec<Attribute<'a>>,
+    pub attributes: Vec<Attribute>,
 }
 
-#[derive(Debug)]
+impl Method {
+    pub fn parsed_descriptor(&self) -> Result<MethodDescriptor, ParsingError> {
+        parse_method_descriptor(&self.descriptor)
+    }
+
+    /// The java names of the parameter types, e.g. `["int", "java.lang.String[]"]`. Empty if the
+    /// descriptor is malformed.
+    pub fn parameter_type_names(&self) -> Vec<String> {
+        self.parsed_descriptor()
+            .map(|descriptor| descriptor.parameters.iter().map(|parameter| parameter.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The java name of the return type, or the raw descriptor if it is malformed
+    pub fn return_type_name(&self) -> String {
+        match self.parsed_descriptor() {
+            Ok(MethodDescriptor { return_type: Some(return_type), .. }) => return_type.to_string(),
+            Ok(MethodDescriptor { return_type: None, .. }) => String::from("void"),
+            Err(_) => self.descriptor.clone(),
+        }
+    }
+
+    /// The generic signature of the method, `None` if it has no `Signature` attribute
+    pub fn signature(&self) -> Option<Result<MethodSignature, ParsingError>> {
+        find_signature(&self.attributes).map(parse_method_signature)
+    }
+
+    /// The source line of the instruction at `pc`, according to the `LineNumberTable` of the method's code
+    pub fn line_number(&self, pc: u16) -> Option<u16> {
+        self.attributes.iter()
+            .filter_map(|attribute| match attribute {
+                Attribute::Code { attributes, .. } => Some(attributes),
+                _ => None
+            })
+            .flatten()
+            .filter_map(|attribute| match attribute {
+                Attribute::LineNumberTable { line_number_table } => Some(line_number_table),
+                _ => None
+            })
+            .flatten()
+            .filter(|line_number| line_number.start_pc <= pc)
+            .max_by_key(|line_number| line_number.start_pc)
+            .map(|line_number| line_number.line_number)
+    }
+
+    /// The names of the parameters as recorded in the `MethodParameters` attribute or, if the class
+    /// was compiled without `-parameters`, the `LocalVariableTable` of the method's code.
+    /// A name is `None` if the class was compiled without debug information.
+    pub fn parameter_names(&self) -> Vec<Option<String>> {
+        let method_parameters = self.attributes.iter().find_map(|attribute| match attribute {
+            Attribute::MethodParameters { parameters } => Some(parameters),
+            _ => None
+        });
+        let parameters = match self.parsed_descriptor() {
+            Ok(descriptor) => descriptor.parameters,
+            Err(_) => return Vec::new(),
+        };
+        if let Some(method_parameters) = method_parameters.filter(|method_parameters| method_parameters.len() == parameters.len()) {
+            return method_parameters.iter().map(|parameter| parameter.name.clone()).collect();
+        }
+
+        let local_variables: Vec<&LocalVariable> = self.attributes.iter()
+            .filter_map(|attribute| match attribute {
+                Attribute::Code { attributes, .. } => Some(attributes),
+                _ => None
+            })
+            .flatten()
+            .filter_map(|attribute| match attribute {
+                Attribute::LocalVariableTable { local_variable_table } => Some(local_variable_table),
+                _ => None
+            })
+            .flatten()
+            .collect();
+
+        let mut slot: u16 = if self.access_flags.contains(&MethodFlag::AccStatic) { 0 } else { 1 };
+        let mut names: Vec<Option<String>> = Vec::new();
+        for parameter in parameters {
+            let local_variable = local_variables.iter().find(|variable| variable.index == slot && variable.start_pc == 0);
+            names.push(local_variable.map(|variable| variable.name.clone()));
+            slot = slot.saturating_add(parameter.slot_size());
+        }
+        names
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum MethodFlag {
     AccPublic,
     AccPrivate,
@@ -153,42 +782,346 @@ pub enum MethodFlag {
     AccAbstract,
     AccStrict,
     AccSynthetic,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
 }
 
-#[derive(Debug)]
-pub enum Attribute<'a> {
-    ConstantValue { value: &'a ConstantPoolEntry },
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum InnerClassFlag {
+    AccPublic,
+    AccPrivate,
+    AccProtected,
+    AccStatic,
+    AccFinal,
+    AccInterface,
+    AccAbstract,
+    AccSynthetic,
+    AccAnnotation,
+    AccEnum,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum MethodParameterFlag {
+    AccFinal,
+    AccSynthetic,
+    AccMandated,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum ModuleFlag {
+    AccOpen,
+    AccSynthetic,
+    AccMandated,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum RequiresFlag {
+    AccTransitive,
+    AccStaticPhase,
+    AccSynthetic,
+    AccMandated,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
+}
+
+/// Flags of an `exports` or `opens` directive
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum ExportsFlag {
+    AccSynthetic,
+    AccMandated,
+    /// Undefined bits, see [`AccessFlag::Reserved`]
+    Reserved(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attribute {
+    ConstantValue { index: u16, value: ConstantValue },
     Synthetic,
     Signature { signature: String },
     Deprecated,
-    RuntimeVisibleAnnotations { annotations: Vec<Annotation<'a>> },
-    RuntimeInvisibleAnnotations { annotations: Vec<Annotation<'a>> },
+    RuntimeVisibleAnnotations { annotations: Vec<Annotation> },
+    RuntimeInvisibleAnnotations { annotations: Vec<Annotation> },
+    RuntimeVisibleTypeAnnotations { annotations: Vec<TypeAnnotation> },
+    RuntimeInvisibleTypeAnnotations { annotations: Vec<TypeAnnotation> },
     Code {
         max_stack: u16,
         max_locals: u16,
         code: Vec<u8>,
         exception_table: Vec<ExceptionHandler>,
-        attributes: Vec<Attribute<'a>>,
+        attributes: Vec<Attribute>,
     },
     Exceptions { exceptions: Vec<Class> },
-    RuntimeVisibleParameterAnnotations { annotations: Vec<Vec<Annotation<'a>>> },
-    RuntimeInvisibleParameterAnnotations { annotations: Vec<Vec<Annotation<'a>>> },
-    AnnotationDefault { default_value: ElementValue<'a> },
+    RuntimeVisibleParameterAnnotations { annotations: Vec<Vec<Annotation>> },
+    RuntimeInvisibleParameterAnnotations { annotations: Vec<Vec<Annotation>> },
+    AnnotationDefault { default_value: ElementValue },
     LineNumberTable {
         line_number_table: Vec<LineNumber>
     },
+    LocalVariableTable {
+        local_variable_table: Vec<LocalVariable>
+    },
+    LocalVariableTypeTable {
+        local_variable_type_table: Vec<LocalVariableType>
+    },
     SourceFile { source_file: String },
     NestMembers { classes: Vec<Class> },
-    Unimplemented, // TODO remove
+    StackMapTable { entries: Vec<StackMapFrame> },
+    InnerClasses { classes: Vec<InnerClass> },
+    /// Present on local and anonymous classes. `method` is `None` if the class isn't enclosed by a
+    /// method, e.g. because it appears in an initializer.
+    EnclosingMethod { class: Class, method: Option<NameAndType> },
+    NestHost { host_class: Class },
+    BootstrapMethods { bootstrap_methods: Vec<BootstrapMethod> },
+    Record { components: Vec<RecordComponent> },
+    PermittedSubclasses { classes: Vec<Class> },
+    MethodParameters { parameters: Vec<MethodParameter> },
+    Module { module: Module },
+    /// Package names in internal form, e.g. `java/util`
+    ModulePackages { packages: Vec<String> },
+    ModuleMainClass { main_class: Class },
+    /// The raw modified UTF-8 contents, usually a SMAP that can be decoded with [`crate::smap::parse_smap`]
+    SourceDebugExtension { debug_extension: Vec<u8> },
+    /// An attribute decoded by a decoder registered in an [`crate::reader::AttributeRegistry`]
+    Custom { name: String, value: CustomValue },
+    /// An attribute the parser doesn't know, with its raw contents
+    Unknown { name: String, data: Vec<u8> },
 }
 
-#[derive(Debug)]
+impl Attribute {
+    /// The name the attribute is stored under in the class file
+    pub fn name(&self) -> &str {
+        match self {
+            Attribute::ConstantValue { .. } => "ConstantValue",
+            Attribute::Synthetic => "Synthetic",
+            Attribute::Signature { .. } => "Signature",
+            Attribute::Deprecated => "Deprecated",
+            Attribute::RuntimeVisibleAnnotations { .. } => "RuntimeVisibleAnnotations",
+            Attribute::RuntimeInvisibleAnnotations { .. } => "RuntimeInvisibleAnnotations",
+            Attribute::RuntimeVisibleTypeAnnotations { .. } => "RuntimeVisibleTypeAnnotations",
+            Attribute::RuntimeInvisibleTypeAnnotations { .. } => "RuntimeInvisibleTypeAnnotations",
+            Attribute::Code { .. } => "Code",
+            Attribute::Exceptions { .. } => "Exceptions",
+            Attribute::RuntimeVisibleParameterAnnotations { .. } => "RuntimeVisibleParameterAnnotations",
+            Attribute::RuntimeInvisibleParameterAnnotations { .. } => "RuntimeInvisibleParameterAnnotations",
+            Attribute::AnnotationDefault { .. } => "AnnotationDefault",
+            Attribute::LineNumberTable { .. } => "LineNumberTable",
+            Attribute::LocalVariableTable { .. } => "LocalVariableTable",
+            Attribute::LocalVariableTypeTable { .. } => "LocalVariableTypeTable",
+            Attribute::SourceFile { .. } => "SourceFile",
+            Attribute::NestMembers { .. } => "NestMembers",
+            Attribute::StackMapTable { .. } => "StackMapTable",
+            Attribute::InnerClasses { .. } => "InnerClasses",
+            Attribute::EnclosingMethod { .. } => "EnclosingMethod",
+            Attribute::NestHost { .. } => "NestHost",
+            Attribute::BootstrapMethods { .. } => "BootstrapMethods",
+            Attribute::Record { .. } => "Record",
+            Attribute::PermittedSubclasses { .. } => "PermittedSubclasses",
+            Attribute::MethodParameters { .. } => "MethodParameters",
+            Attribute::Module { .. } => "Module",
+            Attribute::ModulePackages { .. } => "ModulePackages",
+            Attribute::ModuleMainClass { .. } => "ModuleMainClass",
+            Attribute::SourceDebugExtension { .. } => "SourceDebugExtension",
+            Attribute::Custom { name, .. } | Attribute::Unknown { name, .. } => name,
+        }
+    }
+}
+
+/// Values produced by custom attribute decoders
+pub trait AttributeValue: Any + fmt::Debug + Send + Sync {}
+
+impl<T: Any + fmt::Debug + Send + Sync> AttributeValue for T {}
+
+/// The contents of a custom attribute, both raw and decoded. Two values are equal if their raw data
+/// is, since the decoded values can't be compared.
+#[derive(Debug, Clone)]
+pub struct CustomValue {
+    /// The contents of the attribute without its header, which are written back as they are
+    pub data: Vec<u8>,
+    value: Arc<dyn AttributeValue>,
+}
+
+impl CustomValue {
+    pub fn new<T: AttributeValue>(data: Vec<u8>, value: T) -> CustomValue {
+        CustomValue { data, value: Arc::new(value) }
+    }
+
+    /// The value as produced by the decoder, `None` if it isn't a `T`
+    pub fn downcast_ref<T: AttributeValue>(&self) -> Option<&T> {
+        let value: &dyn Any = self.value.as_ref();
+        value.downcast_ref()
+    }
+}
+
+impl PartialEq for CustomValue {
+    fn eq(&self, other: &CustomValue) -> bool {
+        self.data == other.data
+    }
+}
+
+/// A frame of a `StackMapTable`. `pc` is the absolute offset into the code the frame applies to,
+/// computed from the offset deltas stored in the class file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackMapFrame {
+    Same { pc: u16 },
+    SameExtended { pc: u16 },
+    SameLocals1StackItem { pc: u16, stack: VerificationType },
+    SameLocals1StackItemExtended { pc: u16, stack: VerificationType },
+    /// The last `chopped` locals are removed
+    Chop { pc: u16, chopped: u8 },
+    Append { pc: u16, locals: Vec<VerificationType> },
+    Full { pc: u16, locals: Vec<VerificationType>, stack: Vec<VerificationType> },
+}
+
+impl StackMapFrame {
+    pub fn pc(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { pc } |
+            StackMapFrame::SameExtended { pc } |
+            StackMapFrame::SameLocals1StackItem { pc, .. } |
+            StackMapFrame::SameLocals1StackItemExtended { pc, .. } |
+            StackMapFrame::Chop { pc, .. } |
+            StackMapFrame::Append { pc, .. } |
+            StackMapFrame::Full { pc, .. } => *pc
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object(Class),
+    /// An object created by the `new` instruction at `offset` whose constructor hasn't been called yet
+    Uninitialized { offset: u16 },
+}
+
+/// An entry of the `InnerClasses` attribute. `outer_class` is `None` for local and anonymous classes,
+/// `inner_name` is `None` for anonymous classes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InnerClass {
+    pub inner_class: Class,
+    pub outer_class: Option<Class>,
+    pub inner_name: Option<String>,
+    pub access_flags: Vec<InnerClassFlag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordComponent {
+    pub name: String,
+    pub descriptor: String,
+    pub attributes: Vec<Attribute>,
+}
+
+impl RecordComponent {
+    pub fn field_type(&self) -> Result<FieldType, ParsingError> {
+        parse_field_descriptor(&self.descriptor)
+    }
+
+    /// The java name of the type, or the raw descriptor if it is malformed
+    pub fn type_name(&self) -> String {
+        self.field_type().map_or_else(|_| self.descriptor.clone(), |field_type| field_type.to_string())
+    }
+
+    /// The generic type of the component, `None` if it has no `Signature` attribute
+    pub fn signature(&self) -> Option<Result<FieldSignature, ParsingError>> {
+        find_signature(&self.attributes).map(parse_field_signature)
+    }
+}
+
+/// An entry of the `MethodParameters` attribute. `name` is `None` for parameters the compiler
+/// didn't record a name for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodParameter {
+    pub name: Option<String>,
+    pub access_flags: Vec<MethodParameterFlag>,
+}
+
+/// The contents of the `Module` attribute of a `module-info` class. Package names are in internal
+/// form, e.g. `java/util`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub flags: Vec<ModuleFlag>,
+    pub version: Option<String>,
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Opens>,
+    pub uses: Vec<Class>,
+    pub provides: Vec<Provides>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requires {
+    pub module: String,
+    pub flags: Vec<RequiresFlag>,
+    pub version: Option<String>,
+}
+
+/// An `exports` directive. `to` is empty for unqualified exports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exports {
+    pub package: String,
+    pub flags: Vec<ExportsFlag>,
+    pub to: Vec<String>,
+}
+
+/// An `opens` directive. `to` is empty for unqualified opens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opens {
+    pub package: String,
+    pub flags: Vec<ExportsFlag>,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provides {
+    pub service: Class,
+    pub with: Vec<Class>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LineNumber {
     pub start_pc: u16,
     pub line_number: u16,
 }
 
-#[derive(Debug)]
+/// A local variable with the `descriptor` type, stored in slot `index` from `start_pc` until `start_pc + length`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: String,
+    pub descriptor: String,
+    pub index: u16,
+}
+
+/// Like [`LocalVariable`], but with a generic signature instead of a descriptor
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariableType {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: String,
+    pub signature: String,
+    pub index: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExceptionHandler {
     pub start_pc: u16,
     pub end_pc: u16,
@@ -196,20 +1129,103 @@ pub struct ExceptionHandler {
     pub catch_type: Option<Class>,
 }
 
-#[derive(Debug)]
-pub struct Annotation<'a> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
     pub type_name: String,
-    pub element_value_pairs: Vec<ElementValuePair<'a>>,
+    pub element_value_pairs: Vec<ElementValuePair>,
 }
 
-#[derive(Debug)]
-pub struct ElementValuePair<'a>(pub String, pub ElementValue<'a>);
+/// An annotation on a use of a type, e.g. `@Nullable` in `List<@Nullable String>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation {
+    pub target_type: TargetType,
+    pub target_info: TargetInfo,
+    /// The part of the targeted type that is annotated, outermost step first. Empty if the
+    /// annotation applies to the whole type.
+    pub type_path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
 
-#[derive(Debug)]
-pub enum ElementValue<'a> {
-    ConstValue { value: &'a ConstantPoolEntry },
+/// The kind of type use a type annotation applies to. The values are the `target_type` bytes of
+/// the class file format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetType {
+    ClassTypeParameter = 0x00,
+    MethodTypeParameter = 0x01,
+    ClassExtends = 0x10,
+    ClassTypeParameterBound = 0x11,
+    MethodTypeParameterBound = 0x12,
+    Field = 0x13,
+    MethodReturn = 0x14,
+    MethodReceiver = 0x15,
+    MethodFormalParameter = 0x16,
+    Throws = 0x17,
+    LocalVariable = 0x40,
+    ResourceVariable = 0x41,
+    ExceptionParameter = 0x42,
+    Instanceof = 0x43,
+    New = 0x44,
+    ConstructorReference = 0x45,
+    MethodReference = 0x46,
+    Cast = 0x47,
+    ConstructorInvocationTypeArgument = 0x48,
+    MethodInvocationTypeArgument = 0x49,
+    ConstructorReferenceTypeArgument = 0x4A,
+    MethodReferenceTypeArgument = 0x4B,
+}
+
+/// Which type in a declaration or expression a type annotation applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetInfo {
+    TypeParameter { type_parameter_index: u8 },
+    /// `supertype_index` is 65535 for the super class, otherwise an index into the interfaces
+    Supertype { supertype_index: u16 },
+    TypeParameterBound { type_parameter_index: u8, bound_index: u8 },
+    /// The type of a field, the return type or the receiver type
+    Empty,
+    FormalParameter { formal_parameter_index: u8 },
+    Throws { throws_type_index: u16 },
+    LocalVariable { table: Vec<LocalVariableTarget> },
+    Catch { exception_table_index: u16 },
+    Offset { offset: u16 },
+    TypeArgument { offset: u16, type_argument_index: u8 },
+}
+
+/// A range of code in which a local variable has the annotated type
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariableTarget {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypePathEntry {
+    pub kind: TypePathKind,
+    /// Which type argument is annotated, always 0 unless `kind` is `TypeArgument`
+    pub type_argument_index: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypePathKind {
+    /// Deeper in an array type
+    Array = 0,
+    /// Deeper in a nested type
+    Nested = 1,
+    /// On the bound of a wildcard type argument
+    Wildcard = 2,
+    /// On a type argument of a parameterized type
+    TypeArgument = 3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementValuePair(pub String, pub ElementValue);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue {
+    ConstValue { tag: char, index: u16, value: ConstantValue },
     EnumConstValue { type_name: String, const_name: String },
     ClassInfo { descriptor: String },
-    AnnotationValue { annotation: Annotation<'a> },
-    ArrayValue { elements: Vec<ElementValue<'a>> },
+    AnnotationValue { annotation: Annotation },
+    ArrayValue { elements: Vec<ElementValue> },
 }
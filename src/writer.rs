@@ -0,0 +1,1006 @@
+//! Turns a [`ClassFile`] back into the bytes of a `.class` file.
+//!
+//! Structures refer to the constant pool by the entry that holds their resolved value. Values that
+//! aren't in the pool yet are appended to it, so classes can be modified freely. A class that was
+//! parsed and not modified is written back byte for byte.
+
+use crate::constant_pool::ConstantPoolBuilder;
+use crate::mutf8;
+use crate::reader::{read_class_from_constant_pool, read_constant_from_constant_pool, read_constant_pool_entry_at, read_constant_value, read_method_handle_from_constant_pool, read_name_and_type_from_constant_pool, read_utf8_from_constant_pool};
+use crate::types::{AccessFlag, Annotation, Attribute, BootstrapMethod, ClassFile, Constant, ConstantPool, ConstantPoolEntry, ConstantValue, CustomValue, ElementValue, ExportsFlag, Field, FieldFlag, InnerClass, InnerClassFlag, Method, MethodFlag, MethodHandle, MethodParameterFlag, Module, ModuleFlag, RequiresFlag, StackMapFrame, TargetInfo, TypeAnnotation, VerificationType, WritingError, WritingErrorKind};
+
+pub fn write_class_file(class_file: &ClassFile) -> Result<Vec<u8>, WritingError> {
+    let mut constant_pool = ConstantPoolBuilder::from_class_file(class_file);
+
+    // Everything after the constant pool is written first, since it may still add entries
+    let mut body: Vec<u8> = Vec::new();
+    write_u2(&mut body, access_flags_mask(&class_file.access_flags));
+    write_u2(&mut body, class_index(&mut constant_pool, &class_file.this_class.name).map_err(|e| e.with_context("this_class"))?);
+    let super_class_index = match &class_file.super_class {
+        Some(super_class) => class_index(&mut constant_pool, &super_class.name).map_err(|e| e.with_context("super_class"))?,
+        None => 0,
+    };
+    write_u2(&mut body, super_class_index);
+
+    write_length_u2(&mut body, class_file.interfaces.len())?;
+    for (i, interface) in class_file.interfaces.iter().enumerate() {
+        let interface_index = constant_pool.in_part(&format!("interfaces[{}]", interface.name), |constant_pool| class_index(constant_pool, &interface.name))
+            .map_err(|e| e.with_context(format!("interfaces[{}]", i)))?;
+        write_u2(&mut body, interface_index);
+    }
+
+    write_length_u2(&mut body, class_file.fields.len())?;
+    for (i, field) in class_file.fields.iter().enumerate() {
+        constant_pool.in_part(&format!("fields[{}:{}]", field.name, field.descriptor), |constant_pool| write_field(&mut body, field, constant_pool))
+            .map_err(|e| e.with_context(format!("fields[{}]", i)))?;
+    }
+
+    write_length_u2(&mut body, class_file.methods.len())?;
+    for (i, method) in class_file.methods.iter().enumerate() {
+        constant_pool.in_part(&format!("methods[{}:{}]", method.name, method.descriptor), |constant_pool| write_method(&mut body, method, constant_pool))
+            .map_err(|e| e.with_context(format!("methods[{}]", i)))?;
+    }
+
+    write_attributes(&mut body, &class_file.attributes, &mut constant_pool)?;
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(class_file.parsed_bytes);
+    write_u4(&mut buffer, class_file.magic);
+    write_u2(&mut buffer, class_file.minor_version);
+    write_u2(&mut buffer, class_file.major_version);
+    write_constant_pool(&mut buffer, constant_pool.constant_pool())?;
+    buffer.extend_from_slice(&body);
+    Ok(buffer)
+}
+
+fn write_constant_pool(buffer: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WritingError> {
+    write_length_u2(buffer, constant_pool.len() + 1).map_err(|e| e.with_context("constant_pool"))?;
+    for (i, entry) in constant_pool.iter().enumerate() {
+        write_constant_pool_entry(buffer, entry).map_err(|e| e.with_context(format!("constant_pool[{}]", i + 1)))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_constant_pool_entry(buffer: &mut Vec<u8>, entry: &ConstantPoolEntry) -> Result<(), WritingError> {
+    match entry {
+        ConstantPoolEntry::Utf8Info { value, raw } => {
+            let bytes = match raw {
+                Some(raw) => raw.clone(),
+                None => mutf8::encode(value),
+            };
+            write_u1(buffer, 1);
+            write_length_u2(buffer, bytes.len())?;
+            buffer.extend_from_slice(&bytes);
+        }
+        ConstantPoolEntry::IntegerInfo { value } => {
+            write_u1(buffer, 3);
+            write_u4(buffer, *value);
+        }
+        ConstantPoolEntry::FloatInfo { value } => {
+            write_u1(buffer, 4);
+            write_u4(buffer, value.to_bits());
+        }
+        ConstantPoolEntry::LongInfo { value } => {
+            write_u1(buffer, 5);
+            write_u8(buffer, *value);
+        }
+        ConstantPoolEntry::DoubleInfo { value } => {
+            write_u1(buffer, 6);
+            write_u8(buffer, value.to_bits());
+        }
+        ConstantPoolEntry::Class { name_index } => {
+            write_u1(buffer, 7);
+            write_u2(buffer, *name_index);
+        }
+        ConstantPoolEntry::StringInfo { string_index } => {
+            write_u1(buffer, 8);
+            write_u2(buffer, *string_index);
+        }
+        ConstantPoolEntry::Fieldref { class_index, name_and_type_index } => {
+            write_u1(buffer, 9);
+            write_u2(buffer, *class_index);
+            write_u2(buffer, *name_and_type_index);
+        }
+        ConstantPoolEntry::Methodref { class_index, name_and_type_index } => {
+            write_u1(buffer, 10);
+            write_u2(buffer, *class_index);
+            write_u2(buffer, *name_and_type_index);
+        }
+        ConstantPoolEntry::InterfaceMethodref { class_index, name_and_type_index } => {
+            write_u1(buffer, 11);
+            write_u2(buffer, *class_index);
+            write_u2(buffer, *name_and_type_index);
+        }
+        ConstantPoolEntry::NameAndTypeInfo { name_index, descriptor_index } => {
+            write_u1(buffer, 12);
+            write_u2(buffer, *name_index);
+            write_u2(buffer, *descriptor_index);
+        }
+        ConstantPoolEntry::MethodHandle { reference_kind, reference_index } => {
+            write_u1(buffer, 15);
+            write_u1(buffer, *reference_kind);
+            write_u2(buffer, *reference_index);
+        }
+        ConstantPoolEntry::MethodTypeInfo { descriptor_index } => {
+            write_u1(buffer, 16);
+            write_u2(buffer, *descriptor_index);
+        }
+        ConstantPoolEntry::Dynamic { bootstrap_method_attr_index, name_and_type_index } => {
+            write_u1(buffer, 17);
+            write_u2(buffer, *bootstrap_method_attr_index);
+            write_u2(buffer, *name_and_type_index);
+        }
+        ConstantPoolEntry::InvokeDynamicInfo { bootstrap_method_attr_index, name_and_type_index } => {
+            write_u1(buffer, 18);
+            write_u2(buffer, *bootstrap_method_attr_index);
+            write_u2(buffer, *name_and_type_index);
+        }
+        ConstantPoolEntry::Module { name_index } => {
+            write_u1(buffer, 19);
+            write_u2(buffer, *name_index);
+        }
+        ConstantPoolEntry::Package { name_index } => {
+            write_u1(buffer, 20);
+            write_u2(buffer, *name_index);
+        }
+        // The second slot of a Long or Double takes up no bytes
+        ConstantPoolEntry::Empty => {}
+    }
+    Ok(())
+}
+
+// Each of these adds a value like the method of ConstantPoolBuilder with the same name, but keeps
+// referring to the entry the parsed class referred to while it still holds the value
+
+fn utf8_index(constant_pool: &mut ConstantPoolBuilder, value: &str) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| read_utf8_from_constant_pool(entries, index, 0).is_ok_and(|current| current == value),
+        |constant_pool| constant_pool.utf8(value),
+    )
+}
+
+/// Like [`utf8_index`], but `None` is written as index 0
+fn optional_utf8(constant_pool: &mut ConstantPoolBuilder, value: Option<&str>) -> Result<u16, WritingError> {
+    value.map_or(Ok(0), |value| utf8_index(constant_pool, value))
+}
+
+fn class_index(constant_pool: &mut ConstantPoolBuilder, name: &str) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| read_class_from_constant_pool(entries, index, 0).is_ok_and(|current| current.name == name),
+        |constant_pool| constant_pool.class(name),
+    )
+}
+
+fn name_and_type_index(constant_pool: &mut ConstantPoolBuilder, name: &str, descriptor: &str) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| read_name_and_type_from_constant_pool(entries, index, 0).is_ok_and(|current| current.name == name && current.descriptor == descriptor),
+        |constant_pool| constant_pool.name_and_type(name, descriptor),
+    )
+}
+
+fn method_handle_index(constant_pool: &mut ConstantPoolBuilder, method_handle: &MethodHandle) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| read_method_handle_from_constant_pool(entries, index, 0).is_ok_and(|current| current == *method_handle),
+        |constant_pool| constant_pool.method_handle(method_handle),
+    )
+}
+
+fn constant_index(constant_pool: &mut ConstantPoolBuilder, constant: &Constant) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        // Floating point values are compared by their bits, so that NaN matches itself
+        |entries, index| match (read_constant_from_constant_pool(entries, index, 0), constant) {
+            (Ok(Constant::Float(current)), Constant::Float(value)) => current.to_bits() == value.to_bits(),
+            (Ok(Constant::Double(current)), Constant::Double(value)) => current.to_bits() == value.to_bits(),
+            (Ok(current), constant) => current == *constant,
+            (Err(_), _) => false,
+        },
+        |constant_pool| constant_pool.constant(constant),
+    )
+}
+
+fn module_index(constant_pool: &mut ConstantPoolBuilder, name: &str) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| match read_constant_pool_entry_at(entries, index, 0) {
+            Ok(ConstantPoolEntry::Module { name_index }) => read_utf8_from_constant_pool(entries, *name_index, 0).is_ok_and(|current| current == name),
+            _ => false,
+        },
+        |constant_pool| constant_pool.module(name),
+    )
+}
+
+fn package_index(constant_pool: &mut ConstantPoolBuilder, name: &str) -> Result<u16, WritingError> {
+    constant_pool.reference(
+        |entries, index| match read_constant_pool_entry_at(entries, index, 0) {
+            Ok(ConstantPoolEntry::Package { name_index }) => read_utf8_from_constant_pool(entries, *name_index, 0).is_ok_and(|current| current == name),
+            _ => false,
+        },
+        |constant_pool| constant_pool.package(name),
+    )
+}
+
+/// The index for the value of a `ConstantValue` attribute or a constant element value. The parsed
+/// `index` is kept as long as its entry still holds `value`, otherwise `value` is added to the pool.
+/// Strings are added with `add_string`, since `ConstantValue` refers to a String entry and element
+/// values to a Utf8 entry.
+fn constant_value(constant_pool: &mut ConstantPoolBuilder, index: u16, value: &ConstantValue, add_string: fn(&mut ConstantPoolBuilder, &str) -> Result<u16, WritingError>) -> Result<u16, WritingError> {
+    // Floating point values are compared by their bits, so that NaN matches itself
+    let unchanged = match (read_constant_value(constant_pool.constant_pool(), index, 0), value) {
+        (Ok(ConstantValue::Float(current)), ConstantValue::Float(value)) => current.to_bits() == value.to_bits(),
+        (Ok(ConstantValue::Double(current)), ConstantValue::Double(value)) => current.to_bits() == value.to_bits(),
+        (Ok(current), value) => current == *value,
+        (Err(_), _) => false,
+    };
+    if unchanged {
+        return Ok(index);
+    }
+    match value {
+        ConstantValue::Integer(value) => constant_pool.integer(*value),
+        ConstantValue::Float(value) => constant_pool.float(*value),
+        ConstantValue::Long(value) => constant_pool.long(*value),
+        ConstantValue::Double(value) => constant_pool.double(*value),
+        ConstantValue::String(value) => add_string(constant_pool, value),
+    }
+}
+
+fn write_field(buffer: &mut Vec<u8>, field: &Field, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, field_flags_mask(&field.access_flags));
+    write_u2(buffer, utf8_index(constant_pool, &field.name)?);
+    write_u2(buffer, utf8_index(constant_pool, &field.descriptor)?);
+    write_attributes(buffer, &field.attributes, constant_pool)
+}
+
+fn write_method(buffer: &mut Vec<u8>, method: &Method, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, method_flags_mask(&method.access_flags));
+    write_u2(buffer, utf8_index(constant_pool, &method.name)?);
+    write_u2(buffer, utf8_index(constant_pool, &method.descriptor)?);
+    write_attributes(buffer, &method.attributes, constant_pool)
+}
+
+fn write_attributes(buffer: &mut Vec<u8>, attributes: &[Attribute], constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_length_u2(buffer, attributes.len())?;
+    for (i, attribute) in attributes.iter().enumerate() {
+        let name = attribute.name();
+        constant_pool.in_part(&format!("attributes[{}]", name), |constant_pool| {
+            write_u2(buffer, utf8_index(constant_pool, name).map_err(|e| e.with_context(format!("attributes[{}]", i)))?);
+
+            // The length comes first, so the body is written separately
+            let mut body: Vec<u8> = Vec::new();
+            write_attribute(&mut body, attribute, constant_pool).map_err(|e| e.with_context(format!("attributes[{}]", name)))?;
+            write_length_u4(buffer, body.len()).map_err(|e| e.with_context(format!("attributes[{}]", name)))?;
+            buffer.extend_from_slice(&body);
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn write_attribute(buffer: &mut Vec<u8>, attribute: &Attribute, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    match attribute {
+        Attribute::ConstantValue { index, value } => write_u2(buffer, constant_value(constant_pool, *index, value, ConstantPoolBuilder::string)?),
+
+        Attribute::Synthetic | Attribute::Deprecated => {}
+
+        Attribute::Signature { signature } => write_u2(buffer, utf8_index(constant_pool, signature)?),
+
+        Attribute::RuntimeVisibleAnnotations { annotations } |
+        Attribute::RuntimeInvisibleAnnotations { annotations } => write_annotations(buffer, annotations, constant_pool)?,
+
+        Attribute::RuntimeVisibleTypeAnnotations { annotations } |
+        Attribute::RuntimeInvisibleTypeAnnotations { annotations } => {
+            write_length_u2(buffer, annotations.len())?;
+            for (i, annotation) in annotations.iter().enumerate() {
+                write_type_annotation(buffer, annotation, constant_pool).map_err(|e| e.with_context(format!("annotations[{}]", i)))?;
+            }
+        }
+
+        Attribute::Code { max_stack, max_locals, code, exception_table, attributes } => {
+            write_u2(buffer, *max_stack);
+            write_u2(buffer, *max_locals);
+            write_length_u4(buffer, code.len())?;
+            buffer.extend_from_slice(code);
+
+            write_length_u2(buffer, exception_table.len())?;
+            for (i, handler) in exception_table.iter().enumerate() {
+                write_u2(buffer, handler.start_pc);
+                write_u2(buffer, handler.end_pc);
+                write_u2(buffer, handler.handler_pc);
+                let catch_type = match &handler.catch_type {
+                    Some(catch_type) => class_index(constant_pool, &catch_type.name).map_err(|e| e.with_context(format!("exception_table[{}]", i)))?,
+                    None => 0,
+                };
+                write_u2(buffer, catch_type);
+            }
+            write_attributes(buffer, attributes, constant_pool)?;
+        }
+
+        Attribute::Exceptions { exceptions } => write_classes(buffer, exceptions.iter().map(|class| class.name.as_str()), exceptions.len(), constant_pool)?,
+
+        Attribute::RuntimeVisibleParameterAnnotations { annotations } |
+        Attribute::RuntimeInvisibleParameterAnnotations { annotations } => {
+            write_length_u1(buffer, annotations.len())?;
+            for (i, parameter_annotations) in annotations.iter().enumerate() {
+                write_annotations(buffer, parameter_annotations, constant_pool).map_err(|e| e.with_context(format!("parameters[{}]", i)))?;
+            }
+        }
+
+        Attribute::AnnotationDefault { default_value } => write_element_value(buffer, default_value, constant_pool)?,
+
+        Attribute::LineNumberTable { line_number_table } => {
+            write_length_u2(buffer, line_number_table.len())?;
+            for line_number in line_number_table {
+                write_u2(buffer, line_number.start_pc);
+                write_u2(buffer, line_number.line_number);
+            }
+        }
+
+        Attribute::LocalVariableTable { local_variable_table } => {
+            write_length_u2(buffer, local_variable_table.len())?;
+            for variable in local_variable_table {
+                write_u2(buffer, variable.start_pc);
+                write_u2(buffer, variable.length);
+                write_u2(buffer, utf8_index(constant_pool, &variable.name)?);
+                write_u2(buffer, utf8_index(constant_pool, &variable.descriptor)?);
+                write_u2(buffer, variable.index);
+            }
+        }
+
+        Attribute::LocalVariableTypeTable { local_variable_type_table } => {
+            write_length_u2(buffer, local_variable_type_table.len())?;
+            for variable in local_variable_type_table {
+                write_u2(buffer, variable.start_pc);
+                write_u2(buffer, variable.length);
+                write_u2(buffer, utf8_index(constant_pool, &variable.name)?);
+                write_u2(buffer, utf8_index(constant_pool, &variable.signature)?);
+                write_u2(buffer, variable.index);
+            }
+        }
+
+        Attribute::SourceFile { source_file } => write_u2(buffer, utf8_index(constant_pool, source_file)?),
+
+        Attribute::NestMembers { classes } |
+        Attribute::PermittedSubclasses { classes } => write_classes(buffer, classes.iter().map(|class| class.name.as_str()), classes.len(), constant_pool)?,
+
+        Attribute::StackMapTable { entries } => write_stack_map_table(buffer, entries, constant_pool)?,
+
+        Attribute::InnerClasses { classes } => {
+            write_length_u2(buffer, classes.len())?;
+            for (i, inner_class) in classes.iter().enumerate() {
+                write_inner_class(buffer, inner_class, constant_pool).map_err(|e| e.with_context(format!("classes[{}]", i)))?;
+            }
+        }
+
+        Attribute::EnclosingMethod { class, method } => {
+            write_u2(buffer, class_index(constant_pool, &class.name)?);
+            let method_index = match method {
+                Some(method) => name_and_type_index(constant_pool, &method.name, &method.descriptor)?,
+                None => 0,
+            };
+            write_u2(buffer, method_index);
+        }
+
+        Attribute::NestHost { host_class } => write_u2(buffer, class_index(constant_pool, &host_class.name)?),
+
+        Attribute::BootstrapMethods { bootstrap_methods } => {
+            write_length_u2(buffer, bootstrap_methods.len())?;
+            for (i, bootstrap_method) in bootstrap_methods.iter().enumerate() {
+                write_bootstrap_method(buffer, bootstrap_method, constant_pool).map_err(|e| e.with_context(format!("bootstrap_methods[{}]", i)))?;
+            }
+        }
+
+        Attribute::Record { components } => {
+            write_length_u2(buffer, components.len())?;
+            for (i, component) in components.iter().enumerate() {
+                constant_pool.in_part(&format!("components[{}]", component.name), |constant_pool| {
+                    write_u2(buffer, utf8_index(constant_pool, &component.name)?);
+                    write_u2(buffer, utf8_index(constant_pool, &component.descriptor)?);
+                    write_attributes(buffer, &component.attributes, constant_pool)
+                }).map_err(|e| e.with_context(format!("components[{}]", i)))?;
+            }
+        }
+
+        Attribute::MethodParameters { parameters } => {
+            write_length_u1(buffer, parameters.len())?;
+            for (i, parameter) in parameters.iter().enumerate() {
+                write_u2(buffer, optional_utf8(constant_pool, parameter.name.as_deref()).map_err(|e| e.with_context(format!("parameters[{}]", i)))?);
+                write_u2(buffer, method_parameter_flags_mask(&parameter.access_flags));
+            }
+        }
+
+        Attribute::Module { module } => write_module(buffer, module, constant_pool)?,
+
+        Attribute::ModulePackages { packages } => {
+            write_length_u2(buffer, packages.len())?;
+            for package in packages {
+                write_u2(buffer, package_index(constant_pool, package)?);
+            }
+        }
+
+        Attribute::ModuleMainClass { main_class } => write_u2(buffer, class_index(constant_pool, &main_class.name)?),
+
+        Attribute::SourceDebugExtension { debug_extension: data } |
+        Attribute::Custom { value: CustomValue { data, .. }, .. } |
+        Attribute::Unknown { data, .. } => buffer.extend_from_slice(data),
+    }
+    Ok(())
+}
+
+/// Writes a u2 count followed by the Class indices of `names`, as used by `Exceptions`, `NestMembers`
+/// and `PermittedSubclasses`
+fn write_classes<'a>(buffer: &mut Vec<u8>, names: impl Iterator<Item = &'a str>, count: usize, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_length_u2(buffer, count)?;
+    for (i, name) in names.enumerate() {
+        write_u2(buffer, class_index(constant_pool, name).map_err(|e| e.with_context(format!("classes[{}]", i)))?);
+    }
+    Ok(())
+}
+
+fn write_inner_class(buffer: &mut Vec<u8>, inner_class: &InnerClass, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, class_index(constant_pool, &inner_class.inner_class.name)?);
+    let outer_class_index = match &inner_class.outer_class {
+        Some(outer_class) => class_index(constant_pool, &outer_class.name)?,
+        None => 0,
+    };
+    write_u2(buffer, outer_class_index);
+    write_u2(buffer, optional_utf8(constant_pool, inner_class.inner_name.as_deref())?);
+    write_u2(buffer, inner_class_flags_mask(&inner_class.access_flags));
+    Ok(())
+}
+
+fn write_bootstrap_method(buffer: &mut Vec<u8>, bootstrap_method: &BootstrapMethod, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, method_handle_index(constant_pool, &bootstrap_method.method)?);
+    write_length_u2(buffer, bootstrap_method.arguments.len())?;
+    for (i, argument) in bootstrap_method.arguments.iter().enumerate() {
+        write_u2(buffer, constant_index(constant_pool, argument).map_err(|e| e.with_context(format!("arguments[{}]", i)))?);
+    }
+    Ok(())
+}
+
+fn write_module(buffer: &mut Vec<u8>, module: &Module, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, module_index(constant_pool, &module.name)?);
+    write_u2(buffer, module_flags_mask(&module.flags));
+    write_u2(buffer, optional_utf8(constant_pool, module.version.as_deref())?);
+
+    write_length_u2(buffer, module.requires.len())?;
+    for (i, requires) in module.requires.iter().enumerate() {
+        let context = |e: WritingError| e.with_context(format!("requires[{}]", i));
+        write_u2(buffer, module_index(constant_pool, &requires.module).map_err(context)?);
+        write_u2(buffer, requires_flags_mask(&requires.flags));
+        write_u2(buffer, optional_utf8(constant_pool, requires.version.as_deref()).map_err(context)?);
+    }
+
+    write_length_u2(buffer, module.exports.len())?;
+    for (i, exports) in module.exports.iter().enumerate() {
+        write_exports(buffer, &exports.package, &exports.flags, &exports.to, constant_pool).map_err(|e| e.with_context(format!("exports[{}]", i)))?;
+    }
+
+    write_length_u2(buffer, module.opens.len())?;
+    for (i, opens) in module.opens.iter().enumerate() {
+        write_exports(buffer, &opens.package, &opens.flags, &opens.to, constant_pool).map_err(|e| e.with_context(format!("opens[{}]", i)))?;
+    }
+
+    write_classes(buffer, module.uses.iter().map(|class| class.name.as_str()), module.uses.len(), constant_pool).map_err(|e| e.with_context("uses"))?;
+
+    write_length_u2(buffer, module.provides.len())?;
+    for (i, provides) in module.provides.iter().enumerate() {
+        let context = |e: WritingError| e.with_context(format!("provides[{}]", i));
+        write_u2(buffer, class_index(constant_pool, &provides.service.name).map_err(context)?);
+        write_classes(buffer, provides.with.iter().map(|class| class.name.as_str()), provides.with.len(), constant_pool).map_err(context)?;
+    }
+    Ok(())
+}
+
+/// Writes an `exports` or `opens` entry, which share the same layout
+fn write_exports(buffer: &mut Vec<u8>, package: &str, flags: &[ExportsFlag], to: &[String], constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, package_index(constant_pool, package)?);
+    write_u2(buffer, exports_flags_mask(flags));
+    write_length_u2(buffer, to.len())?;
+    for module in to {
+        write_u2(buffer, module_index(constant_pool, module)?);
+    }
+    Ok(())
+}
+
+pub(crate) fn write_stack_map_table(buffer: &mut Vec<u8>, entries: &[StackMapFrame], constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_length_u2(buffer, entries.len())?;
+    let mut previous_pc: Option<u16> = None;
+    for (i, frame) in entries.iter().enumerate() {
+        write_stack_map_frame(buffer, frame, constant_pool, previous_pc).map_err(|e| e.with_context(format!("entries[{}]", i)))?;
+        previous_pc = Some(frame.pc());
+    }
+    Ok(())
+}
+
+fn write_stack_map_frame(buffer: &mut Vec<u8>, frame: &StackMapFrame, constant_pool: &mut ConstantPoolBuilder, previous_pc: Option<u16>) -> Result<(), WritingError> {
+    let pc = frame.pc();
+    let invalid_frame = || WritingError::new(WritingErrorKind::InvalidStackMapFrame { pc });
+    // The inverse of the offset delta computation in the reader
+    let offset_delta = match previous_pc {
+        None => Some(pc),
+        Some(previous_pc) => pc.checked_sub(previous_pc).and_then(|delta| delta.checked_sub(1)),
+    }.ok_or_else(invalid_frame)?;
+
+    match frame {
+        StackMapFrame::Same { .. } => {
+            if offset_delta > 63 {
+                return Err(invalid_frame());
+            }
+            write_u1(buffer, offset_delta as u8);
+        }
+        StackMapFrame::SameLocals1StackItem { stack, .. } => {
+            if offset_delta > 63 {
+                return Err(invalid_frame());
+            }
+            write_u1(buffer, 64 + offset_delta as u8);
+            write_verification_type(buffer, stack, constant_pool)?;
+        }
+        StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+            write_u1(buffer, 247);
+            write_u2(buffer, offset_delta);
+            write_verification_type(buffer, stack, constant_pool)?;
+        }
+        StackMapFrame::Chop { chopped, .. } => {
+            if !(1..=3).contains(chopped) {
+                return Err(invalid_frame());
+            }
+            write_u1(buffer, 251 - chopped);
+            write_u2(buffer, offset_delta);
+        }
+        StackMapFrame::SameExtended { .. } => {
+            write_u1(buffer, 251);
+            write_u2(buffer, offset_delta);
+        }
+        StackMapFrame::Append { locals, .. } => {
+            if !(1..=3).contains(&locals.len()) {
+                return Err(invalid_frame());
+            }
+            write_u1(buffer, 251 + locals.len() as u8);
+            write_u2(buffer, offset_delta);
+            write_verification_types(buffer, locals, constant_pool)?;
+        }
+        StackMapFrame::Full { locals, stack, .. } => {
+            write_u1(buffer, 255);
+            write_u2(buffer, offset_delta);
+            write_length_u2(buffer, locals.len())?;
+            write_verification_types(buffer, locals, constant_pool)?;
+            write_length_u2(buffer, stack.len())?;
+            write_verification_types(buffer, stack, constant_pool)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_verification_types(buffer: &mut Vec<u8>, verification_types: &[VerificationType], constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    for verification_type in verification_types {
+        write_verification_type(buffer, verification_type, constant_pool)?;
+    }
+    Ok(())
+}
+
+fn write_verification_type(buffer: &mut Vec<u8>, verification_type: &VerificationType, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    match verification_type {
+        VerificationType::Top => write_u1(buffer, 0),
+        VerificationType::Integer => write_u1(buffer, 1),
+        VerificationType::Float => write_u1(buffer, 2),
+        VerificationType::Double => write_u1(buffer, 3),
+        VerificationType::Long => write_u1(buffer, 4),
+        VerificationType::Null => write_u1(buffer, 5),
+        VerificationType::UninitializedThis => write_u1(buffer, 6),
+        VerificationType::Object(class) => {
+            write_u1(buffer, 7);
+            write_u2(buffer, class_index(constant_pool, &class.name)?);
+        }
+        VerificationType::Uninitialized { offset } => {
+            write_u1(buffer, 8);
+            write_u2(buffer, *offset);
+        }
+    }
+    Ok(())
+}
+
+fn write_annotations(buffer: &mut Vec<u8>, annotations: &[Annotation], constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_length_u2(buffer, annotations.len())?;
+    for (i, annotation) in annotations.iter().enumerate() {
+        write_annotation(buffer, annotation, constant_pool).map_err(|e| e.with_context(format!("annotations[{}]", i)))?;
+    }
+    Ok(())
+}
+
+fn write_annotation(buffer: &mut Vec<u8>, annotation: &Annotation, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u2(buffer, utf8_index(constant_pool, &annotation.type_name)?);
+    write_length_u2(buffer, annotation.element_value_pairs.len())?;
+    for (i, pair) in annotation.element_value_pairs.iter().enumerate() {
+        write_u2(buffer, utf8_index(constant_pool, &pair.0).map_err(|e| e.with_context(format!("element_value_pairs[{}]", i)))?);
+        write_element_value(buffer, &pair.1, constant_pool).map_err(|e| e.with_context(pair.0.as_str()))?;
+    }
+    Ok(())
+}
+
+fn write_element_value(buffer: &mut Vec<u8>, element_value: &ElementValue, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    match element_value {
+        ElementValue::ConstValue { tag, index, value } => {
+            write_u1(buffer, *tag as u8);
+            write_u2(buffer, constant_value(constant_pool, *index, value, ConstantPoolBuilder::utf8)?);
+        }
+        ElementValue::EnumConstValue { type_name, const_name } => {
+            write_u1(buffer, b'e');
+            write_u2(buffer, utf8_index(constant_pool, type_name)?);
+            write_u2(buffer, utf8_index(constant_pool, const_name)?);
+        }
+        ElementValue::ClassInfo { descriptor } => {
+            write_u1(buffer, b'c');
+            write_u2(buffer, utf8_index(constant_pool, descriptor)?);
+        }
+        ElementValue::AnnotationValue { annotation } => {
+            write_u1(buffer, b'@');
+            write_annotation(buffer, annotation, constant_pool)?;
+        }
+        ElementValue::ArrayValue { elements } => {
+            write_u1(buffer, b'[');
+            write_length_u2(buffer, elements.len())?;
+            for (i, element) in elements.iter().enumerate() {
+                write_element_value(buffer, element, constant_pool).map_err(|e| e.with_context(format!("elements[{}]", i)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn write_type_annotation(buffer: &mut Vec<u8>, type_annotation: &TypeAnnotation, constant_pool: &mut ConstantPoolBuilder) -> Result<(), WritingError> {
+    write_u1(buffer, type_annotation.target_type as u8);
+    match &type_annotation.target_info {
+        TargetInfo::TypeParameter { type_parameter_index } => write_u1(buffer, *type_parameter_index),
+        TargetInfo::Supertype { supertype_index } => write_u2(buffer, *supertype_index),
+        TargetInfo::TypeParameterBound { type_parameter_index, bound_index } => {
+            write_u1(buffer, *type_parameter_index);
+            write_u1(buffer, *bound_index);
+        }
+        TargetInfo::Empty => {}
+        TargetInfo::FormalParameter { formal_parameter_index } => write_u1(buffer, *formal_parameter_index),
+        TargetInfo::Throws { throws_type_index } => write_u2(buffer, *throws_type_index),
+        TargetInfo::LocalVariable { table } => {
+            write_length_u2(buffer, table.len())?;
+            for target in table {
+                write_u2(buffer, target.start_pc);
+                write_u2(buffer, target.length);
+                write_u2(buffer, target.index);
+            }
+        }
+        TargetInfo::Catch { exception_table_index } => write_u2(buffer, *exception_table_index),
+        TargetInfo::Offset { offset } => write_u2(buffer, *offset),
+        TargetInfo::TypeArgument { offset, type_argument_index } => {
+            write_u2(buffer, *offset);
+            write_u1(buffer, *type_argument_index);
+        }
+    }
+
+    write_length_u1(buffer, type_annotation.type_path.len())?;
+    for entry in &type_annotation.type_path {
+        write_u1(buffer, entry.kind as u8);
+        write_u1(buffer, entry.type_argument_index);
+    }
+    write_annotation(buffer, &type_annotation.annotation, constant_pool)
+}
+
+fn access_flags_mask(flags: &[AccessFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        AccessFlag::AccPublic => 0x0001,
+        AccessFlag::AccFinal => 0x0010,
+        AccessFlag::AccSuper => 0x0020,
+        AccessFlag::AccInterface => 0x0200,
+        AccessFlag::AccAbstract => 0x0400,
+        AccessFlag::AccSynthetic => 0x1000,
+        AccessFlag::AccAnnotation => 0x2000,
+        AccessFlag::AccEnum => 0x4000,
+        AccessFlag::AccModule => 0x8000,
+        AccessFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn field_flags_mask(flags: &[FieldFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        FieldFlag::AccPublic => 0x0001,
+        FieldFlag::AccPrivate => 0x0002,
+        FieldFlag::AccProtected => 0x0004,
+        FieldFlag::AccStatic => 0x0008,
+        FieldFlag::AccFinal => 0x0010,
+        FieldFlag::AccVolatile => 0x0040,
+        FieldFlag::AccTransient => 0x0080,
+        FieldFlag::AccSynthetic => 0x1000,
+        FieldFlag::AccEnum => 0x4000,
+        FieldFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn method_flags_mask(flags: &[MethodFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        MethodFlag::AccPublic => 0x0001,
+        MethodFlag::AccPrivate => 0x0002,
+        MethodFlag::AccProtected => 0x0004,
+        MethodFlag::AccStatic => 0x0008,
+        MethodFlag::AccFinal => 0x0010,
+        MethodFlag::AccSynchronized => 0x0020,
+        MethodFlag::AccBridge => 0x0040,
+        MethodFlag::AccVarargs => 0x0080,
+        MethodFlag::AccNative => 0x0100,
+        MethodFlag::AccAbstract => 0x0400,
+        MethodFlag::AccStrict => 0x0800,
+        MethodFlag::AccSynthetic => 0x1000,
+        MethodFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn inner_class_flags_mask(flags: &[InnerClassFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        InnerClassFlag::AccPublic => 0x0001,
+        InnerClassFlag::AccPrivate => 0x0002,
+        InnerClassFlag::AccProtected => 0x0004,
+        InnerClassFlag::AccStatic => 0x0008,
+        InnerClassFlag::AccFinal => 0x0010,
+        InnerClassFlag::AccInterface => 0x0200,
+        InnerClassFlag::AccAbstract => 0x0400,
+        InnerClassFlag::AccSynthetic => 0x1000,
+        InnerClassFlag::AccAnnotation => 0x2000,
+        InnerClassFlag::AccEnum => 0x4000,
+        InnerClassFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn method_parameter_flags_mask(flags: &[MethodParameterFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        MethodParameterFlag::AccFinal => 0x0010,
+        MethodParameterFlag::AccSynthetic => 0x1000,
+        MethodParameterFlag::AccMandated => 0x8000,
+        MethodParameterFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn module_flags_mask(flags: &[ModuleFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        ModuleFlag::AccOpen => 0x0020,
+        ModuleFlag::AccSynthetic => 0x1000,
+        ModuleFlag::AccMandated => 0x8000,
+        ModuleFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn requires_flags_mask(flags: &[RequiresFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        RequiresFlag::AccTransitive => 0x0020,
+        RequiresFlag::AccStaticPhase => 0x0040,
+        RequiresFlag::AccSynthetic => 0x1000,
+        RequiresFlag::AccMandated => 0x8000,
+        RequiresFlag::Reserved(bits) => *bits,
+    })
+}
+
+fn exports_flags_mask(flags: &[ExportsFlag]) -> u16 {
+    flags.iter().fold(0, |mask, flag| mask | match flag {
+        ExportsFlag::AccSynthetic => 0x1000,
+        ExportsFlag::AccMandated => 0x8000,
+        ExportsFlag::Reserved(bits) => *bits,
+    })
+}
+
+pub(crate) fn write_u1(buffer: &mut Vec<u8>, value: u8) {
+    buffer.push(value);
+}
+
+pub(crate) fn write_u2(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_u4(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_u8(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn too_large(length: usize, max: usize) -> WritingError {
+    WritingError::new(WritingErrorKind::TooLarge { length, max })
+}
+
+fn write_length_u1(buffer: &mut Vec<u8>, length: usize) -> Result<(), WritingError> {
+    let length = u8::try_from(length).map_err(|_| too_large(length, u8::MAX as usize))?;
+    write_u1(buffer, length);
+    Ok(())
+}
+
+fn write_length_u2(buffer: &mut Vec<u8>, length: usize) -> Result<(), WritingError> {
+    let length = u16::try_from(length).map_err(|_| too_large(length, u16::MAX as usize))?;
+    write_u2(buffer, length);
+    Ok(())
+}
+
+fn write_length_u4(buffer: &mut Vec<u8>, length: usize) -> Result<(), WritingError> {
+    let length = u32::try_from(length).map_err(|_| too_large(length, u32::MAX as usize))?;
+    write_u4(buffer, length);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_class_file;
+    use crate::types::{Class, ElementValuePair};
+
+    const SIGNATURE: &str = "fields[x:I]/attributes[Signature]";
+
+    fn class_with_field(constant_pool: ConstantPool, attributes: Vec<Attribute>) -> ClassFile {
+        let mut class_file = ClassFile::new(61, Class { name: "A".to_string() }, Some(Class { name: "java/lang/Object".to_string() }));
+        class_file.constant_pool = constant_pool;
+        class_file.access_flags = vec![AccessFlag::AccPublic];
+        class_file.fields = vec![Field { access_flags: vec![FieldFlag::AccStatic, FieldFlag::AccFinal], name: "x".to_string(), descriptor: "I".to_string(), attributes }];
+        class_file
+    }
+
+    /// Moves every reference of `class_file` to a copy of the entry it referred to, appended to the pool
+    fn refer_to_copies(class_file: &mut ClassFile) -> u16 {
+        let length = class_file.constant_pool.len() as u16;
+        class_file.constant_pool.extend_from_within(..);
+        for index in class_file.constant_pool_references.indices.values_mut().flatten() {
+            *index += length;
+        }
+        length
+    }
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/tests/fixtures/classes/{}.class", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    fn rewrite(class_file: &ClassFile) -> ClassFile {
+        read_class_file(&write_class_file(class_file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn constant_values_are_added_by_value() {
+        let annotation = Annotation {
+            type_name: "LA;".to_string(),
+            element_value_pairs: vec![
+                ElementValuePair("s".to_string(), ElementValue::ConstValue { tag: 's', index: 0, value: ConstantValue::String("hi".to_string()) }),
+                ElementValuePair("j".to_string(), ElementValue::ConstValue { tag: 'J', index: 7, value: ConstantValue::Long(5) }),
+            ],
+        };
+        let class_file = class_with_field(Vec::new(), vec![
+            Attribute::ConstantValue { index: 0, value: ConstantValue::String("hi".to_string()) },
+            Attribute::RuntimeVisibleAnnotations { annotations: vec![annotation] },
+        ]);
+        let class_file = rewrite(&class_file);
+        let attributes = &class_file.fields[0].attributes;
+
+        match &attributes[0] {
+            Attribute::ConstantValue { index, value } => {
+                assert_eq!(value, &ConstantValue::String("hi".to_string()));
+                assert!(matches!(class_file.constant_pool[*index as usize - 1], ConstantPoolEntry::StringInfo { .. }));
+            }
+            attribute => panic!("{:?}", attribute),
+        }
+        let pairs = match &attributes[1] {
+            Attribute::RuntimeVisibleAnnotations { annotations } => &annotations[0].element_value_pairs,
+            attribute => panic!("{:?}", attribute),
+        };
+        match &pairs[0].1 {
+            ElementValue::ConstValue { index, value, .. } => {
+                assert_eq!(value, &ConstantValue::String("hi".to_string()));
+                assert!(matches!(class_file.constant_pool[*index as usize - 1], ConstantPoolEntry::Utf8Info { .. }));
+            }
+            element_value => panic!("{:?}", element_value),
+        }
+        match &pairs[1].1 {
+            ElementValue::ConstValue { value, .. } => assert_eq!(value, &ConstantValue::Long(5)),
+            element_value => panic!("{:?}", element_value),
+        }
+    }
+
+    #[test]
+    fn constant_value_index_is_kept_while_it_holds_the_value() {
+        let constant_pool = vec![
+            ConstantPoolEntry::IntegerInfo { value: 1 },
+            ConstantPoolEntry::IntegerInfo { value: 1 },
+            ConstantPoolEntry::FloatInfo { value: f32::NAN },
+            ConstantPoolEntry::FloatInfo { value: f32::NAN },
+        ];
+        let constant_value_index = |value: ConstantValue, index: u16| {
+            let class_file = rewrite(&class_with_field(constant_pool.clone(), vec![Attribute::ConstantValue { index, value }]));
+            match class_file.fields[0].attributes[0] {
+                Attribute::ConstantValue { index, .. } => (index, class_file.constant_pool[index as usize - 1].clone()),
+                ref attribute => panic!("{:?}", attribute),
+            }
+        };
+        // The second of two equal entries stays in use
+        assert_eq!(constant_value_index(ConstantValue::Integer(1), 2).0, 2);
+        assert_eq!(constant_value_index(ConstantValue::Float(f32::NAN), 4).0, 4);
+        // A changed value refers to an entry that holds it
+        assert_eq!(constant_value_index(ConstantValue::Integer(1), 3).0, 1);
+        let (index, entry) = constant_value_index(ConstantValue::Integer(2), 1);
+        assert!(index > 4);
+        assert_eq!(entry, ConstantPoolEntry::IntegerInfo { value: 2 });
+    }
+
+    #[test]
+    fn duplicate_entries_stay_in_use() {
+        let class_file = class_with_field(Vec::new(), vec![Attribute::Signature { signature: "TT;".to_string() }]);
+        let mut class_file = rewrite(&class_file);
+        // this_class and super_class, the name and descriptor of the field, and the name and value of its attribute
+        let references = &class_file.constant_pool_references.indices;
+        assert_eq!(references.keys().collect::<Vec<_>>(), ["", "fields[x:I]", SIGNATURE]);
+        assert_eq!(references.values().map(Vec::len).collect::<Vec<_>>(), [2, 2, 2]);
+
+        let length = refer_to_copies(&mut class_file);
+        let bytes = write_class_file(&class_file).unwrap();
+        let written = read_class_file(&bytes).unwrap();
+        assert_eq!(written.constant_pool, class_file.constant_pool);
+        assert_eq!(written.constant_pool_references, class_file.constant_pool_references);
+        assert_eq!(write_class_file(&written).unwrap(), bytes);
+
+        // A reference to an entry that no longer holds the value falls back to the first equal entry
+        let signature_index = class_file.constant_pool_references.indices[SIGNATURE][1];
+        class_file.constant_pool[signature_index as usize - 1] = ConstantPoolEntry::Utf8Info { value: "TU;".to_string(), raw: None };
+        let written = rewrite(&class_file);
+        assert_eq!(written.fields[0].attributes[0], Attribute::Signature { signature: "TT;".to_string() });
+        assert_eq!(written.constant_pool_references.indices[SIGNATURE], [signature_index - 1, signature_index - length]);
+        assert_eq!(written.constant_pool_references.indices[""], class_file.constant_pool_references.indices[""]);
+    }
+
+    #[test]
+    fn duplicate_entries_of_parsed_classes_stay_in_use() {
+        let mut names: Vec<String> = vec!["module-info".to_string()];
+        for entry in std::fs::read_dir(format!("{}/tests/fixtures/classes/fixtures", env!("CARGO_MANIFEST_DIR"))).unwrap() {
+            let file_name = entry.unwrap().file_name().into_string().unwrap();
+            names.push(format!("fixtures/{}", file_name.trim_end_matches(".class")));
+        }
+        for name in &names {
+            let mut class_file = read_class_file(&fixture(name)).unwrap();
+            let length = refer_to_copies(&mut class_file);
+            let bytes = write_class_file(&class_file).unwrap();
+            let written = read_class_file(&bytes).unwrap();
+            assert_eq!(written.constant_pool.len(), 2 * length as usize, "{}", name);
+            assert_eq!(written.constant_pool_references, class_file.constant_pool_references, "{}", name);
+            assert_eq!(write_class_file(&written).unwrap(), bytes, "{}", name);
+        }
+    }
+
+    #[test]
+    fn changed_parts_leave_the_references_of_the_others_intact() {
+        let mut class_file = read_class_file(&fixture("fixtures/ControlFlow")).unwrap();
+        refer_to_copies(&mut class_file);
+        let references = class_file.constant_pool_references.indices.clone();
+
+        class_file.interfaces.insert(0, Class { name: "java/io/Serializable".to_string() });
+        class_file.fields.insert(0, Field { access_flags: Vec::new(), name: "added".to_string(), descriptor: "J".to_string(), attributes: Vec::new() });
+        let removed = class_file.methods.remove(0);
+        for method in &mut class_file.methods {
+            method.attributes.insert(0, Attribute::Deprecated);
+        }
+        class_file.attributes.insert(0, Attribute::Synthetic);
+
+        let written = rewrite(&class_file).constant_pool_references.indices;
+        let removed = format!("methods[{}:{}]", removed.name, removed.descriptor);
+        for (path, indices) in &references {
+            if *path == removed || path.starts_with(&format!("{}/", removed)) {
+                assert!(!written.contains_key(path), "{}", path);
+            } else {
+                assert_eq!(written.get(path), Some(indices), "{}", path);
+            }
+        }
+        assert!(written.contains_key("interfaces[java/io/Serializable]"));
+        assert!(written.contains_key("fields[added:J]"));
+        assert!(written.contains_key("attributes[Synthetic]"));
+    }
+
+    #[test]
+    fn raw_utf8_entries_are_written_back() {
+        let raw: Vec<u8> = vec![b'T', 0xED, 0xA0, 0x80, b';'];
+        let lossy = mutf8::decode_lossy(&raw);
+        let mut class_file = rewrite(&class_with_field(Vec::new(), vec![Attribute::Signature { signature: lossy.clone() }]));
+        let signature_index = class_file.constant_pool_references.indices[SIGNATURE][1];
+        class_file.constant_pool[signature_index as usize - 1] = ConstantPoolEntry::Utf8Info { value: lossy.clone(), raw: Some(raw.clone()) };
+
+        let bytes = write_class_file(&class_file).unwrap();
+        assert!(bytes.windows(raw.len()).any(|window| window == raw));
+        let written = read_class_file(&bytes).unwrap();
+        assert_eq!(written.constant_pool, class_file.constant_pool);
+        assert_eq!(written.fields[0].attributes[0], Attribute::Signature { signature: lossy });
+        assert_eq!(write_class_file(&written).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,86 @@
+//! Compares the `max_stack` and `max_locals` computed for the classes in `tests/fixtures/classes`
+//! with the ones javac declared
+
+mod common;
+
+use bytecode_parser::analysis::{check_code_limits, method_code_limits, CodeLimits};
+use bytecode_parser::read_class_file;
+use bytecode_parser::types::{Attribute, ParsingErrorKind};
+
+use common::fixtures;
+
+#[test]
+fn computed_limits_agree_with_javac() {
+    let mut methods = 0;
+    for (path, data) in fixtures() {
+        let class_file = read_class_file(&data).unwrap();
+        for method in &class_file.methods {
+            let declared = method.attributes.iter().find_map(|attribute| match attribute {
+                Attribute::Code { max_stack, max_locals, .. } => Some(CodeLimits { max_stack: *max_stack, max_locals: *max_locals }),
+                _ => None,
+            });
+            let computed = method_code_limits(method, &class_file.constant_pool).unwrap();
+            assert_eq!(computed, declared, "{} {}{}", path.display(), method.name, method.descriptor);
+            methods += computed.is_some() as usize;
+        }
+        assert_eq!(check_code_limits(&class_file), [], "{}", path.display());
+    }
+    assert!(methods >= 50);
+}
+
+#[test]
+fn mismatches_are_reported_in_both_directions() {
+    for (path, data) in fixtures() {
+        let mut class_file = read_class_file(&data).unwrap();
+        let mut expected: Vec<(usize, bool)> = Vec::new();
+        for (i, method) in class_file.methods.iter_mut().enumerate() {
+            for attribute in &mut method.attributes {
+                if let Attribute::Code { max_stack, max_locals, .. } = attribute {
+                    if i % 2 == 0 && *max_stack > 0 {
+                        *max_stack -= 1;
+                        expected.push((i, true));
+                    } else {
+                        *max_locals += 1;
+                        expected.push((i, false));
+                    }
+                }
+            }
+        }
+        let reported: Vec<(usize, bool)> = check_code_limits(&class_file).into_iter()
+            .map(|mismatch| mismatch.map(|mismatch| (mismatch.method_index, mismatch.is_too_small())))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(reported, expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn methods_that_cant_be_analyzed_are_reported_on_their_own() {
+    let (_, data) = fixtures().into_iter().find(|(path, _)| path.ends_with("fixtures/ControlFlow.class")).unwrap();
+    let mut class_file = read_class_file(&data).unwrap();
+    let mut code_methods = (0..class_file.methods.len()).filter(|i| {
+        class_file.methods[*i].attributes.iter().any(|attribute| matches!(attribute, Attribute::Code { .. }))
+    });
+    let broken = code_methods.next().unwrap();
+    let too_small = code_methods.next().unwrap();
+    for attribute in &mut class_file.methods[broken].attributes {
+        if let Attribute::Code { code, .. } = attribute {
+            // pop on an empty stack
+            code.insert(0, 0x57);
+        }
+    }
+    for attribute in &mut class_file.methods[too_small].attributes {
+        if let Attribute::Code { max_locals, .. } = attribute {
+            *max_locals = 0;
+        }
+    }
+
+    let mismatches = check_code_limits(&class_file);
+    assert_eq!(mismatches.len(), 2);
+    let error = mismatches[0].as_ref().unwrap_err();
+    assert_eq!(error.kind, ParsingErrorKind::StackUnderflow);
+    assert_eq!(error.context[0], format!("methods[{}]", broken));
+    let mismatch = mismatches[1].as_ref().unwrap();
+    assert_eq!(mismatch.method_index, too_small);
+    assert!(mismatch.is_too_small());
+}
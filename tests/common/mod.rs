@@ -0,0 +1,26 @@
+//! Loading the javac output in `tests/fixtures/classes`, shared by the integration tests
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn find_classes(directory: &Path, classes: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_classes(&path, classes);
+        } else if path.extension().is_some_and(|extension| extension == "class") {
+            classes.push(path);
+        }
+    }
+}
+
+pub fn fixtures() -> Vec<(PathBuf, Vec<u8>)> {
+    let mut classes: Vec<PathBuf> = Vec::new();
+    find_classes(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/classes"), &mut classes);
+    classes.sort();
+    assert!(!classes.is_empty());
+    classes.into_iter().map(|path| {
+        let data = fs::read(&path).unwrap();
+        (path, data)
+    }).collect()
+}
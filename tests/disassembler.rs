@@ -0,0 +1,26 @@
+//! Compares the disassembly of the fixture classes with the expected output in
+//! `tests/fixtures/disassembly`. The pcs, mnemonics and exception tables in there were checked against
+//! `javap -c -p` for the same class.
+
+use std::fs;
+use std::path::Path;
+
+use bytecode_parser::disassembler::disassemble;
+use bytecode_parser::read_class_file;
+
+#[test]
+fn control_flow_matches_the_expected_output() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let class_file = read_class_file(&fs::read(fixtures.join("classes/fixtures/ControlFlow.class")).unwrap()).unwrap();
+    let expected = fs::read_to_string(fixtures.join("disassembly/ControlFlow.txt")).unwrap();
+    let actual = disassemble(&class_file).unwrap();
+
+    // The expected output covers these parts of the layout
+    for part in ["stack=3, locals=1", "// line 14", "tableswitch   { // 0 to 4", "lookupswitch  { // 3", "Exception table:", "       from    to  target type"] {
+        assert!(expected.contains(part), "{}", part);
+    }
+    for (i, (actual, expected)) in actual.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(actual, expected, "line {}", i + 1);
+    }
+    assert_eq!(actual.lines().count(), expected.lines().count());
+}
@@ -0,0 +1,63 @@
+package fixtures;
+
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.List;
+import java.util.Map;
+
+@Annotations.Values(name = "class", count = 3, classes = { String.class, int[].class, void.class })
+public class Annotations<@Annotations.Use T> {
+    @Retention(RetentionPolicy.RUNTIME)
+    public @interface Marker {}
+
+    public @interface Note {}
+
+    @Target(ElementType.TYPE_USE)
+    public @interface Hidden {}
+
+    @Retention(RetentionPolicy.RUNTIME)
+    @Target({ ElementType.TYPE_USE, ElementType.TYPE_PARAMETER })
+    public @interface Use {
+        String value() default "";
+    }
+
+    @Retention(RetentionPolicy.RUNTIME)
+    public @interface Values {
+        byte b() default 1;
+        char c() default 'c';
+        short s() default -2;
+        int count() default Integer.MAX_VALUE;
+        long l() default Long.MIN_VALUE;
+        float f() default Float.NaN;
+        double d() default -0.0;
+        boolean z() default true;
+        String name();
+        Class<?>[] classes() default {};
+        ElementType element() default ElementType.FIELD;
+        Marker marker() default @Marker;
+        int[] numbers() default { 1, 2, 3 };
+    }
+
+    @Deprecated
+    @Values(name = "field", element = ElementType.METHOD, numbers = {})
+    public static final String CONSTANT = "constant";
+
+    public static final double NOT_A_NUMBER = Double.NaN;
+    public static final float NEGATIVE_ZERO = -0.0f;
+    public static final long BIG = 1L << 40;
+    public static final char CHAR = '\u00e9';
+    public static final String UNICODE = "\u0000\ud83d\ude00";
+
+    private @Use("field") List<@Use("argument") Map<String, @Use T[]>> typed;
+
+    @Marker
+    public <@Use X extends @Use Exception> @Use String method(@Marker @Use("parameter") final int parameter, @Values(name = "other") @Note @Hidden String other) throws @Use X {
+        @Use Object local = (@Use @Hidden CharSequence) other;
+        if (local instanceof @Use String string) {
+            return string + parameter;
+        }
+        return null;
+    }
+}
@@ -0,0 +1,111 @@
+package fixtures;
+
+import java.io.IOException;
+import java.util.ArrayList;
+import java.util.List;
+import java.util.function.Function;
+import java.util.function.Supplier;
+
+public class ControlFlow {
+    private final Object lock = new Object();
+    private int counter;
+
+    public static int tableSwitch(int value) {
+        switch (value) {
+            case 0: return 10;
+            case 1: return 20;
+            case 2: return 30;
+            case 4: return 50;
+            default: return -1;
+        }
+    }
+
+    public static String lookupSwitch(int value) {
+        switch (value) {
+            case -1000: return "small";
+            case 7: return "seven";
+            case 1 << 20: return "big";
+            default: return "other";
+        }
+    }
+
+    public static int stringSwitch(String value) {
+        return switch (value) {
+            case "a", "b" -> 1;
+            case "c" -> 2;
+            default -> value.length();
+        };
+    }
+
+    public static int enumSwitch(Shapes.Kind kind) {
+        switch (kind) {
+            case ROUND: return 1;
+            case SQUARE: return 4;
+            default: return 0;
+        }
+    }
+
+    public static double loops(long[] values, double scale) {
+        double sum = 0;
+        long last = 0;
+        for (int i = 0; i < values.length; i++) {
+            if (values[i] < 0) {
+                continue;
+            }
+            int j = 0;
+            while (j < i && values[j] != last) {
+                j++;
+            }
+            last = values[i] >>> j;
+            sum += last * scale;
+        }
+        return sum > 0 ? sum : -sum;
+    }
+
+    public String exceptions(String path) {
+        StringBuilder builder = new StringBuilder();
+        try {
+            if (path.isEmpty()) {
+                throw new IOException(path);
+            }
+            builder.append(path);
+        } catch (IOException | IllegalStateException e) {
+            builder.append(e.getMessage());
+        } catch (RuntimeException e) {
+            return null;
+        } finally {
+            builder.append('!');
+        }
+        synchronized (lock) {
+            counter++;
+        }
+        return builder.toString();
+    }
+
+    public List<Supplier<String>> lambdas(int count, String prefix) {
+        List<Supplier<String>> suppliers = new ArrayList<>();
+        Function<Integer, String> format = i -> prefix + i + counter;
+        for (int i = 0; i < count; i++) {
+            int captured = i;
+            suppliers.add(() -> format.apply(captured));
+        }
+        suppliers.add(prefix::trim);
+        suppliers.add(String::new);
+        return suppliers;
+    }
+
+    public Runnable anonymous() {
+        return new Runnable() {
+            @Override
+            public void run() {
+                counter += 2;
+            }
+        };
+    }
+
+    public static int[][] arrays(int size) {
+        int[][] grid = new int[size][size + 1];
+        Object[] objects = { grid, null, "x" };
+        return objects.length > size ? null : grid;
+    }
+}
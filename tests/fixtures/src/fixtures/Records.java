@@ -0,0 +1,22 @@
+package fixtures;
+
+import java.util.List;
+
+public record Records(int x, @Annotations.Marker long y, String... names) implements Comparable<Records> {
+    public Records {
+        if (x < 0) {
+            throw new IllegalArgumentException("x " + x);
+        }
+    }
+
+    public record Pair<A extends Comparable<A>, B>(A first, List<? super B> second) {
+        public static <T extends Comparable<T>> Pair<T, T> of(T value) {
+            return new Pair<>(value, List.of(value));
+        }
+    }
+
+    @Override
+    public int compareTo(Records other) {
+        return Long.compare(y + x, other.y + other.x);
+    }
+}
@@ -0,0 +1,38 @@
+package fixtures;
+
+public sealed interface Shapes permits Shapes.Circle, Shapes.Square {
+    enum Kind { ROUND, SQUARE }
+
+    double area();
+
+    default Kind kind() {
+        return this instanceof Circle ? Kind.ROUND : Kind.SQUARE;
+    }
+
+    record Circle(double radius) implements Shapes {
+        public double area() {
+            return Math.PI * radius * radius;
+        }
+    }
+
+    final class Square implements Shapes {
+        private final long side;
+
+        public Square(long side) {
+            this.side = side;
+        }
+
+        public double area() {
+            return side * side;
+        }
+    }
+
+    class Task implements Runnable {
+        public void run() {
+            class Local {
+                int value = new Square(2).kind().ordinal();
+            }
+            new Local();
+        }
+    }
+}
@@ -0,0 +1,9 @@
+module fixtures {
+    requires java.logging;
+    requires transitive java.sql;
+    requires static java.desktop;
+    exports fixtures;
+    opens fixtures to java.base;
+    uses java.lang.Runnable;
+    provides java.lang.Runnable with fixtures.Shapes.Task;
+}
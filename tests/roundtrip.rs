@@ -0,0 +1,100 @@
+//! Parses the classes in `tests/fixtures/classes` and writes them back. They are the javac 17 output
+//! for the sources in `tests/fixtures/src`, compiled with
+//! `javac -g -parameters -d tests/fixtures/classes tests/fixtures/src/module-info.java tests/fixtures/src/fixtures/*.java`
+
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use bytecode_parser::types::{Attribute, Class, ConstantValue, Field};
+use bytecode_parser::{read_class_file, write_class_file, ClassFile};
+
+use common::fixtures;
+
+fn collect_attribute_names(attributes: &[Attribute], names: &mut HashSet<String>) {
+    for attribute in attributes {
+        names.insert(attribute.name().to_string());
+        match attribute {
+            Attribute::Code { attributes, .. } => collect_attribute_names(attributes, names),
+            Attribute::Record { components } => {
+                for component in components {
+                    collect_attribute_names(&component.attributes, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn fixtures_cover_attributes() {
+    let mut names: HashSet<String> = HashSet::new();
+    for (_, data) in fixtures() {
+        let class_file = read_class_file(&data).unwrap();
+        collect_attribute_names(&class_file.attributes, &mut names);
+        for field in &class_file.fields {
+            collect_attribute_names(&field.attributes, &mut names);
+        }
+        for method in &class_file.methods {
+            collect_attribute_names(&method.attributes, &mut names);
+        }
+    }
+    for name in [
+        "AnnotationDefault", "BootstrapMethods", "ConstantValue", "EnclosingMethod", "Exceptions", "InnerClasses",
+        "LineNumberTable", "LocalVariableTable", "LocalVariableTypeTable", "MethodParameters", "Module", "NestHost",
+        "NestMembers", "PermittedSubclasses", "Record", "RuntimeInvisibleParameterAnnotations",
+        "RuntimeInvisibleTypeAnnotations", "RuntimeVisibleAnnotations", "RuntimeVisibleParameterAnnotations",
+        "RuntimeVisibleTypeAnnotations", "Signature", "SourceFile", "StackMapTable",
+    ] {
+        assert!(names.contains(name), "no fixture has a {} attribute", name);
+    }
+}
+
+#[test]
+fn unmodified_classes_are_written_back_byte_for_byte() {
+    for (path, data) in fixtures() {
+        let class_file = read_class_file(&data).unwrap();
+        assert_eq!(class_file.parsed_bytes, data.len(), "{}", path.display());
+        assert!(write_class_file(&class_file).unwrap() == data, "{}", path.display());
+    }
+}
+
+fn constant_value<'a>(class_file: &'a mut ClassFile, field: &str) -> &'a mut ConstantValue {
+    let field = class_file.fields.iter_mut().find(|candidate| candidate.name == field).unwrap();
+    field.attributes.iter_mut().find_map(|attribute| match attribute {
+        Attribute::ConstantValue { value, .. } => Some(value),
+        _ => None,
+    }).unwrap()
+}
+
+fn without_constant_indices(class_file: &ClassFile) -> Vec<Field> {
+    let mut fields = class_file.fields.clone();
+    for attribute in fields.iter_mut().flat_map(|field| field.attributes.iter_mut()) {
+        if let Attribute::ConstantValue { index, .. } = attribute {
+            *index = 0;
+        }
+    }
+    fields
+}
+
+#[test]
+fn modified_classes_keep_the_rest_of_their_contents() {
+    let data = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/classes/fixtures/Annotations.class")).unwrap();
+    let mut class_file = read_class_file(&data).unwrap();
+    *constant_value(&mut class_file, "CONSTANT") = ConstantValue::String("changed".to_string());
+    *constant_value(&mut class_file, "BIG") = ConstantValue::Long(-1);
+    class_file.interfaces.push(Class { name: "java/io/Serializable".to_string() });
+
+    let mut written = read_class_file(&write_class_file(&class_file).unwrap()).unwrap();
+    assert!(written.constant_pool.len() > class_file.constant_pool.len());
+    assert_eq!(*constant_value(&mut written, "CONSTANT"), ConstantValue::String("changed".to_string()));
+    assert_eq!(*constant_value(&mut written, "BIG"), ConstantValue::Long(-1));
+    assert_eq!(written.interfaces, class_file.interfaces);
+    // The changed constants are stored at new indices. Fields are compared by their debug output,
+    // since one of them holds NaN.
+    assert_eq!(format!("{:?}", without_constant_indices(&written)), format!("{:?}", without_constant_indices(&class_file)));
+    assert_eq!(written.methods, class_file.methods);
+    assert_eq!(written.attributes, class_file.attributes);
+}